@@ -0,0 +1,72 @@
+use std::hint::black_box;
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use proto::ack::{read_acknowledgement, write_acknowledgement};
+use proto::packet::{split_packet, Packet as Frame, Reliability};
+use proto::types::U24;
+
+const MTU: u16 = 1400;
+
+fn split(c: &mut Criterion) {
+    let mut group = c.benchmark_group("split_packet");
+    for size in [100, 1400, 10_000, 100_000] {
+        let data = Bytes::from(vec![0xfe; size]);
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| split_packet(black_box(data), MTU))
+        });
+    }
+    group.finish();
+}
+
+fn frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame");
+    for size in [16, 512, 1300] {
+        let frame = Frame {
+            reliability: Reliability::ReliableOrdered,
+            message_index: U24::new(1000),
+            order_index: U24::new(500),
+            data: vec![0xfe; size].into(),
+            ..Default::default()
+        };
+        let mut encoded = Vec::new();
+        frame.write(&mut encoded);
+        group.throughput(Throughput::Bytes(encoded.len() as u64));
+        group.bench_with_input(BenchmarkId::new("write", size), &frame, |b, frame| {
+            let mut buf = Vec::with_capacity(MTU as usize);
+            b.iter(|| {
+                buf.clear();
+                black_box(frame).write(&mut buf);
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("read", size), &encoded, |b, encoded| {
+            b.iter(|| Frame::read(black_box(encoded)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn acknowledgements(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ack");
+    // contiguous sequence numbers compress to one range, every other one leaves only singles.
+    for (name, step) in [("contiguous", 1), ("gaps", 2)] {
+        let packets: Vec<U24> = (0..500).map(|i| U24::new(i * step)).collect();
+        let mut encoded = vec![0xc0];
+        write_acknowledgement(&mut encoded, &packets, MTU as usize);
+        group.throughput(Throughput::Elements(packets.len() as u64));
+        group.bench_with_input(BenchmarkId::new("write", name), &packets, |b, packets| {
+            let mut buf = Vec::with_capacity(MTU as usize);
+            b.iter(|| {
+                buf.clear();
+                write_acknowledgement(&mut buf, black_box(packets), MTU as usize)
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("read", name), &encoded, |b, encoded| {
+            b.iter(|| read_acknowledgement(black_box(&encoded[1..])).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, split, frames, acknowledgements);
+criterion_main!(benches);
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use proto::conn::Conn;
+use proto::listener::Listener;
+use tokio::runtime::Runtime;
+
+// connect starts a listener on the loopback interface and dials it.
+fn connect(rt: &Runtime) -> (Arc<Listener>, Arc<Conn>, Arc<Conn>) {
+    rt.block_on(async {
+        let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+        let (client, server) = tokio::join!(Conn::dial(listener.local_addr().unwrap()), listener.accept());
+        (listener, client.unwrap(), server.unwrap())
+    })
+}
+
+// BATCH is the amount of messages written before they are read, so that the messages waiting to
+// be read don't pile up for the largest sizes.
+const BATCH: u64 = 512;
+
+// transfer sends `n` messages of `size` bytes from the client and waits until the server read
+// them all, returning how long that took.
+fn transfer(rt: &Runtime, client: &Conn, server: &Conn, n: u64, size: usize) -> Duration {
+    let message = vec![0xfe; size];
+    rt.block_on(async {
+        let start = Instant::now();
+        let mut sent = 0;
+        while sent < n {
+            let batch = BATCH.min(n - sent);
+            for _ in 0..batch {
+                client.Write(&message).await.unwrap();
+            }
+            for _ in 0..batch {
+                server.ReadPacket().await.unwrap();
+            }
+            sent += batch;
+        }
+        start.elapsed()
+    })
+}
+
+fn loopback(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+    let (_listener, client, server) = connect(&rt);
+
+    let mut group = c.benchmark_group("loopback");
+    group.sample_size(20).measurement_time(Duration::from_secs(5));
+    for size in [16, 512, 1400, 16_384, 100_000] {
+        group.throughput(Throughput::Elements(1));
+        group.bench_with_input(BenchmarkId::new("messages", size), &size, |b, &size| {
+            b.iter_custom(|n| transfer(&rt, &client, &server, n, size))
+        });
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("bytes", size), &size, |b, &size| {
+            b.iter_custom(|n| transfer(&rt, &client, &server, n, size))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, loopback);
+criterion_main!(benches);
@@ -0,0 +1,101 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use proto::frame::Window;
+use proto::packet::{Packet as Frame, Reliability};
+use proto::session::{Event, Session};
+use proto::types::{PacketId, U24};
+
+const PACKETS: u32 = 1000;
+
+// reversed_pairs swaps every two indices, so that half of them arrive before the one they follow.
+fn reversed_pairs() -> impl Iterator<Item = u32> {
+    (0..PACKETS).map(|i| i ^ 1)
+}
+
+fn window(c: &mut Criterion) {
+    let mut group = c.benchmark_group("window");
+    group.throughput(Throughput::Elements(PACKETS as u64));
+    let now = Instant::now();
+    group.bench_function("add_in_order", |b| {
+        b.iter(|| {
+            let mut window = Window::new();
+            for i in 0..PACKETS {
+                window.add(U24::new(i), now).unwrap();
+                window.shift();
+            }
+            window
+        })
+    });
+    group.bench_function("add_reordered", |b| {
+        b.iter(|| {
+            let mut window = Window::new();
+            for i in reversed_pairs() {
+                window.add(U24::new(i), now).unwrap();
+                window.shift();
+            }
+            window
+        })
+    });
+    // every tenth datagram is lost and reported missing.
+    group.bench_function("missing", |b| {
+        b.iter(|| {
+            let mut window = Window::new();
+            for i in (0..PACKETS).filter(|i| i % 10 != 0) {
+                window.add(U24::new(i), now).unwrap();
+            }
+            black_box(window.missing(Duration::ZERO, now))
+        })
+    });
+    group.finish();
+}
+
+// datagram builds a datagram carrying the ordered message `i` of `size` bytes. The first one is a
+// NewIncomingConnection, which completes the handshake of the session the rest is delivered by.
+fn datagram(i: u32, size: usize) -> Vec<u8> {
+    let mut datagram = vec![0x84];
+    datagram.extend_from_slice(&U24::new(i).to_le_bytes());
+    let frame = Frame {
+        reliability: Reliability::ReliableOrdered,
+        message_index: U24::new(i),
+        order_index: U24::new(i),
+        data: if i == 0 { vec![PacketId::NewIncomingConnection as u8].into() } else { vec![0xfe; size].into() },
+        ..Default::default()
+    };
+    frame.write(&mut datagram);
+    datagram
+}
+
+// receive hands the datagrams to a new session in the order of `arrivals` and takes every message
+// it delivers.
+fn receive(datagrams: &[Vec<u8>], arrivals: impl Iterator<Item = u32>) -> usize {
+    let now = Instant::now();
+    let mut session = Session::server(now, "127.0.0.1:19132".parse().unwrap(), 1, 1492);
+    let mut messages = 0;
+    for i in arrivals {
+        session.handle_datagram(now, &datagrams[i as usize]).unwrap();
+        messages += std::iter::from_fn(|| session.poll_event()).filter(|event| matches!(event, Event::Message(_))).count();
+    }
+    assert_eq!(messages, PACKETS as usize - 1);
+    messages
+}
+
+fn ordered(c: &mut Criterion) {
+    let mut group = c.benchmark_group("ordered");
+    group.throughput(Throughput::Elements(PACKETS as u64));
+    for size in [16, 1024] {
+        let datagrams: Vec<Vec<u8>> = (0..PACKETS).map(|i| datagram(i, size)).collect();
+        // delivered as they arrive, half of them after the one they follow.
+        group.bench_with_input(BenchmarkId::new("reordered", size), &datagrams, |b, datagrams| {
+            b.iter(|| receive(datagrams, reversed_pairs()))
+        });
+        // everything waits behind a late first message, then comes out at once.
+        group.bench_with_input(BenchmarkId::new("backlog", size), &datagrams, |b, datagrams| {
+            b.iter(|| receive(datagrams, (1..PACKETS).chain([0])))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, window, ordered);
+criterion_main!(benches);
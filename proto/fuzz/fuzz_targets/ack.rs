@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proto::ack::{read_acknowledgement, MAX_ACKNOWLEDGEMENT_PACKETS};
+
+// the records of an ACK or NACK, after the datagram header.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packets) = read_acknowledgement(data) {
+        assert!(packets.len() <= MAX_ACKNOWLEDGEMENT_PACKETS);
+    }
+});
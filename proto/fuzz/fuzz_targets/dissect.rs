@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proto::dissect::dissect;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = dissect(data).to_json();
+});
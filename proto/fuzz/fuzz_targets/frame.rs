@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proto::packet::Packet;
+
+// the frames of a connected datagram, after its header and sequence number.
+fuzz_target!(|data: &[u8]| {
+    let mut offset = 0;
+    while offset < data.len() {
+        match Packet::read(&data[offset..]) {
+            Ok((packet, size)) => {
+                assert_eq!(size, packet.size());
+                offset += size;
+            }
+            Err(_) => break,
+        }
+    }
+});
@@ -0,0 +1,41 @@
+#![no_main]
+
+use std::sync::Arc;
+use std::time::Duration;
+use libfuzzer_sys::fuzz_target;
+use proto::clock::SystemClock;
+use proto::listener::Listener;
+use proto::messages::unconnected_ping::UnconnectedPing;
+use proto::transport::{DatagramTransport, MemoryNetwork};
+use proto::types::Packet;
+
+// The input is datagrams sent to a listener by one client, each preceded by its length as a
+// little endian u16, like the datagrams of receive_packet. Afterwards the listener must still
+// answer a ping: the datagrams are handled by its read loop, which would stop at a panic.
+// Every input gets a runtime of its own, so that the connections it opened don't pile up.
+fuzz_target!(|data: &[u8]| {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let network = MemoryNetwork::new();
+        let server = network.bind("127.0.0.1:19132".parse().unwrap()).unwrap();
+        let listener = Listener::listen_on(Arc::new(server), Arc::new(SystemClock));
+        let client: Arc<dyn DatagramTransport> = Arc::new(network.bind("127.0.0.1:50000".parse().unwrap()).unwrap());
+        let target = listener.local_addr().unwrap();
+
+        let mut rest = data;
+        while rest.len() >= 2 {
+            let len = (u16::from_le_bytes([rest[0], rest[1]]) as usize).min(rest.len() - 2);
+            client.send_to(&rest[2..2 + len], target).await.unwrap();
+            rest = &rest[2 + len..];
+        }
+
+        // a client that is connected doesn't get offline answers, ask from another address.
+        let pinger: Arc<dyn DatagramTransport> = Arc::new(network.bind("127.0.0.1:50001".parse().unwrap()).unwrap());
+        let ping = UnconnectedPing { client_send_time_be: 1, client_guid_be: 2 };
+        pinger.send_to(&ping.serialize(), target).await.unwrap();
+        let mut buf = [0u8; 1500];
+        tokio::time::timeout(Duration::from_secs(5), pinger.recv_from(&mut buf)).await
+            .expect("the listener stopped answering pings")
+            .unwrap();
+    });
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proto::motd::MOTD;
+use proto::query::Status;
+use proto::types::Packet;
+
+// the status string of an unconnected pong, and the ping it answers.
+fuzz_target!(|data: &[u8]| {
+    let _ = String::from_utf8_lossy(data).parse::<Status>();
+    let _ = MOTD::deserialize(data);
+});
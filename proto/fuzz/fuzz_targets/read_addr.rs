@@ -0,0 +1,13 @@
+#![no_main]
+
+use std::net::SocketAddr;
+use libfuzzer_sys::fuzz_target;
+use proto::address::read_addr;
+
+// addresses decode to what they encode back to.
+fuzz_target!(|data: &[u8]| {
+    if let Ok(address) = read_addr(data) {
+        let reread = read_addr(&address.serialize()).unwrap();
+        assert_eq!(SocketAddr::from(&address), SocketAddr::from(&reread));
+    }
+});
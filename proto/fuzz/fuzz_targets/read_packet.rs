@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proto::ReadPacket;
+
+// offline messages, as the listener decodes them from any address.
+fuzz_target!(|data: &[u8]| {
+    let _ = ReadPacket(data);
+});
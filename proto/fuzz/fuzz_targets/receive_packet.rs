@@ -0,0 +1,42 @@
+#![no_main]
+
+use std::sync::{Arc, OnceLock};
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use proto::clock::{Clock, SystemClock};
+use proto::conn::Conn;
+use proto::session::Session;
+use proto::transport::MemoryNetwork;
+use tokio::runtime::Runtime;
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap())
+}
+
+// The first byte picks the end of the connection the datagrams are received by: a server when
+// it is even, a client otherwise. The rest are datagrams, each preceded by its length as a
+// little endian u16, so that a whole handshake can be received before the datagram that breaks
+// the connection.
+fuzz_target!(|data: &[u8]| {
+    let Some((&role, mut rest)) = data.split_first() else {
+        return;
+    };
+    runtime().block_on(async {
+        let network = MemoryNetwork::new();
+        let socket = network.bind("127.0.0.1:19132".parse().unwrap()).unwrap();
+        let clock = Arc::new(SystemClock);
+        let remote = "127.0.0.1:50000".parse().unwrap();
+        let session = if role % 2 == 0 {
+            Session::server(clock.now(), remote, 1, proto::MTU_SIZE)
+        } else {
+            Session::client(clock.now(), remote, 1)
+        };
+        let conn = Conn::new(Arc::new(socket), clock, session);
+        while rest.len() >= 2 {
+            let len = (u16::from_le_bytes([rest[0], rest[1]]) as usize).min(rest.len() - 2);
+            let _ = conn.ReceivePacket(Bytes::copy_from_slice(&rest[2..2 + len])).await;
+            rest = &rest[2 + len..];
+        }
+    });
+});
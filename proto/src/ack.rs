@@ -0,0 +1,89 @@
+use crate::types::U24;
+
+// record types used in acknowledgements.
+const RECORD_RANGE: u8 = 0;
+const RECORD_SINGLE: u8 = 1;
+
+// size of the largest record, a range with two sequence numbers.
+const MAX_RECORD_SIZE: usize = 1 + 3 + 3;
+
+/// MAX_ACKNOWLEDGEMENT_PACKETS is the maximum amount of sequence numbers a single acknowledgement
+/// may cover. Ranges are expanded on read, so without a limit a tiny ACK could make us allocate
+/// millions of entries.
+pub const MAX_ACKNOWLEDGEMENT_PACKETS: usize = 8192;
+
+/// write_acknowledgement writes as many of the sequence numbers in `packets` as fit in `mtu` bytes
+/// to `buf` as ACK/NACK records, compressing consecutive numbers into ranges, including ranges
+/// that wrap around. `packets` must be sorted. The amount of sequence numbers written is returned, the caller writes the rest to the
+/// next datagram. `buf` should already hold the one byte datagram header.
+pub fn write_acknowledgement(buf: &mut Vec<u8>, packets: &[U24], mtu: usize) -> usize {
+    let count_offset = buf.len();
+    buf.extend_from_slice(&[0, 0]);
+
+    let mut records: u16 = 0;
+    let mut written = 0;
+    while written < packets.len() && buf.len() + MAX_RECORD_SIZE <= mtu {
+        let first = packets[written];
+        let mut last = first;
+        let mut n = 1;
+        while written + n < packets.len() && packets[written + n] == last.next() {
+            last = last.next();
+            n += 1;
+        }
+        if first == last {
+            buf.push(RECORD_SINGLE);
+            buf.extend_from_slice(&first.to_le_bytes());
+        } else {
+            buf.push(RECORD_RANGE);
+            buf.extend_from_slice(&first.to_le_bytes());
+            buf.extend_from_slice(&last.to_le_bytes());
+        }
+        records += 1;
+        written += n;
+    }
+    buf[count_offset..count_offset + 2].copy_from_slice(&records.to_be_bytes());
+    written
+}
+
+/// read_acknowledgement reads the records of an ACK or NACK, `data` starting right after the
+/// datagram header, and returns every sequence number they cover.
+pub fn read_acknowledgement(data: &[u8]) -> Result<Vec<U24>, String> {
+    if data.len() < 2 {
+        return Err("read acknowledgement: missing record count".to_string());
+    }
+    let record_count = u16::from_be_bytes([data[0], data[1]]);
+    let mut packets = Vec::new();
+    let mut offset = 2;
+    for _ in 0..record_count {
+        if data.len() < offset + 4 {
+            return Err("read acknowledgement: record is truncated".to_string());
+        }
+        match data[offset] {
+            RECORD_SINGLE => {
+                packets.push(U24::read(&data[offset + 1..]));
+                offset += 4;
+            }
+            RECORD_RANGE => {
+                if data.len() < offset + 7 {
+                    return Err("read acknowledgement: range record is truncated".to_string());
+                }
+                let start = U24::read(&data[offset + 1..]);
+                let end = U24::read(&data[offset + 4..]);
+                if start > end {
+                    return Err(format!("read acknowledgement: invalid range {}-{}", start, end));
+                }
+                let count = start.distance(end) as usize + 1;
+                if count + packets.len() > MAX_ACKNOWLEDGEMENT_PACKETS {
+                    return Err(format!("read acknowledgement: too many packets acknowledged ({}-{})", start, end));
+                }
+                packets.extend((0..count as u32).map(|i| start.wrapping_add(i)));
+                offset += 7;
+            }
+            record_type => return Err(format!("read acknowledgement: unknown record type {}", record_type)),
+        }
+        if packets.len() > MAX_ACKNOWLEDGEMENT_PACKETS {
+            return Err("read acknowledgement: too many packets acknowledged".to_string());
+        }
+    }
+    Ok(packets)
+}
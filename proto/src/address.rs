@@ -5,7 +5,7 @@ use std::str::FromStr;
 pub const SIZEOF_ADDR4: u8 = 1 + 4 + 2;
 pub const SIZEOF_ADDR6: u8 = 1 + 2 + 2 + 4 + 16 + 4;
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone, Copy)]
 pub enum AddrType {
     IPv4,
     IPv6,
@@ -15,7 +15,7 @@ pub enum AddrType {
 pub type Addr4 = [u8; 4];
 pub type Addr6 = [u8; 16];
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Addr {
     Addr4(Addr4),
     Addr6(Addr6),
@@ -63,7 +63,7 @@ impl FromStr for Addr {
         Ok(ip)
     }
 }
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Address {
     pub addr: Addr,
     pub port: u16,
@@ -84,14 +84,17 @@ impl Address {
     }
     pub fn size(&self) -> u8 {
         match self.addr_type {
-            AddrType::IPv4 => SIZEOF_ADDR4,
+            AddrType::IPv4 | AddrType::Zero => SIZEOF_ADDR4,
             AddrType::IPv6 => SIZEOF_ADDR6,
-            AddrType::Zero => 5,
         }
     }
     pub fn serialize(&self) -> Vec<u8> {
         serialize_addr(self)
     }
+
+    pub fn write(&self, buf: &mut Vec<u8>) {
+        write_addr(buf, self)
+    }
 }
 
 impl FromStr for Address {
@@ -134,33 +137,55 @@ impl From<SocketAddr> for Address {
     }
 }
 
+impl From<&Address> for SocketAddr {
+    fn from(address: &Address) -> Self {
+        match address.addr {
+            Addr::Addr4(ip) => SocketAddr::from((ip, address.port)),
+            Addr::Addr6(ip) => SocketAddr::from((ip, address.port)),
+        }
+    }
+}
+
 pub fn serialize_addr(addr: &Address) -> Vec<u8> {
-    if addr.addr_type == AddrType::IPv4 {
-        // IPv4 address.
-        let addr_bytes = addr.addr.to_bytes();
-        vec![
-            4,
-            !addr_bytes[0],
-            !addr_bytes[1],
-            !addr_bytes[2],
-            !addr_bytes[3],
-        ];
-    } else if addr.addr_type == AddrType::IPv6 {
+    let mut buf = Vec::with_capacity(addr.size() as usize);
+    write_addr(&mut buf, addr);
+    buf
+}
+
+/// write_addr appends the encoded address to `buf`.
+pub fn write_addr(buf: &mut Vec<u8>, addr: &Address) {
+    if addr.addr_type == AddrType::IPv6 {
         // IPv6 address.
-        let mut ret = vec![6];
-        ret.extend_from_slice(&23u16.to_be_bytes());
-        ret.extend_from_slice(&addr.port.to_be_bytes());
-        ret.extend_from_slice(&addr.addr.to_bytes());
-        return ret;
-    } else {
-        // Special case for zero addresses.
-        vec![4, 255, 255, 255, 255];
+        buf.push(6);
+        // AF_INET6 as defined on windows.
+        buf.extend_from_slice(&23u16.to_le_bytes());
+        buf.extend_from_slice(&addr.port.to_be_bytes());
+        // flow info.
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        buf.extend_from_slice(&addr.addr.to_bytes());
+        // scope id.
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        return;
     }
-    vec![]
+    // IPv4 addresses and the special case of zero addresses, which keep their type of 0 so that
+    // they are read back as such. A zero address without an IPv4 address is written as 0.0.0.0.
+    let addr_bytes = match addr.addr {
+        Addr::Addr4(addr) => addr,
+        Addr::Addr6(_) => [0; 4],
+    };
+    let addr_type = if addr.addr_type == AddrType::Zero { 0 } else { 4 };
+    buf.extend_from_slice(&[
+        addr_type,
+        !addr_bytes[0],
+        !addr_bytes[1],
+        !addr_bytes[2],
+        !addr_bytes[3],
+    ]);
+    buf.extend_from_slice(&addr.port.to_be_bytes());
 }
 
 pub fn read_addr(buf: &[u8]) -> Result<Address, String> {
-    if buf.len() < 5 {
+    if buf.len() < SIZEOF_ADDR4 as usize {
         return Err("Invalid address length".to_string());
     }
     let addr_type = match buf[0] {
@@ -169,9 +194,12 @@ pub fn read_addr(buf: &[u8]) -> Result<Address, String> {
         _ => AddrType::IPv6,
     };
     if addr_type == AddrType::IPv6 {
-        let port = read_be_u16(buf[3..].try_into().map_err(|_| "Failed to read port")?);
+        if buf.len() < SIZEOF_ADDR6 as usize {
+            return Err("Invalid address length".to_string());
+        }
+        let port = read_be_u16(&buf[3..]);
         let mut ip = [0u8; 16];
-        ip.copy_from_slice(&buf[9..]);
+        ip.copy_from_slice(&buf[9..25]);
         Ok(Address {
             addr: Addr::Addr6(ip),
             port,
@@ -183,7 +211,7 @@ pub fn read_addr(buf: &[u8]) -> Result<Address, String> {
         ip[1] = !buf[2];
         ip[2] = !buf[3];
         ip[3] = !buf[4];
-        let port = read_be_u16(buf[5..].try_into().map_err(|_| "Failed to read port")?);
+        let port = read_be_u16(&buf[5..]);
         Ok(Address {
             addr: Addr::Addr4(ip),
             port,
@@ -193,7 +221,7 @@ pub fn read_addr(buf: &[u8]) -> Result<Address, String> {
 }
 
 pub fn addr_size(b: &[u8]) -> u8 {
-    if b.len() == 0 || b[0] == 4 || b[0] == 0 {
+    if b.is_empty() || b[0] == 4 || b[0] == 0 {
         return SIZEOF_ADDR4;
     }
     SIZEOF_ADDR6
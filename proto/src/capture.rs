@@ -0,0 +1,553 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::ReadBuf;
+use crate::transport::DatagramTransport;
+
+// pcapng block types.
+const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const ENHANCED_PACKET: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+// packets start with an IPv4 or IPv6 header, without a link layer.
+const LINKTYPE_RAW: u32 = 101;
+// option codes: the end of the options, the interface name and the direction of a packet.
+const OPT_END: u16 = 0;
+const IF_NAME: u16 = 2;
+const EPB_FLAGS: u16 = 2;
+
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+const UDP_HEADER_SIZE: usize = 8;
+const PROTOCOL_UDP: u8 = 17;
+
+// how many datagrams can wait for the file unless the capture says otherwise.
+const CAPTURE_QUEUE: usize = 4096;
+
+/// Direction is whether a captured datagram was received or sent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+/// PcapngWriter writes datagrams to a pcapng file that Wireshark and tcpdump can open. Every
+/// datagram is wrapped in synthesized IP and UDP headers with the addresses it was sent from and
+/// to, so that dissectors see the same packets as on the wire.
+pub struct PcapngWriter<W: Write> {
+    w: W,
+    written: u64,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Starts a capture by writing the section header and a single interface to `w`.
+    pub fn new(w: W) -> io::Result<Self> {
+        let mut writer = Self { w, written: 0 };
+        let mut section = Vec::new();
+        section.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        // version 1.0, followed by a section length that isn't known up front.
+        section.extend_from_slice(&1u16.to_le_bytes());
+        section.extend_from_slice(&0u16.to_le_bytes());
+        section.extend_from_slice(&(-1i64).to_le_bytes());
+        writer.write_block(SECTION_HEADER, &section)?;
+
+        let mut interface = Vec::new();
+        interface.extend_from_slice(&(LINKTYPE_RAW as u16).to_le_bytes());
+        interface.extend_from_slice(&0u16.to_le_bytes());
+        // no snapshot length: datagrams are always captured whole.
+        interface.extend_from_slice(&0u32.to_le_bytes());
+        write_option(&mut interface, IF_NAME, b"raknet");
+        write_option(&mut interface, OPT_END, &[]);
+        writer.write_block(INTERFACE_DESCRIPTION, &interface)?;
+        Ok(writer)
+    }
+
+    /// Writes a datagram sent from `src` to `dst` at `time`.
+    pub fn write_datagram(&mut self, time: SystemTime, src: SocketAddr, dst: SocketAddr, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let packet = ip_packet(src, dst, data);
+        let micros = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+
+        let mut block = Vec::with_capacity(packet.len() + 32);
+        // the only interface.
+        block.extend_from_slice(&0u32.to_le_bytes());
+        block.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+        block.extend_from_slice(&(micros as u32).to_le_bytes());
+        block.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        block.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        block.extend_from_slice(&packet);
+        pad(&mut block);
+        let flags: u32 = match direction {
+            Direction::Inbound => 1,
+            Direction::Outbound => 2,
+        };
+        write_option(&mut block, EPB_FLAGS, &flags.to_le_bytes());
+        write_option(&mut block, OPT_END, &[]);
+        self.write_block(ENHANCED_PACKET, &block)
+    }
+
+    /// Returns how many bytes were written so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let length = (body.len() + 12) as u32;
+        self.w.write_all(&block_type.to_le_bytes())?;
+        self.w.write_all(&length.to_le_bytes())?;
+        self.w.write_all(body)?;
+        self.w.write_all(&length.to_le_bytes())?;
+        self.written += length as u64;
+        Ok(())
+    }
+}
+
+fn write_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    pad(buf);
+}
+
+// pad pads a block to a multiple of 4 bytes, as pcapng requires.
+fn pad(buf: &mut Vec<u8>) {
+    buf.resize(buf.len().next_multiple_of(4), 0);
+}
+
+// ip_packet wraps a datagram in the IP and UDP headers it would have had on the wire. IPv4
+// addresses are mapped to IPv6 if the other address is IPv6, like on a dual stack socket.
+fn ip_packet(src: SocketAddr, dst: SocketAddr, data: &[u8]) -> Vec<u8> {
+    let udp_length = (UDP_HEADER_SIZE + data.len()) as u16;
+    let mut packet = match (src.ip(), dst.ip()) {
+        (IpAddr::V4(src_ip), IpAddr::V4(dst_ip)) => {
+            let mut header = Vec::with_capacity(IPV4_HEADER_SIZE + udp_length as usize);
+            header.push(0x45);
+            header.push(0);
+            header.extend_from_slice(&(IPV4_HEADER_SIZE as u16 + udp_length).to_be_bytes());
+            // identification, then don't fragment without a fragment offset.
+            header.extend_from_slice(&[0, 0, 0x40, 0]);
+            header.push(64);
+            header.push(PROTOCOL_UDP);
+            header.extend_from_slice(&[0, 0]);
+            header.extend_from_slice(&src_ip.octets());
+            header.extend_from_slice(&dst_ip.octets());
+            let checksum = !ones_complement_sum(&[&header]);
+            header[10..12].copy_from_slice(&checksum.to_be_bytes());
+            header
+        }
+        (src_ip, dst_ip) => {
+            let mut header = Vec::with_capacity(IPV6_HEADER_SIZE + udp_length as usize);
+            header.extend_from_slice(&[0x60, 0, 0, 0]);
+            header.extend_from_slice(&udp_length.to_be_bytes());
+            header.push(PROTOCOL_UDP);
+            header.push(64);
+            header.extend_from_slice(&to_ipv6(src_ip).octets());
+            header.extend_from_slice(&to_ipv6(dst_ip).octets());
+            header
+        }
+    };
+    let ip_header_size = packet.len();
+    packet.extend_from_slice(&src.port().to_be_bytes());
+    packet.extend_from_slice(&dst.port().to_be_bytes());
+    packet.extend_from_slice(&udp_length.to_be_bytes());
+    packet.extend_from_slice(&[0, 0]);
+    packet.extend_from_slice(data);
+
+    // the UDP checksum covers a pseudo header of the addresses, the protocol and the length.
+    let addresses = if ip_header_size == IPV4_HEADER_SIZE { &packet[12..20] } else { &packet[8..40] };
+    let pseudo = [0, PROTOCOL_UDP, (udp_length >> 8) as u8, udp_length as u8];
+    let checksum = match !ones_complement_sum(&[addresses, &pseudo, &packet[ip_header_size..]]) {
+        // zero means no checksum, a checksum of zero is sent as all ones instead.
+        0 => 0xffff,
+        checksum => checksum,
+    };
+    packet[ip_header_size + 6..ip_header_size + 8].copy_from_slice(&checksum.to_be_bytes());
+    packet
+}
+
+fn to_ipv6(ip: IpAddr) -> std::net::Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+// ones_complement_sum is the internet checksum (RFC 1071) of the parts passed, before the final
+// complement. Every part but the last must have an even length.
+fn ones_complement_sum(parts: &[&[u8]]) -> u16 {
+    let mut sum: u32 = 0;
+    for part in parts {
+        for pair in part.chunks(2) {
+            let word = if pair.len() == 2 { u16::from_be_bytes([pair[0], pair[1]]) } else { u16::from_be_bytes([pair[0], 0]) };
+            sum += word as u32;
+        }
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+/// Capture describes where a `CaptureTransport` writes the datagrams it sees to.
+#[derive(Debug, Clone, Default)]
+pub struct Capture {
+    pub path: PathBuf,
+    /// Starts a new file once the current one grew past this many bytes. The files are numbered,
+    /// `capture.pcapng` being written as `capture-0.pcapng`, `capture-1.pcapng` and so on.
+    pub rotate_size: Option<u64>,
+    /// Only datagrams to and from these addresses are captured, a port of 0 matching any port of
+    /// its IP. Everything is captured if it is empty.
+    pub peers: Vec<SocketAddr>,
+    /// How many datagrams can wait to be written before new ones are dropped, 4096 if not set.
+    pub queue_size: Option<usize>,
+}
+
+impl Capture {
+    fn matches(&self, peer: SocketAddr) -> bool {
+        self.peers.is_empty() || self.peers.iter().any(|filter| {
+            filter.ip() == peer.ip() && (filter.port() == 0 || filter.port() == peer.port())
+        })
+    }
+
+    // file_path returns the path of the nth file of the capture.
+    fn file_path(&self, n: u64) -> PathBuf {
+        if self.rotate_size.is_none() {
+            return self.path.clone();
+        }
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match self.path.extension() {
+            Some(extension) => format!("{}-{}.{}", stem, n, extension.to_string_lossy()),
+            None => format!("{}-{}", stem, n),
+        };
+        self.path.with_file_name(name)
+    }
+}
+
+enum Record {
+    Datagram { time: SystemTime, src: SocketAddr, dst: SocketAddr, direction: Direction, data: Vec<u8> },
+    Flush(mpsc::Sender<io::Result<()>>),
+}
+
+/// CaptureTransport wraps a transport and writes every datagram sent and received through it to
+/// a pcapng file, from the point of view of the wrapped transport's local address. Transports
+/// don't tell which address of theirs a datagram went through, so a transport bound to 0.0.0.0 or
+/// :: is captured with that unspecified address on its side of every datagram.
+///
+/// Files are written by a thread of their own, so capturing never makes the connections wait on
+/// the disk: datagrams that come in while the queue of the thread is full are dropped from the
+/// capture and counted instead.
+pub struct CaptureTransport {
+    inner: Arc<dyn DatagramTransport>,
+    local: SocketAddr,
+    capture: Capture,
+    records: mpsc::SyncSender<Record>,
+    dropped: AtomicU64,
+}
+
+impl CaptureTransport {
+    /// Wraps `inner`, creating the first file of the capture right away so that a path that
+    /// can't be written to is reported here.
+    pub fn new(inner: Arc<dyn DatagramTransport>, capture: Capture) -> io::Result<Self> {
+        let local = inner.local_addr()?;
+        let writer = create(&capture.file_path(0))?;
+        let (tx, rx) = mpsc::sync_channel(capture.queue_size.unwrap_or(CAPTURE_QUEUE));
+        let files = capture.clone();
+        thread::spawn(move || {
+            if let Err(e) = write_records(writer, &files, rx) {
+                tracing::warn!(path = %files.path.display(), error = %e, "capture stopped");
+            }
+        });
+        Ok(Self { inner, local, capture, records: tx, dropped: AtomicU64::new(0) })
+    }
+
+    /// Returns how many datagrams were left out of the capture because the file couldn't keep up.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Waits for everything captured so far to be written to the file.
+    pub fn flush(&self) -> io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let stopped = || io::Error::other("capture stopped");
+        self.records.send(Record::Flush(tx)).map_err(|_| stopped())?;
+        rx.recv().map_err(|_| stopped())?
+    }
+
+    fn record(&self, src: SocketAddr, dst: SocketAddr, direction: Direction, data: &[u8]) {
+        let peer = if direction == Direction::Inbound { src } else { dst };
+        if self.capture.matches(peer) {
+            let time = SystemTime::now();
+            let record = Record::Datagram { time, src, dst, direction, data: data.to_vec() };
+            // if the capture stopped it logged why, the transport keeps working without it.
+            if let Err(mpsc::TrySendError::Full(_)) = self.records.try_send(record) {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl DatagramTransport for CaptureTransport {
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        let res = self.inner.poll_send_to(cx, buf, target);
+        if let Poll::Ready(Ok(_)) = res {
+            self.record(self.local, target, Direction::Outbound, buf);
+        }
+        res
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        let filled = buf.filled().len();
+        let res = self.inner.poll_recv_from(cx, buf);
+        if let Poll::Ready(Ok(src)) = res {
+            self.record(src, self.local, Direction::Inbound, &buf.filled()[filled..]);
+        }
+        res
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local)
+    }
+}
+
+fn create(path: &Path) -> io::Result<PcapngWriter<BufWriter<File>>> {
+    PcapngWriter::new(BufWriter::new(File::create(path)?))
+}
+
+// write_records writes the datagrams captured until the transport is dropped, flushing whenever
+// it caught up so that the file can be followed while it is written.
+fn write_records(mut writer: PcapngWriter<BufWriter<File>>, capture: &Capture, records: mpsc::Receiver<Record>) -> io::Result<()> {
+    let mut file = 0;
+    loop {
+        let record = match records.try_recv() {
+            Ok(record) => record,
+            Err(mpsc::TryRecvError::Empty) => {
+                writer.flush()?;
+                match records.recv() {
+                    Ok(record) => record,
+                    Err(_) => return Ok(()),
+                }
+            }
+            Err(mpsc::TryRecvError::Disconnected) => return writer.flush(),
+        };
+        match record {
+            Record::Datagram { time, src, dst, direction, data } => {
+                if capture.rotate_size.is_some_and(|size| writer.written() >= size) {
+                    writer.flush()?;
+                    file += 1;
+                    writer = create(&capture.file_path(file))?;
+                }
+                writer.write_datagram(time, src, dst, direction, &data)?;
+            }
+            Record::Flush(done) => {
+                let _ = done.send(writer.flush());
+            }
+        }
+    }
+}
+
+// link types of captures that can be read, besides LINKTYPE_RAW.
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_LOOP: u32 = 108;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_IPV4: u32 = 228;
+const LINKTYPE_IPV6: u32 = 229;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+const SIMPLE_PACKET: u32 = 3;
+const IF_TSRESOL: u16 = 9;
+
+/// CapturedDatagram is a UDP datagram read from a capture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedDatagram {
+    pub time: SystemTime,
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub data: Vec<u8>,
+}
+
+/// Reads the UDP datagrams in a pcap or pcapng capture, such as those written by
+/// `CaptureTransport` or by tcpdump and Wireshark. Captures of raw IP, Ethernet, loopback and
+/// Linux cooked links can be read. Packets that aren't UDP, IP fragments and datagrams cut short
+/// by the snapshot length are skipped.
+pub fn read_capture(data: &[u8]) -> io::Result<Vec<CapturedDatagram>> {
+    let magic = data.get(..4).ok_or_else(|| invalid("capture is empty"))?;
+    let packets = match magic {
+        [0x0a, 0x0d, 0x0d, 0x0a] => read_pcapng(data)?,
+        _ => read_pcap(data)?,
+    };
+    Ok(packets.into_iter().filter_map(|(time, link_type, packet)| {
+        let (src, dst, data) = udp_datagram(link_type, packet)?;
+        Some(CapturedDatagram { time, src, dst, data: data.to_vec() })
+    }).collect())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("read capture: {}", message))
+}
+
+// Reader reads the integers of a capture in the byte order it was written in.
+#[derive(Copy, Clone)]
+struct Reader {
+    big_endian: bool,
+}
+
+impl Reader {
+    fn u16(self, data: &[u8], at: usize) -> io::Result<u16> {
+        let bytes = data.get(at..at + 2).ok_or_else(|| invalid("truncated"))?.try_into().unwrap();
+        Ok(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(self, data: &[u8], at: usize) -> io::Result<u32> {
+        let bytes = data.get(at..at + 4).ok_or_else(|| invalid("truncated"))?.try_into().unwrap();
+        Ok(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+}
+
+// read_pcap reads the packets of a classic pcap file with their time and link type.
+fn read_pcap(data: &[u8]) -> io::Result<Vec<(SystemTime, u32, &[u8])>> {
+    let (big_endian, nanos) = match data.get(..4) {
+        Some([0xd4, 0xc3, 0xb2, 0xa1]) => (false, false),
+        Some([0xa1, 0xb2, 0xc3, 0xd4]) => (true, false),
+        Some([0x4d, 0x3c, 0xb2, 0xa1]) => (false, true),
+        Some([0xa1, 0xb2, 0x3c, 0x4d]) => (true, true),
+        _ => return Err(invalid("not a pcap or pcapng file")),
+    };
+    let r = Reader { big_endian };
+    let link_type = r.u32(data, 20)?;
+    let mut packets = Vec::new();
+    let mut at = 24;
+    while at < data.len() {
+        let seconds = r.u32(data, at)? as u64;
+        let fraction = r.u32(data, at + 4)? as u64;
+        let length = r.u32(data, at + 8)? as usize;
+        let packet = data.get(at + 16..at + 16 + length).ok_or_else(|| invalid("truncated packet"))?;
+        let since_epoch = if nanos {
+            Duration::from_secs(seconds) + Duration::from_nanos(fraction)
+        } else {
+            Duration::from_secs(seconds) + Duration::from_micros(fraction)
+        };
+        packets.push((UNIX_EPOCH + since_epoch, link_type, packet));
+        at += 16 + length;
+    }
+    Ok(packets)
+}
+
+// read_pcapng reads the packets of a pcapng file with their time and the link type of their
+// interface. Simple packet blocks have no time, they get that of the packet before them.
+fn read_pcapng(data: &[u8]) -> io::Result<Vec<(SystemTime, u32, &[u8])>> {
+    let mut r = Reader { big_endian: false };
+    // link type and time resolution in units per second, by interface.
+    let mut interfaces: Vec<(u32, u64)> = Vec::new();
+    let mut packets = Vec::new();
+    let mut time = UNIX_EPOCH;
+    let mut at = 0;
+    while at < data.len() {
+        if data.get(at..at + 4) == Some(&SECTION_HEADER.to_le_bytes()) {
+            r.big_endian = data.get(at + 8..at + 12) == Some(&BYTE_ORDER_MAGIC.to_be_bytes());
+            interfaces.clear();
+        }
+        let block_type = r.u32(data, at)?;
+        let length = r.u32(data, at + 4)? as usize;
+        if length < 12 || !length.is_multiple_of(4) {
+            return Err(invalid("bad block length"));
+        }
+        let body = data.get(at + 8..at + length - 4).ok_or_else(|| invalid("truncated block"))?;
+        match block_type {
+            INTERFACE_DESCRIPTION => {
+                let link_type = r.u16(body, 0)? as u32;
+                let mut resolution = 1_000_000;
+                let mut options = body.get(8..).unwrap_or_default();
+                while options.len() >= 4 {
+                    let code = r.u16(options, 0)?;
+                    let len = r.u16(options, 2)? as usize;
+                    if code == IF_TSRESOL && len == 1 && options.len() > 4 {
+                        let exponent = (options[4] & 0x7f) as u32;
+                        let base: u64 = if options[4] & 0x80 == 0 { 10 } else { 2 };
+                        resolution = base.checked_pow(exponent).ok_or_else(|| invalid("bad time resolution"))?;
+                    }
+                    if code == OPT_END {
+                        break;
+                    }
+                    options = options.get(4 + len.next_multiple_of(4)..).unwrap_or_default();
+                }
+                interfaces.push((link_type, resolution));
+            }
+            ENHANCED_PACKET => {
+                let &(link_type, resolution) = interfaces.get(r.u32(body, 0)? as usize).ok_or_else(|| invalid("unknown interface"))?;
+                let units = (r.u32(body, 4)? as u64) << 32 | r.u32(body, 8)? as u64;
+                let length = r.u32(body, 12)? as usize;
+                let packet = body.get(20..20 + length).ok_or_else(|| invalid("truncated packet"))?;
+                let since_epoch = Duration::from_secs(units / resolution)
+                    + Duration::from_nanos((units % resolution) * 1_000_000_000 / resolution);
+                time = UNIX_EPOCH + since_epoch;
+                packets.push((time, link_type, packet));
+            }
+            SIMPLE_PACKET => {
+                let &(link_type, _) = interfaces.first().ok_or_else(|| invalid("unknown interface"))?;
+                let length = (r.u32(body, 0)? as usize).min(body.len() - 4);
+                packets.push((time, link_type, &body[4..4 + length]));
+            }
+            _ => {}
+        }
+        at += length;
+    }
+    Ok(packets)
+}
+
+// udp_datagram takes the addresses and the payload of a UDP packet out of a captured frame.
+fn udp_datagram(link_type: u32, frame: &[u8]) -> Option<(SocketAddr, SocketAddr, &[u8])> {
+    let ip = match link_type {
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => frame,
+        LINKTYPE_NULL | LINKTYPE_LOOP => frame.get(4..)?,
+        LINKTYPE_ETHERNET => {
+            let mut at = 12;
+            // skip VLAN tags.
+            while matches!(frame.get(at..at + 2)?, [0x81, 0x00] | [0x88, 0xa8]) {
+                at += 4;
+            }
+            frame.get(at + 2..)?
+        }
+        LINKTYPE_LINUX_SLL => frame.get(16..)?,
+        LINKTYPE_LINUX_SLL2 => frame.get(20..)?,
+        _ => return None,
+    };
+    let (src, dst, udp) = match ip.first()? >> 4 {
+        4 => {
+            let header_size = ((ip[0] & 0x0f) as usize) * 4;
+            let fragmented = ip.get(6)? & 0x3f != 0 || *ip.get(7)? != 0;
+            if ip.get(9)? != &PROTOCOL_UDP || fragmented {
+                return None;
+            }
+            let src: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let dst: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            (IpAddr::from(src), IpAddr::from(dst), ip.get(header_size..)?)
+        }
+        6 => {
+            if ip.get(6)? != &PROTOCOL_UDP {
+                return None;
+            }
+            let src: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let dst: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            (IpAddr::from(src), IpAddr::from(dst), ip.get(IPV6_HEADER_SIZE..)?)
+        }
+        _ => return None,
+    };
+    let src_port = u16::from_be_bytes(udp.get(0..2)?.try_into().ok()?);
+    let dst_port = u16::from_be_bytes(udp.get(2..4)?.try_into().ok()?);
+    let length = u16::from_be_bytes(udp.get(4..6)?.try_into().ok()?) as usize;
+    let payload = udp.get(UDP_HEADER_SIZE..length)?;
+    Some((SocketAddr::new(src, src_port), SocketAddr::new(dst, dst_port), payload))
+}
@@ -0,0 +1,64 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Clock is the source of time of connections and listeners. Sessions only ever see the instants
+/// a clock returns, so swapping the clock for a `ManualClock` makes every timer deterministic.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Returns a future that completes once `now` has reached `deadline`.
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send + '_>>;
+}
+
+/// SystemClock is the monotonic clock of the system, sleeping with tokio's timer.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(tokio::time::sleep_until(deadline.into()))
+    }
+}
+
+/// ManualClock is a clock that only moves when `advance` is called, for tests that need to
+/// control exactly when timers fire.
+pub struct ManualClock {
+    now: watch::Sender<Instant>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self { now: watch::Sender::new(Instant::now()) }
+    }
+
+    /// Moves the clock forward, waking everything sleeping until a time that has now passed.
+    pub fn advance(&self, duration: Duration) {
+        self.now.send_modify(|now| *now += duration);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.borrow()
+    }
+
+    fn sleep_until(&self, deadline: Instant) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        let mut now = self.now.subscribe();
+        Box::pin(async move {
+            // the sender lives as long as the clock, which outlives this future.
+            let _ = now.wait_for(|now| *now >= deadline).await;
+        })
+    }
+}
@@ -1,219 +1,432 @@
 use std::collections::HashMap;
+use std::future::Future;
+use std::io;
 use std::net::SocketAddr;
-use std::ops::{Add, Deref, Div};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
-use lazy_static::lazy_static;
-use tokio::net::UdpSocket;
-use tokio::sync::{oneshot, Mutex};
-use crate::address::Address;
-use crate::dynamic_queue::DynamicQueue;
-use crate::messages::connected_ping::ConnectedPing;
-use crate::messages::unknown::UnknownPacket;
-use crate::types::{read_u24, uint24, Packet};
-use crate::frame::Window;
-use crate::packet_queue::PacketQueue;
-use crate::{PacketT, ReadPacket};
-use crate::packet::PacketBitFlags;
-
-// Current RakNet protocol version for Minecraft
-const PROTOCOL_VERSION: u8 = 11;
-
-const MIN_TRANSMISSION_UNIT_SIZE: u16    = 576;
-const MAX_TRANSMISSION_UNIT_SIZE: u16    = 1492;
-const MAX_WINDOW_SIZE: u16 = 2048;
-
-
+use std::task::{Context, Poll};
+use std::time::Duration;
+use bytes::Bytes;
+use tokio::net::{lookup_host, ToSocketAddrs, UdpSocket};
+use tokio::sync::{mpsc, oneshot, Mutex, Notify};
+use tracing::{debug, field, info_span, trace, warn, Instrument, Span};
+use crate::clock::{Clock, SystemClock};
+use crate::packet::{Priority, Reliability};
+use crate::session::{DisconnectReason, Event, Session};
+use crate::stats::Stats;
+use crate::transport::{spawn_writer, DatagramTransport, Outbound, ReceiveBuffer};
+use crate::types::{Hex, Packet};
+
+// how often the ticker puts the stats returned by `last_stats` together.
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Conn is a RakNet connection to a single peer. It drives a `Session` with a datagram transport:
+/// the datagrams a session produces are sent on the transport and its timer is run by `start_ticking`.
+/// Datagrams from the peer are passed to `ReceivePacket`, either by the read loop `dial` spawns or
+/// by the listener that accepted the connection. Datagrams are sent by a writer task of their own,
+/// so writing only waits on the socket once it falls behind by a few hundred datagrams.
+///
+/// Messages that aren't written immediately are buffered by the session and sent together in as
+/// few datagrams as possible once a datagram is full, `flush` is called or 10ms have passed.
+///
+/// A Conn is shared as `Arc<Conn>` between the tasks reading, writing and ticking it. The session
+/// sits behind a mutex, and what the application may want to know about it without waiting for
+/// that mutex is mirrored in atomics every time the output of the session is dispatched.
+///
+/// What happens on the connection is logged with `tracing` in a `conn` span carrying the remote
+/// address and, once the handshake is done, the GUID of the peer. Every datagram and message is
+/// logged at trace level.
 pub struct Conn {
-    pub round_trip_time: Arc<u64>,
-    pub closing: Arc<bool>,
-
-    pub conn: Arc<Mutex<UdpSocket>>,
-
-    pub connected_rx: Option<oneshot::Receiver<()>>,
-    pub connected_tx: Option<oneshot::Sender<()>>,
-
-    pub buf: Mutex<Vec<u8>>,
-    pub close_conn: fn(Arc<Mutex<UdpSocket>>),
-    pub ack_buf: Mutex<Vec<u8>>,
-    pub nack_buf: Mutex<Vec<u8>>,
-
-    pub packet: Box<dyn Packet>,
-
-    pub sequence_number: uint24,
-    pub order_index: uint24,
-    pub message_index: uint24,
-
-    pub split_id: u32,
-
-    pub max_transmission_unit: u16,
-
-    pub splits: HashMap<u16, Vec<Vec<u8>>>,
-
-    pub window: Mutex<Window>,
-
-    pub ack_slice: Mutex<Vec<uint24>>,
-
-    pub packet_queue: *mut PacketQueue,
-    pub packets: DynamicQueue<Vec<u8>>,
-
-    pub last_packet_time: Arc<*mut SystemTime>,
-
-    pub limits_enabled: bool,
+    pub conn: Arc<dyn DatagramTransport>,
+    pub remote: SocketAddr,
     pub is_server: bool,
+
+    clock: Arc<dyn Clock>,
+    outbound: Outbound,
+    span: Span,
+    session: Mutex<Session>,
+    wake: Notify,
+    // notified when the session may want `handle_timeout` to be called earlier than the ticker sleeps.
+    reschedule: Notify,
+
+    closed: AtomicBool,
+    round_trip_time: AtomicU64,
+    effective_mtu: AtomicU16,
+    // refreshed by the ticker, for those that can't wait for the session.
+    last_stats: std::sync::Mutex<Stats>,
+
+    events_tx: std::sync::Mutex<Option<mpsc::UnboundedSender<Event>>>,
+    events_rx: Mutex<mpsc::UnboundedReceiver<Event>>,
+    // the receipts handed out by write_with_receipt that haven't completed yet, by session receipt.
+    receipts: std::sync::Mutex<HashMap<u32, oneshot::Sender<bool>>>,
 }
 
 impl Conn {
-    pub async fn new(socket: Arc<Mutex<UdpSocket>>, max_transmission_unit: u16, is_server: bool) -> Self {
-        let (tx, rx) = oneshot::channel::<()>();
+    pub fn new(socket: Arc<dyn DatagramTransport>, clock: Arc<dyn Clock>, session: Session) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
         Self {
-            conn: Arc::clone(&socket),
-            max_transmission_unit,
+            outbound: spawn_writer(socket.clone()),
+            conn: socket,
+            remote: session.remote,
+            is_server: session.is_server,
+
+            clock,
+            span: info_span!("conn", remote = %session.remote, guid = field::Empty),
+            wake: Notify::new(),
+            reschedule: Notify::new(),
+
+            closed: AtomicBool::new(session.is_closed()),
+            round_trip_time: AtomicU64::new(session.round_trip_time().as_micros() as u64),
+            effective_mtu: AtomicU16::new(session.effective_mtu()),
+            last_stats: std::sync::Mutex::new(session.stats()),
+            session: Mutex::new(session),
+
+            events_tx: std::sync::Mutex::new(Some(tx)),
+            events_rx: Mutex::new(rx),
+            receipts: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
 
-            round_trip_time: Arc::new(0),
-            closing: Arc::new(false),
+    /// Connects to a RakNet server, returning once both the offline and the connected handshake
+    /// are done.
+    pub async fn dial<A: ToSocketAddrs>(address: A) -> io::Result<Arc<Conn>> {
+        let remote = lookup_host(address).await?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "dial: address did not resolve")
+        })?;
+        let local: SocketAddr = if remote.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+        let socket = UdpSocket::bind(local).await?;
+        Self::dial_on(Arc::new(socket), Arc::new(SystemClock), remote).await
+    }
 
-            connected_rx: Some(rx),
-            connected_tx: Some(tx),
+    /// Connects to a RakNet server at `remote` over a transport that is already bound, such as a
+    /// `MemorySocket`, with the timers of the connection running on `clock`. The transport should
+    /// not be shared with anything else.
+    pub async fn dial_on(transport: Arc<dyn DatagramTransport>, clock: Arc<dyn Clock>, remote: SocketAddr) -> io::Result<Arc<Conn>> {
+        let session = Session::client(clock.now(), remote, rand::random());
+        let conn = Arc::new(Conn::new(transport, clock, session));
+        conn.dispatch().await;
+        tokio::spawn(conn.clone().start_ticking());
+        tokio::spawn(conn.clone().read_loop());
+
+        conn.wait_connected().await?;
+        Ok(conn)
+    }
 
-            packets: DynamicQueue::new(4, 4096),
+    /// Waits for the connected handshake to complete. This must be called before the first
+    /// ReadPacket, which would otherwise skip over the connected event.
+    pub(crate) async fn wait_connected(&self) -> io::Result<()> {
+        match self.events_rx.lock().await.recv().await {
+            Some(Event::Connected) => Ok(()),
+            Some(Event::Disconnected(reason)) => Err(disconnect_error(reason)),
+            _ => Err(io::Error::new(io::ErrorKind::ConnectionAborted, "connection closed during handshake")),
+        }
+    }
 
-            buf: Mutex::new(Vec::with_capacity(max_transmission_unit as usize - 28)),
-            ack_buf: Mutex::new(Vec::with_capacity(128)),
-            nack_buf: Mutex::new(Vec::with_capacity(64)),
+    // read_loop reads the datagrams of a dialed connection until it is closed.
+    async fn read_loop(self: Arc<Self>) {
+        let span = self.span.clone();
+        self.read_datagrams().instrument(span).await
+    }
 
-            packet: Box::new(UnknownPacket{id: 0, data: Vec::new()}),
-            splits: HashMap::new(),
-            ack_slice: Mutex::new(Vec::new()),
-            last_packet_time: Arc::new(Box::into_raw(Box::new(SystemTime::now()))),
+    async fn read_datagrams(self: Arc<Self>) {
+        let mut buf = ReceiveBuffer::new();
+        loop {
+            let received = tokio::select! {
+                res = buf.recv_from(&self.conn) => res,
+                _ = self.wait_closed() => return,
+            };
+            match received {
+                Ok((data, src)) if src == self.remote => {
+                    // ReceivePacket logs the datagrams it can't handle itself.
+                    let _ = self.ReceivePacket(data).await;
+                }
+                Ok((_, src)) => trace!(%src, "ignoring datagram from another address"),
+                // an ICMP port unreachable for an earlier datagram, which Windows reports here.
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => trace!(error = %e, "ignoring reset"),
+                Err(e) => {
+                    warn!(error = %e, "reading datagrams failed");
+                    return;
+                }
+            }
+        }
+    }
 
-            sequence_number: 0,
-            order_index: 0,
-            message_index: 0,
-            split_id: 0,
+    pub(crate) async fn wait_closed(&self) {
+        loop {
+            let notified = self.wake.notified();
+            if self.is_closed() {
+                return;
+            }
+            notified.await;
+        }
+    }
 
-            window: Mutex::new(Window::new()),
-            packet_queue: Box::into_raw(Box::new(PacketQueue::new())),
-            close_conn: |socket| {
-                drop(socket)
-            },
+    /// Runs the timer of the session until the connection is closed.
+    pub async fn start_ticking(self: Arc<Self>) {
+        let span = self.span.clone();
+        self.tick().instrument(span).await
+    }
 
-            limits_enabled: true,
-            is_server
+    async fn tick(self: Arc<Self>) {
+        let mut stats_due = self.clock.now() + STATS_INTERVAL;
+        loop {
+            let deadline = match self.session.lock().await.poll_timeout() {
+                Some(deadline) => deadline,
+                None => break,
+            };
+            tokio::select! {
+                _ = self.clock.sleep_until(deadline) => {
+                    let now = self.clock.now();
+                    let mut session = self.session.lock().await;
+                    session.handle_timeout(now);
+                    if now >= stats_due {
+                        *self.last_stats.lock().unwrap() = session.stats();
+                        stats_due = now + STATS_INTERVAL;
+                    }
+                }
+                _ = self.wake.notified() => {}
+                _ = self.reschedule.notified() => {}
+            }
+            self.dispatch().await;
         }
+        *self.last_stats.lock().unwrap() = self.session.lock().await.stats();
+        self.wake.notify_waiters();
     }
 
-    pub fn effective_mtu(&self) -> u16 {
-        self.max_transmission_unit - 28
+    /// Handles a datagram received from the peer of the connection.
+    #[allow(non_snake_case)]
+    pub async fn ReceivePacket(&self, data: Bytes) -> Result<(), String> {
+        trace!(parent: &self.span, len = data.len(), "received datagram");
+        let res = self.session.lock().await.handle_datagram_bytes(self.clock.now(), data.clone());
+        if let Err(e) = &res {
+            // a peer that sends invalid data can't be trusted with the connection anymore.
+            warn!(parent: &self.span, error = %e, "closing connection after invalid datagram");
+            debug!(parent: &self.span, data = %Hex(&data), "invalid datagram");
+            self.session.lock().await.close(self.clock.now());
+        }
+        self.dispatch().await;
+        res
     }
 
-    pub async fn start_ticking(&mut self) {
-        const INTERVAL: Duration = Duration::from_millis(100);
-        let mut tick_count: i64 = 0;
-        let mut acks_left: i32 = 0;
+    /// Reads the next message sent by the peer, waiting until one arrives.
+    #[allow(non_snake_case)]
+    pub async fn ReadPacket(&self) -> io::Result<Bytes> {
+        let mut events = self.events_rx.lock().await;
         loop {
-            tokio::time::sleep(INTERVAL).await;
-            let system_time =  SystemTime::now();
-            tick_count += 1;
-            if *self.closing.as_ref() {
-                break;
-            }
-            if tick_count%3 == 0 {
-                self.check_resend(system_time).await;
-            }
-            if tick_count%5 == 0 {
-                _ = self.conn.lock().await.send(&ConnectedPing{client_send_time_be: system_time.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as u64}.serialize());
+            match events.recv().await {
+                Some(Event::Message(data)) => return Ok(data),
+                Some(Event::Disconnected(reason)) => return Err(disconnect_error(reason)),
+                // receipts never end up here, they're passed to their writers.
+                Some(Event::Connected | Event::Delivered(_) | Event::Lost(_)) => {}
+                None => return Err(io::Error::new(io::ErrorKind::NotConnected, "read packet: connection closed")),
             }
         }
     }
-    pub fn handle_nack(&self, data: &[u8]) -> Result<Option<PacketT>, String> {
-        ReadPacket(data)
-    }
 
-    pub fn handle_ack(&self, data: &[u8]) -> Result<Option<PacketT>, String> {
-        ReadPacket(data)
+    /// Writes a message to the peer as reliable ordered on the first channel, sending it right away.
+    #[allow(non_snake_case)]
+    pub async fn Write(&self, data: &[u8]) -> io::Result<usize> {
+        self.write_with(data, Reliability::ReliableOrdered, 0, true).await
     }
 
-    pub async fn send_ack(&self, missing: &[uint24], flags: PacketBitFlags, buf: &mut Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
-        panic!("send_ack: not implemented");
+    /// Writes a message to the peer with the reliability and order channel passed. An immediate
+    /// message is sent right away, together with the messages buffered before it, others are
+    /// buffered until the next flush.
+    pub async fn write_with(&self, data: &[u8], reliability: Reliability, channel: u8, immediate: bool) -> io::Result<usize> {
+        self.write_with_priority(data, reliability, Priority::Medium, channel, immediate).await
     }
 
-    pub async fn send_nack(&self, missing: &[uint24]) -> Result<(), Box<dyn std::error::Error>> {
-        let res = self.send_ack(missing, PacketBitFlags::NACK, self.nack_buf.lock().await.as_mut()).await;
-        self.nack_buf.lock().await.clear();
-        res
+    /// Writes a message like `write_with`, with the priority passed instead of medium priority.
+    pub async fn write_with_priority(&self, data: &[u8], reliability: Reliability, priority: Priority, channel: u8, immediate: bool) -> io::Result<usize> {
+        self.send(data, reliability, priority, channel, immediate, None).await?;
+        Ok(data.len())
     }
 
-    // this function itself resends? (atleast in go-raknet, i might change that because that's weird)
-    pub async fn check_resend(&mut self, now: SystemTime) {
-        // we calculate this.. im lazy i'll do this later.
-        // self.round_trip_time = ;
+    /// Writes a message like `write_with`, returning a receipt that completes once the peer has
+    /// acknowledged all of it, or fails once it is given up on. Sequenced messages can't have a receipt.
+    pub async fn write_with_receipt(&self, data: &[u8], reliability: Reliability, channel: u8, immediate: bool) -> io::Result<Receipt> {
+        let reliability = reliability.with_ack_receipt().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("write with receipt: {:?} messages have no receipts", reliability))
+        })?;
+        let (tx, rx) = oneshot::channel();
+        let id = self.send(data, reliability, Priority::Medium, channel, immediate, Some(tx)).await?;
+        Ok(Receipt { id, rx })
     }
 
-    #[allow(non_snake_case)]
-    pub async fn ReceivePacket(&self, data: &[u8]) -> Result<Option<PacketT>, String> {
-        if data[0]&PacketBitFlags::ACK as u8 != 0 {
-            self.handle_ack(&data)
-        } else if data[0]&PacketBitFlags::NACK as u8 != 0 {
-            self.handle_nack(&data)
-        } else if data[0]&PacketBitFlags::Datagram as u8 != 0 {
-            self.handle_datagram(&data).await
-        } else {
-            ReadPacket(&data)
+    async fn send(&self, data: &[u8], reliability: Reliability, priority: Priority, channel: u8, immediate: bool, receipt: Option<oneshot::Sender<bool>>) -> io::Result<u32> {
+        trace!(parent: &self.span, id = data.first().copied(), len = data.len(), ?reliability, ?priority, channel, immediate, "writing message");
+        let id = {
+            let mut session = self.session.lock().await;
+            let now = self.clock.now();
+            let id = session.send_with_priority(now, data, reliability, priority, channel)
+                .map_err(|e| io::Error::new(io::ErrorKind::NotConnected, e))?;
+            if let (Some(id), Some(receipt)) = (id, receipt) {
+                // registered before the session lock is released, so dispatch can't miss it.
+                self.receipts.lock().unwrap().insert(id, receipt);
+            }
+            if immediate {
+                session.flush(now);
+            }
+            id.unwrap_or_default()
+        };
+        if !immediate {
+            self.reschedule.notify_one();
         }
+        self.dispatch().await;
+        Ok(id)
+    }
+
+    /// Sends the buffered messages right away.
+    pub async fn flush(&self) -> io::Result<()> {
+        self.session.lock().await.flush(self.clock.now());
+        self.dispatch().await;
+        Ok(())
     }
 
     // if possible move from using box as its slower
     #[allow(non_snake_case)]
     pub async fn WritePacket(&self, packet: Box<&dyn Packet>, immediate: bool) -> Result<Option<Vec<u8>>, tokio::io::Error> {
-        if self.is_server {
-            return Err(tokio::io::Error::new(tokio::io::ErrorKind::Other, "WritePacket: Server cannot send packets, please use WritePacketTo instead."))
-        }
-            // debug logs;
-            println!("WritePacket: {:?}", packet);
-            self.conn.lock().await.send(&packet.serialize()).await?;
-            Ok(None)
+        self.write_with(&packet.serialize(), Reliability::ReliableOrdered, 0, immediate).await?;
+        Ok(None)
     }
+
+    /// Sends an unconnected packet straight to an address, outside of the connection. It can't
+    /// share a datagram with anything, so it is always sent right away.
     #[allow(non_snake_case)]
     pub async fn WritePacketTo(&self, packet: Box<&dyn Packet>, src: SocketAddr, immediate: bool) -> Result<Option<Vec<u8>>, tokio::io::Error> {
-        // debug logs;
-        println!("WritePacketTo: {}:    {:?}", src, packet);
-        self.conn.lock().await.send_to(&packet.serialize(), src).await?;
+        let _ = immediate;
+        self.outbound.send((packet.serialize().into(), src)).await
+            .map_err(|_| io::Error::new(io::ErrorKind::NotConnected, "write packet to: writer stopped"))?;
         Ok(None)
     }
 
-    pub async fn handle_datagram(&self, data: &[u8]) -> Result<Option<PacketT>, String> {
-        let mut window = self.window.lock().await;
-        let sequence_number = read_u24(&data[0..3]);
-        if !window.add(sequence_number) {
-            return Ok(None)
+    /// Closes the connection, notifying the peer.
+    pub async fn close(&self) -> io::Result<()> {
+        self.session.lock().await.close(self.clock.now());
+        self.dispatch().await;
+        Ok(())
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    pub fn round_trip_time(&self) -> Duration {
+        Duration::from_micros(self.round_trip_time.load(Ordering::Relaxed))
+    }
+
+    pub fn effective_mtu(&self) -> u16 {
+        self.effective_mtu.load(Ordering::Relaxed)
+    }
+
+    /// Returns the span the connection logs in, for the tasks of the application that handle it.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Returns the stats of the connection. They are only put together when asked for, so this
+    /// waits for the session like writing does.
+    pub async fn stats(&self) -> Stats {
+        self.session.lock().await.stats()
+    }
+
+    /// Returns the stats of the connection as they were up to a second ago, without waiting for
+    /// the session. They are only refreshed while the connection is ticking.
+    pub fn last_stats(&self) -> Stats {
+        self.last_stats.lock().unwrap().clone()
+    }
+
+    // dispatch passes the events of the session on to ReadPacket, or to the receipt they are about,
+    // and queues every datagram it produced for the writer. The events are passed on before the
+    // session is unlocked, so that callers dispatching at the same time can't reorder them while
+    // one of them waits for room in the writer queue.
+    async fn dispatch(&self) {
+        let transmits = {
+            let mut session = self.session.lock().await;
+            let transmits: Vec<Bytes> = std::iter::from_fn(|| session.poll_transmit()).collect();
+            self.round_trip_time.store(session.round_trip_time().as_micros() as u64, Ordering::Relaxed);
+            self.effective_mtu.store(session.effective_mtu(), Ordering::Relaxed);
+            self.closed.store(session.is_closed(), Ordering::Release);
+            self.forward_events(&mut session);
+            transmits
+        };
+        for datagram in transmits {
+            trace!(parent: &self.span, len = datagram.len(), "sending datagram");
+            // the writer only stops once the conn is dropped.
+            let _ = self.outbound.send((datagram, self.remote)).await;
         }
-        self.ack_slice.lock().await.push(sequence_number);
+    }
 
-        if window.shift() == 0 {
-            let round_trip_time = self.round_trip_time.clone();
-            let missing = match window.missing(Duration::from_millis(round_trip_time.clone().add(round_trip_time.div(2)))) {
-                Ok(missing) => missing,
-                Err(e) => {
-                    return Err(format!("Failed to get missing data from packet queue: {}", e))
-                }
-            };
-            if missing.len() > 0 {
-                match self.send_nack(&missing).await {
-                    Ok(_) => {},
-                    Err(e) => {
-                        return Err(format!("Failed to send nack: {}", e))
+    fn forward_events(&self, session: &mut Session) {
+        let mut events_tx = self.events_tx.lock().unwrap();
+        while let Some(event) = session.poll_event() {
+            match &event {
+                Event::Connected => {
+                    if let Some(guid) = session.peer_guid() {
+                        self.span.record("guid", guid);
                     }
+                    debug!(parent: &self.span, "connected");
                 }
+                Event::Message(data) => trace!(parent: &self.span, id = data[0], len = data.len(), "received message"),
+                Event::Disconnected(reason) => debug!(parent: &self.span, ?reason, "disconnected"),
+                Event::Delivered(_) | Event::Lost(_) => {}
+            }
+            if let Event::Delivered(id) | Event::Lost(id) = event {
+                if let Some(receipt) = self.receipts.lock().unwrap().remove(&id) {
+                    let _ = receipt.send(matches!(event, Event::Delivered(_)));
+                }
+                continue;
+            }
+            let closed = matches!(event, Event::Disconnected(_));
+            if let Some(tx) = events_tx.as_ref() {
+                let _ = tx.send(event);
+            }
+            if closed {
+                events_tx.take();
+                self.wake.notify_waiters();
             }
         }
-        if self.window.lock().await.len() > MAX_WINDOW_SIZE as usize && self.limits_enabled {
-            return Err(format!("receive datagram: queue window size is too big ({}->{})", window.lowest, window.highest).to_string())
-        }
+    }
+}
 
-        Box::pin(self.handle_datagram(&data[3..])).await
+/// Receipt completes once every datagram carrying the message it was returned for was
+/// acknowledged by the peer, or with an error if the message was lost or the connection closed first.
+pub struct Receipt {
+    id: u32,
+    rx: oneshot::Receiver<bool>,
+}
+
+impl Receipt {
+    pub fn id(&self) -> u32 {
+        self.id
     }
+}
 
+impl Future for Receipt {
+    type Output = io::Result<()>;
 
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let id = self.id;
+        Pin::new(&mut self.rx).poll(cx).map(|delivered| match delivered {
+            Ok(true) => Ok(()),
+            // the sender is dropped with the conn, so it never got delivered either.
+            Ok(false) | Err(_) => Err(io::Error::other(format!("receipt {}: message was not delivered", id))),
+        })
+    }
 }
 
+// Conn is shared between tasks, which must keep compiling.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Conn>();
+};
+
+fn disconnect_error(reason: DisconnectReason) -> io::Error {
+    let kind = match reason {
+        DisconnectReason::TimedOut | DisconnectReason::HandshakeFailed => io::ErrorKind::TimedOut,
+        DisconnectReason::IncompatibleProtocol => io::ErrorKind::ConnectionRefused,
+        DisconnectReason::Closed | DisconnectReason::PeerClosed => io::ErrorKind::ConnectionAborted,
+    };
+    io::Error::new(kind, format!("connection closed: {:?}", reason))
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use if_addrs::IfAddr;
+use tokio::net::UdpSocket;
+use tokio::time::timeout_at;
+use tracing::warn;
+use crate::messages::unconnected_ping::UnconnectedPing;
+use crate::messages::unconnected_pong::UnconnectedPong;
+use crate::query::Status;
+use crate::transport::DatagramTransport;
+use crate::types::Packet;
+use crate::{PacketT, ReadPacket};
+
+/// Port Bedrock clients broadcast their unconnected pings to over IPv4.
+pub const LAN_PORT_V4: u16 = 19132;
+/// Port Bedrock clients multicast their unconnected pings to over IPv6.
+pub const LAN_PORT_V6: u16 = 19133;
+
+// link-local all-nodes multicast group, the IPv6 counterpart of a broadcast.
+const ALL_NODES_V6: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+// how often pings are repeated during a discovery window, in case one got lost.
+const REBROADCAST_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// DiscoveredServer is a server that answered a broadcast ping during `discover`.
+#[derive(Debug, Clone)]
+pub struct DiscoveredServer {
+    pub address: SocketAddr,
+    pub server_guid: u64,
+    pub status: Status,
+    pub latency: Duration,
+}
+
+/// Discovers servers on the local network the way the Bedrock client does: unconnected pings are
+/// broadcast on every IPv4 interface and multicast to the all-nodes group of every IPv6 interface
+/// for the whole `window`. Replies are deduplicated by server GUID, so a server reachable over
+/// several interfaces or both address families is only returned once.
+pub async fn discover(window: Duration) -> io::Result<Vec<DiscoveredServer>> {
+    let v4 = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    v4.set_broadcast(true)?;
+    // not every host has IPv6, discovery over IPv4 alone is still useful.
+    let v6 = UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0)).await.ok().map(|v6| Arc::new(v6) as Arc<dyn DatagramTransport>);
+    discover_on(Arc::new(v4), v6, &broadcast_targets()?, window).await
+}
+
+/// Discovers servers like `discover`, sending the pings to `targets` over the transport of the
+/// same address family and taking the replies from either transport.
+pub async fn discover_on(v4: Arc<dyn DatagramTransport>, v6: Option<Arc<dyn DatagramTransport>>, targets: &[SocketAddr], window: Duration) -> io::Result<Vec<DiscoveredServer>> {
+    let start = Instant::now();
+    let deadline = start + window;
+    let guid: u64 = rand::random();
+
+    let mut found: HashMap<u64, DiscoveredServer> = HashMap::new();
+    let mut buf_v4 = [0u8; 1492];
+    let mut buf_v6 = [0u8; 1492];
+    let mut next_broadcast = start;
+    while Instant::now() < deadline {
+        if Instant::now() >= next_broadcast {
+            let ping = UnconnectedPing { client_send_time_be: start.elapsed().as_micros() as u64, client_guid_be: guid }.serialize();
+            for target in targets {
+                // sending fails on interfaces that are down or lack a route, that shouldn't stop the others.
+                let _ = match (target, &v6) {
+                    (SocketAddr::V4(_), _) => v4.send_to(&ping, *target).await,
+                    (SocketAddr::V6(_), Some(v6)) => v6.send_to(&ping, *target).await,
+                    (SocketAddr::V6(_), None) => continue,
+                };
+            }
+            next_broadcast += REBROADCAST_INTERVAL;
+        }
+
+        let wait_until = next_broadcast.min(deadline);
+        let received = timeout_at(wait_until.into(), async {
+            match &v6 {
+                Some(v6) => tokio::select! {
+                    res = v4.recv_from(&mut buf_v4) => res.map(|(len, src)| (&buf_v4[..len], src)),
+                    res = v6.recv_from(&mut buf_v6) => res.map(|(len, src)| (&buf_v6[..len], src)),
+                },
+                None => v4.recv_from(&mut buf_v4).await.map(|(len, src)| (&buf_v4[..len], src)),
+            }
+        }).await;
+        let (data, src) = match received {
+            Ok(Ok(received)) => received,
+            // ICMP errors from interfaces without listeners show up as receive errors, skip them.
+            Ok(Err(_)) | Err(_) => continue,
+        };
+        if let Some(pong) = read_pong(data) {
+            let sent = Duration::from_micros(pong.client_send_time_be);
+            let latency = start.elapsed().checked_sub(sent).unwrap_or_default();
+            if let Ok(status) = pong.data.parse::<Status>() {
+                // a server answering through `advertise` listens on the port from its status, not the LAN port.
+                let port = if src.is_ipv4() { status.port_v4 } else { status.port_v6 };
+                found.entry(pong.server_guid_be).or_insert(DiscoveredServer {
+                    address: SocketAddr::new(src.ip(), port.unwrap_or(src.port())),
+                    server_guid: pong.server_guid_be,
+                    status,
+                    latency,
+                });
+            }
+        }
+    }
+
+    let mut servers: Vec<DiscoveredServer> = found.into_values().collect();
+    servers.sort_by_key(|server| server.address);
+    Ok(servers)
+}
+
+/// Answers unconnected pings sent to the LAN ports with `pong`, so the server shows up in the
+/// friends tab of clients on the same network. This is only needed when the server's own socket
+/// doesn't receive broadcasts, i.e. when it is bound to a specific address or a port other than
+/// `LAN_PORT_V4`. The pong should advertise the port the server actually listens on.
+/// Only returns if the LAN ports can't be bound or receiving on them fails.
+pub async fn advertise(server_guid: u64, pong: String) -> io::Result<()> {
+    let v4 = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, LAN_PORT_V4)).await?;
+    let v6 = UdpSocket::bind((Ipv6Addr::UNSPECIFIED, LAN_PORT_V6)).await.ok();
+
+    let answer_v6 = async {
+        match v6 {
+            Some(v6) => advertise_on(Arc::new(v6), server_guid, pong.clone()).await,
+            None => Ok(()),
+        }
+    };
+    let (v4, v6) = tokio::join!(advertise_on(Arc::new(v4), server_guid, pong.clone()), answer_v6);
+    v4.and(v6)
+}
+
+/// Answers the unconnected pings `transport` receives with `pong`, see `advertise`. Failed sends
+/// and resets are logged and don't stop it: on Windows, an ICMP port unreachable for an earlier
+/// pong shows up as a reset when receiving the next datagram. Any other error receiving is returned.
+pub async fn advertise_on(transport: Arc<dyn DatagramTransport>, server_guid: u64, pong: String) -> io::Result<()> {
+    let mut buf = [0u8; 1492];
+    loop {
+        let (data, src) = match transport.recv_from(&mut buf).await {
+            Ok((len, src)) => (&buf[..len], src),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionReset => {
+                warn!(error = %e, "receiving LAN pings failed");
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        if data.is_empty() {
+            continue;
+        }
+        if let Ok(Some(PacketT::UnconnectedPing(ping))) = ReadPacket(data) {
+            let response = UnconnectedPong { client_send_time_be: ping.client_send_time_be, server_guid_be: server_guid, data: pong.clone() };
+            if let Err(e) = transport.send_to(&response.serialize(), src).await {
+                warn!(%src, error = %e, "answering LAN ping failed");
+            }
+        }
+    }
+}
+
+fn read_pong(data: &[u8]) -> Option<UnconnectedPong> {
+    if data.is_empty() {
+        return None;
+    }
+    match ReadPacket(data) {
+        Ok(Some(PacketT::UnconnectedPong(pong))) => Some(pong),
+        _ => None,
+    }
+}
+
+// broadcast_targets lists the addresses pings are sent to: the broadcast address of every IPv4
+// interface plus the limited broadcast address, and the all-nodes group scoped to every IPv6 interface.
+fn broadcast_targets() -> io::Result<Vec<SocketAddr>> {
+    let mut targets = vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), LAN_PORT_V4)];
+    for interface in if_addrs::get_if_addrs()? {
+        match interface.addr {
+            IfAddr::V4(addr) => {
+                if let Some(broadcast) = addr.broadcast {
+                    targets.push(SocketAddr::new(IpAddr::V4(broadcast), LAN_PORT_V4));
+                }
+            }
+            IfAddr::V6(_) if interface.is_loopback() => {}
+            IfAddr::V6(_) => {
+                if let Some(index) = interface.index {
+                    targets.push(SocketAddr::V6(SocketAddrV6::new(ALL_NODES_V6, LAN_PORT_V6, 0, index)));
+                }
+            }
+        }
+    }
+    targets.sort();
+    targets.dedup();
+    Ok(targets)
+}
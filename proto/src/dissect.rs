@@ -0,0 +1,390 @@
+use std::fmt::{self, Display, Formatter};
+use std::net::SocketAddr;
+use std::ops::Range;
+use crate::address::{AddrType, Address};
+use crate::packet::{Packet as Frame, PacketBitFlags};
+use crate::types::{json_string, Hex, PacketId, U24};
+use crate::{PacketT, ReadPacket};
+
+// record types used in acknowledgements, see ack.rs.
+const RECORD_RANGE: u8 = 0;
+const RECORD_SINGLE: u8 = 1;
+
+/// Tree is an annotated decode of a datagram: every node labels a field with its decoded value
+/// and the bytes it was decoded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tree {
+    pub label: String,
+    pub value: String,
+    pub range: Option<Range<usize>>,
+    pub children: Vec<Tree>,
+}
+
+impl Tree {
+    fn new(label: &str, value: impl Display, range: Range<usize>) -> Self {
+        Tree { label: label.to_string(), value: value.to_string(), range: Some(range), children: Vec::new() }
+    }
+
+    fn error(e: impl Display, range: Range<usize>) -> Self {
+        Tree::new("error", e, range)
+    }
+
+    /// Returns the first node labelled `label`, searching depth first.
+    pub fn find(&self, label: &str) -> Option<&Tree> {
+        if self.label == label {
+            return Some(self);
+        }
+        self.children.iter().find_map(|child| child.find(label))
+    }
+
+    /// Encodes the tree as a JSON object with the fields `label`, `value`, `start` and `end` if it
+    /// has a range, and `children` if it has any.
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"label\":{},\"value\":{}", json_string(&self.label), json_string(&self.value));
+        if let Some(range) = &self.range {
+            json.push_str(&format!(",\"start\":{},\"end\":{}", range.start, range.end));
+        }
+        if !self.children.is_empty() {
+            let children: Vec<String> = self.children.iter().map(Tree::to_json).collect();
+            json.push_str(&format!(",\"children\":[{}]", children.join(",")));
+        }
+        json.push('}');
+        json
+    }
+
+    fn fmt_indented(&self, f: &mut Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(f, "{:indent$}{}", "", self.label, indent = depth * 2)?;
+        if !self.value.is_empty() {
+            write!(f, ": {}", self.value)?;
+        }
+        if let Some(range) = &self.range {
+            write!(f, "  [{}..{}]", range.start, range.end)?;
+        }
+        writeln!(f)?;
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// Displays the tree indented by two spaces a level, a line per field, with the range of bytes
+/// of each field at the end of its line.
+impl Display for Tree {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Decodes a datagram the way a session would, labelling every field instead of acting on it:
+/// the datagram flags, then the ACK/NACK records or the sequence number and frames of a
+/// connected datagram, or the message of an offline datagram. Decoding never fails: bytes that
+/// can't be decoded end up in an `error` node, after everything that could be.
+pub fn dissect(data: &[u8]) -> Tree {
+    let mut tree = Tree::new("datagram", format!("{} bytes", data.len()), 0..data.len());
+    if data.is_empty() {
+        tree.children.push(Tree::error("empty datagram", 0..0));
+        return tree;
+    }
+    if data[0] & PacketBitFlags::Datagram as u8 == 0 {
+        tree.value = format!("offline, {} bytes", data.len());
+        tree.children.push(message(data, 0));
+        return tree;
+    }
+
+    tree.children.push(Tree::new("flags", flags(data[0]), 0..1));
+    if data[0] & (PacketBitFlags::ACK as u8 | PacketBitFlags::NACK as u8) != 0 {
+        tree.children.extend(acknowledgement(data));
+        return tree;
+    }
+    if data.len() < 4 {
+        tree.children.push(Tree::error("missing sequence number", 1..data.len()));
+        return tree;
+    }
+    tree.children.push(Tree::new("sequence number", U24::read(&data[1..4]), 1..4));
+    let mut offset = 4;
+    let mut n = 0;
+    while offset < data.len() {
+        match frame(data, offset, n) {
+            Ok((node, size)) => {
+                tree.children.push(node);
+                offset += size;
+                n += 1;
+            }
+            Err(node) => {
+                tree.children.push(node);
+                break;
+            }
+        }
+    }
+    tree
+}
+
+// flags names the flags set in the first byte of a datagram.
+fn flags(b: u8) -> String {
+    let names: Vec<&str> = [
+        PacketBitFlags::Datagram,
+        PacketBitFlags::ACK,
+        PacketBitFlags::NACK,
+        PacketBitFlags::NeedsBAndAS,
+    ].iter()
+        .filter(|flag| b & **flag as u8 != 0)
+        .map(|flag| match flag {
+            PacketBitFlags::Datagram => "Datagram",
+            PacketBitFlags::ACK => "ACK",
+            PacketBitFlags::NACK => "NACK",
+            PacketBitFlags::NeedsBAndAS => "NeedsBAndAS",
+        })
+        .collect();
+    format!("0x{:02x} {}", b, names.join("|"))
+}
+
+// acknowledgement decodes the records of an ACK or NACK, like `read_acknowledgement`, keeping
+// the bytes of every record.
+fn acknowledgement(data: &[u8]) -> Vec<Tree> {
+    if data.len() < 3 {
+        return vec![Tree::error("missing record count", 1..data.len())];
+    }
+    let count = u16::from_be_bytes([data[1], data[2]]);
+    let mut nodes = vec![Tree::new("record count", count, 1..3)];
+    let mut offset = 3;
+    for i in 0..count {
+        let (value, size) = match data.get(offset) {
+            Some(&RECORD_SINGLE) if data.len() >= offset + 4 => (format!("single {}", U24::read(&data[offset + 1..])), 4),
+            Some(&RECORD_RANGE) if data.len() >= offset + 7 => {
+                let start = U24::read(&data[offset + 1..]);
+                let end = U24::read(&data[offset + 4..]);
+                (format!("range {}-{}", start, end), 7)
+            }
+            Some(&RECORD_SINGLE | &RECORD_RANGE) | None => {
+                nodes.push(Tree::error("record is truncated", offset..data.len()));
+                return nodes;
+            }
+            Some(record_type) => {
+                nodes.push(Tree::error(format!("unknown record type {}", record_type), offset..data.len()));
+                return nodes;
+            }
+        };
+        nodes.push(Tree::new(&format!("record {}", i), value, offset..offset + size));
+        offset += size;
+    }
+    if offset < data.len() {
+        nodes.push(Tree::error(format!("{} bytes after the records", data.len() - offset), offset..data.len()));
+    }
+    nodes
+}
+
+// frame decodes the frame starting at `offset`, returning it with its size, or an error node
+// covering the rest of the datagram if it can't be decoded.
+fn frame(data: &[u8], offset: usize, n: usize) -> Result<(Tree, usize), Tree> {
+    let (frame, size) = Frame::read(&data[offset..]).map_err(|e| Tree::error(e, offset..data.len()))?;
+    let mut node = Tree::new(&format!("frame {}", n), format!("{:?}", frame.reliability), offset..offset + size);
+    let header = data[offset];
+    let split = if frame.split { " split" } else { "" };
+    node.children.push(Tree::new("header", format!("0x{:02x} {:?}{}", header, frame.reliability, split), offset..offset + 1));
+    // the length as it was sent, in bits, which may not match the bytes decoded.
+    let bits = u16::from_be_bytes([data[offset + 1], data[offset + 2]]);
+    node.children.push(Tree::new("length", format!("{} bits, {} bytes", bits, frame.data.len()), offset + 1..offset + 3));
+
+    let mut at = offset + 3;
+    let mut field = |label: &str, value: String, len: usize| {
+        node.children.push(Tree::new(label, value, at..at + len));
+        at += len;
+    };
+    if frame.reliable() {
+        field("message index", frame.message_index.to_string(), 3);
+    }
+    if frame.sequenced() {
+        field("sequence index", frame.sequence_index.to_string(), 3);
+    }
+    if frame.sequenced_or_ordered() {
+        field("order index", frame.order_index.to_string(), 3);
+        field("order channel", frame.order_channel.to_string(), 1);
+    }
+    if frame.split {
+        field("split count", frame.split_count.to_string(), 4);
+        field("split id", frame.split_id.to_string(), 2);
+        field("split index", frame.split_index.to_string(), 4);
+    }
+    if frame.split {
+        // only the whole message can be decoded, once every fragment arrived.
+        node.children.push(Tree::new("fragment", format!("{} bytes: {}", frame.data.len(), Hex(&frame.data)), at..offset + size));
+    } else {
+        node.children.push(message(&data[..offset + size], at));
+    }
+    Ok((node, size))
+}
+
+// message decodes the message in `data[offset..]`, with the fields of the messages this crate
+// knows.
+fn message(data: &[u8], offset: usize) -> Tree {
+    let body = &data[offset..];
+    let id = PacketId::from(body[0]);
+    let mut node = Tree::new("message", format!("{:?}", id), offset..data.len());
+    node.children.push(Tree::new("id", format!("0x{:02x} {:?}", body[0], id), offset..offset + 1));
+    let payload = |node: &mut Tree| {
+        node.children.push(Tree::new("payload", format!("{} bytes: {}", body.len() - 1, Hex(&body[1..])), offset + 1..data.len()));
+    };
+    match ReadPacket(body) {
+        Ok(Some(PacketT::Unknown(_))) | Ok(None) => payload(&mut node),
+        Ok(Some(packet)) => node.children.extend(fields(&packet, data, offset + 1)),
+        Err(e) => {
+            payload(&mut node);
+            node.children.push(Tree::error(e, offset + 1..data.len()));
+        }
+    }
+    node
+}
+
+// Fields labels the fields of a message one after the other, each with the bytes it takes up.
+struct Fields<'a> {
+    data: &'a [u8],
+    at: usize,
+    nodes: Vec<Tree>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(data: &'a [u8], at: usize) -> Self {
+        Fields { data, at, nodes: Vec::new() }
+    }
+
+    // bytes returns the next `len` bytes, or as many as there are.
+    fn bytes(&self, len: usize) -> &'a [u8] {
+        let start = self.at.min(self.data.len());
+        &self.data[start..(self.at + len).min(self.data.len())]
+    }
+
+    fn field(&mut self, label: &str, value: impl Display, len: usize) {
+        let end = (self.at + len).min(self.data.len()).max(self.at);
+        self.nodes.push(Tree::new(label, value, self.at..end));
+        self.at = end;
+    }
+
+    fn magic(&mut self) {
+        self.field("magic", Hex(self.bytes(16)), 16);
+    }
+
+    // address labels an address encoded like `write_addr` does, with a node for each of its parts.
+    fn address(&mut self, label: &str, address: &Address) {
+        let start = self.at;
+        let mut parts = Fields::new(self.data, start);
+        parts.field("version", parts.bytes(1).first().copied().unwrap_or_default(), 1);
+        let socket_addr = SocketAddr::from(address);
+        if address.addr_type == AddrType::IPv6 {
+            let family = parts.bytes(2).try_into().map(u16::from_le_bytes).unwrap_or_default();
+            parts.field("family", family, 2);
+            parts.field("port", address.port, 2);
+            parts.field("flow info", Hex(parts.bytes(4)), 4);
+            parts.field("ip", socket_addr.ip(), 16);
+            parts.field("scope id", Hex(parts.bytes(4)), 4);
+        } else {
+            // the bits of IPv4 addresses are flipped on the wire.
+            parts.field("ip", socket_addr.ip(), 4);
+            parts.field("port", address.port, 2);
+        }
+        self.at = parts.at;
+        self.nodes.push(Tree { label: label.to_string(), value: socket_addr.to_string(), range: Some(start..parts.at), children: parts.nodes });
+    }
+
+    fn system_addresses(&mut self, addresses: &[Address]) {
+        let start = self.at;
+        let mut list = Fields::new(self.data, start);
+        for (i, address) in addresses.iter().enumerate() {
+            list.address(&format!("system address {}", i), address);
+        }
+        self.at = list.at;
+        self.nodes.push(Tree { label: "system_addresses".to_string(), value: format!("{} addresses", addresses.len()), range: Some(start..list.at), children: list.nodes });
+    }
+}
+
+// fields labels the fields of a message decoded by ReadPacket from `data[offset - 1..]`, reading
+// their offsets the way the message was encoded. Bytes after the last field are labelled too, as
+// they are ignored when decoding.
+fn fields(packet: &PacketT, data: &[u8], offset: usize) -> Vec<Tree> {
+    let mut f = Fields::new(data, offset);
+    match packet {
+        PacketT::ConnectedPing(p) => f.field("client_send_time_be", p.client_send_time_be, 8),
+        PacketT::ConnectedPong(p) => {
+            f.field("client_send_time_be", p.client_send_time_be, 8);
+            // some implementations leave it out.
+            if f.bytes(8).len() == 8 {
+                f.field("server_send_time_be", p.server_send_time_be, 8);
+            }
+        }
+        PacketT::UnconnectedPing(p) => {
+            f.field("client_send_time_be", p.client_send_time_be, 8);
+            f.magic();
+            f.field("client_guid_be", p.client_guid_be, 8);
+        }
+        PacketT::UnconnectedPong(p) => {
+            f.field("client_send_time_be", p.client_send_time_be, 8);
+            f.field("server_guid_be", p.server_guid_be, 8);
+            f.magic();
+            let len = f.bytes(2).try_into().map(u16::from_be_bytes).unwrap_or_default();
+            f.field("data length", len, 2);
+            f.field("data", format!("{:?}", p.data), len as usize);
+        }
+        PacketT::OpenConnectionRequest1(p) => {
+            f.magic();
+            f.field("client_protocol", p.client_protocol, 1);
+            // the request is padded to the MTU, which is all the padding tells.
+            let padding = data.len().saturating_sub(f.at);
+            f.field("max_transmission_unit", format!("{} ({} bytes of padding)", p.max_transmission_unit, padding), padding);
+        }
+        PacketT::OpenConnectionRequest2(p) => {
+            f.magic();
+            if p.server_has_security {
+                f.field("cookie", p.cookie, 4);
+                f.field("client supports security", f.bytes(1).first().is_some_and(|&b| b != 0), 1);
+            }
+            f.address("server_address", &p.server_address);
+            f.field("max_transmission_unit", p.max_transmission_unit, 2);
+            f.field("client_guid", p.client_guid, 8);
+        }
+        PacketT::OpenConnectionReply1(p) => {
+            f.magic();
+            f.field("server_guid_be", p.server_guid_be, 8);
+            f.field("server_has_security", p.server_has_security, 1);
+            if p.server_has_security {
+                f.field("cookie", p.cookie, 4);
+            }
+            f.field("max_transmission_unit_be", p.max_transmission_unit_be, 2);
+        }
+        PacketT::OpenConnectionReply2(p) => {
+            f.magic();
+            f.field("server_guid_be", p.server_guid_be, 8);
+            f.address("client_address", &p.client_address);
+            f.field("max_transmission_unit_be", p.max_transmission_unit_be, 2);
+            f.field("do_security", p.do_security, 1);
+        }
+        PacketT::IncompatibleProtocolVersion(p) => {
+            f.field("server_protocol", p.server_protocol, 1);
+            f.magic();
+            f.field("server_guid_be", p.server_guid_be, 8);
+        }
+        PacketT::ConnectionRequest(p) => {
+            f.field("client_guid_be", p.client_guid_be, 8);
+            f.field("request_time_be", p.request_time_be, 8);
+            f.field("secure", p.secure, 1);
+        }
+        PacketT::ConnectionRequestAccepted(p) => {
+            f.address("client_address", &p.client_address);
+            f.field("system_index", p.system_index, 2);
+            f.system_addresses(&p.system_addresses);
+            f.field("request_time_be", p.request_time_be, 8);
+            f.field("accepted_time_be", p.accepted_time_be, 8);
+        }
+        PacketT::NewIncomingConnection(p) => {
+            f.address("server_address", &p.server_address);
+            f.system_addresses(&p.system_addresses);
+            f.field("request_time_be", p.request_time_be, 8);
+            f.field("accepted_time_be", p.accepted_time_be, 8);
+        }
+        PacketT::DisconnectNotification(_) | PacketT::Unknown(_) => {}
+    }
+    if f.at < data.len() {
+        let rest = f.bytes(data.len() - f.at);
+        f.field("trailing bytes", format!("{} bytes: {}", rest.len(), Hex(rest)), rest.len());
+    }
+    f.nodes
+}
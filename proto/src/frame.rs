@@ -1,77 +1,169 @@
-use std::cmp::max;
-use std::collections::HashMap;
-use crate::types::uint24;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use crate::types::U24;
+use crate::MAX_WINDOW_SIZE;
 
+/// WINDOW_SIZE is the amount of sequence numbers a `Window` tracks past the lowest one it has not
+/// received yet. It divides 2^24, so every sequence number maps to the same slot of the ring no
+/// matter how often the numbers wrapped.
+pub const WINDOW_SIZE: usize = MAX_WINDOW_SIZE as usize;
+const WORDS: usize = WINDOW_SIZE / 64;
+
+/// Window keeps track of the datagram sequence numbers received, to drop duplicates and find the
+/// datagrams that went missing. It is a ring of bits covering the sequence numbers from `lowest`,
+/// the first one not received yet, up to `lowest + WINDOW_SIZE`.
 pub struct Window {
-    pub lowest: uint24,
-    pub highest: uint24,
-    pub queue: HashMap<uint24, std::time::SystemTime>,
+    lowest: U24,
+    // one past the highest sequence number received.
+    highest: U24,
+    received: [u64; WORDS],
+    // missing sequence numbers that were already reported by `missing`.
+    nacked: [u64; WORDS],
+    // when the gaps below each sequence number were noticed: a gap at some index was noticed at
+    // the time of the first entry past it. Entries are pushed in order of sequence number.
+    gaps: VecDeque<(U24, Instant)>,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Window {
     pub fn new() -> Self {
-        Self {highest: 0, lowest: 0, queue: HashMap::new()}
+        Self::starting_at(U24::default())
     }
 
-    pub fn add(&mut self, index: uint24) -> bool {
-        if self.seen(index) {
-            return false
+    /// Creates a window in which `lowest` is the first sequence number expected.
+    pub fn starting_at(lowest: U24) -> Self {
+        Self {
+            lowest,
+            highest: lowest,
+            received: [0; WORDS],
+            nacked: [0; WORDS],
+            gaps: VecDeque::new(),
         }
-        self.highest = max(index+1, self.highest);
-        self.queue.insert(index, std::time::SystemTime::now());
-        true
     }
 
-    pub fn seen(&self, index: uint24) -> bool {
+    /// Returns the lowest sequence number that has not been received yet.
+    pub fn lowest(&self) -> U24 {
+        self.lowest
+    }
+
+    /// Returns one past the highest sequence number received.
+    pub fn highest(&self) -> U24 {
+        self.highest
+    }
+
+    /// Marks a sequence number as received. False is returned if it was received before, and an
+    /// error if it is too far ahead of the lowest missing sequence number to be tracked.
+    pub fn add(&mut self, index: U24, now: Instant) -> Result<bool, String> {
         if index < self.lowest {
-            return true
+            return Ok(false);
         }
-        self.queue.contains_key(&index)
+        if self.lowest.distance(index) as usize >= WINDOW_SIZE {
+            self.skip_reported(index);
+            if self.lowest.distance(index) as usize >= WINDOW_SIZE {
+                return Err(format!("window: sequence number {} is too far ahead of {}", index, self.lowest));
+            }
+        }
+        let (word, bit) = slot(index);
+        if self.received[word] & bit != 0 {
+            return Ok(false);
+        }
+        self.received[word] |= bit;
+        if index >= self.highest {
+            if index > self.highest {
+                self.gaps.push_back((index, now));
+            }
+            self.highest = index.next();
+        }
+        Ok(true)
     }
 
+    /// Reports whether a sequence number was received before.
+    pub fn seen(&self, index: U24) -> bool {
+        if index < self.lowest {
+            return true;
+        }
+        if self.lowest.distance(index) as usize >= WINDOW_SIZE {
+            return false;
+        }
+        let (word, bit) = slot(index);
+        self.received[word] & bit != 0
+    }
+
+    /// Moves the window past every sequence number received without gaps, returning how far it moved.
     pub fn shift(&mut self) -> usize {
-        let mut index: uint24 = self.lowest;
         let mut n = 0;
-        while index < self.highest {
-            index += 1;
-            if !self.queue.contains_key(&index) {
-                break
+        while self.lowest < self.highest {
+            let (word, bit) = slot(self.lowest);
+            if self.received[word] & bit == 0 {
+                break;
             }
-            self.queue.remove(&index);
+            self.received[word] &= !bit;
+            self.nacked[word] &= !bit;
+            self.lowest = self.lowest.next();
             n += 1;
         }
-        self.lowest = index;
+        while self.gaps.front().is_some_and(|(end, _)| *end <= self.lowest) {
+            self.gaps.pop_front();
+        }
         n
     }
 
-    pub fn missing(&mut self, since: std::time::Duration) -> Result<Vec<uint24>, Box<dyn std::error::Error>> {
-        let mut missing = false;
-        let mut indecies: Vec<uint24> = Vec::new();
-        let mut index = self.highest as isize - 1;
-        while index >= self.lowest as isize {
-            index -= 1;
-            let i = index as uint24;
-            match self.queue.get_key_value(&i) {
-                Some((_, v)) => {
-                    let prev = std::time::SystemTime::now().duration_since(*v)?;
-                    if prev >= since {
-                        missing = true;
-                    }
-                    continue
-                },
-                _ => {
-                    if missing {
-                        indecies.push(i);
-                        self.queue.remove(&i);
-                    }
-                },
+    // skip_reported gives up on the lowest sequence numbers that were reported missing, as far as
+    // needed to make room for `index`. The sender resends their frames in new datagrams, so they
+    // never arrive and would hold the window back for good.
+    fn skip_reported(&mut self, index: U24) {
+        while self.lowest < self.highest && self.lowest.distance(index) as usize >= WINDOW_SIZE {
+            let (word, bit) = slot(self.lowest);
+            if (self.received[word] | self.nacked[word]) & bit == 0 {
+                break;
             }
+            self.received[word] &= !bit;
+            self.nacked[word] &= !bit;
+            self.lowest = self.lowest.next();
         }
         self.shift();
-        Ok(indecies)
     }
 
+    /// Returns the sequence numbers that have been missing for at least `delay`, in order. Every
+    /// missing sequence number is only returned once: if it stays missing, the sender resends it
+    /// by itself after a while.
+    pub fn missing(&mut self, delay: Duration, now: Instant) -> Vec<U24> {
+        let mut missing = Vec::new();
+        let mut index = self.lowest;
+        for &(end, noticed) in &self.gaps {
+            if now.saturating_duration_since(noticed) < delay {
+                // gaps noticed later than this one are even younger.
+                break;
+            }
+            while index < end {
+                let (word, bit) = slot(index);
+                if (self.received[word] | self.nacked[word]) & bit == 0 {
+                    self.nacked[word] |= bit;
+                    missing.push(index);
+                }
+                index = index.next();
+            }
+        }
+        missing
+    }
+
+    /// Returns the amount of sequence numbers from the lowest missing to the highest received one.
     pub fn len(&self) -> usize {
-        (self.highest - self.lowest) as usize
+        self.lowest.distance(self.highest) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.highest == self.lowest
     }
-}
\ No newline at end of file
+}
+
+// slot returns the word and bit of a sequence number in the ring.
+fn slot(index: U24) -> (usize, u64) {
+    let slot = index.get() as usize % WINDOW_SIZE;
+    (slot / 64, 1 << (slot % 64))
+}
@@ -0,0 +1,214 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::io::ReadBuf;
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Instant};
+use crate::transport::DatagramTransport;
+
+/// Impairment describes how badly an `ImpairedTransport` treats the datagrams sent through it.
+/// The default impairs nothing. Every random decision comes from `seed`, so a run that fails can
+/// be repeated with the same losses, as long as the same datagrams are sent.
+#[derive(Debug, Clone)]
+pub struct Impairment {
+    pub seed: u64,
+    /// Chance of a datagram being dropped, between 0 and 1.
+    pub loss: f64,
+    /// Drops datagrams in bursts instead of independently, see `BurstLoss`.
+    pub burst_loss: Option<BurstLoss>,
+    /// Chance of a datagram being held back by `reorder_delay`, so that later ones overtake it.
+    pub reorder: f64,
+    pub reorder_delay: Duration,
+    /// Chance of a datagram being delivered twice.
+    pub duplicate: f64,
+    /// Fixed delay added to every datagram.
+    pub delay: Duration,
+    /// Random delay of up to this long added on top of `delay`.
+    pub jitter: Duration,
+    /// Bytes per second the link can carry. Datagrams queue up behind each other once it is full.
+    pub bandwidth: Option<u64>,
+    /// Datagrams larger than this are dropped without notice, like on a path that blackholes
+    /// packets over its MTU instead of sending ICMP errors.
+    pub mtu: Option<usize>,
+}
+
+impl Default for Impairment {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            loss: 0.0,
+            burst_loss: None,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(20),
+            duplicate: 0.0,
+            delay: Duration::ZERO,
+            jitter: Duration::ZERO,
+            bandwidth: None,
+            mtu: None,
+        }
+    }
+}
+
+/// BurstLoss is a Gilbert-Elliott loss model: the link flips between a good state, in which
+/// datagrams get through, and a bad state in which all of them are dropped.
+#[derive(Debug, Copy, Clone)]
+pub struct BurstLoss {
+    /// Chance of going from the good to the bad state with every datagram.
+    pub enter: f64,
+    /// Chance of going back from the bad to the good state with every datagram.
+    pub exit: f64,
+}
+
+/// ImpairmentStats counts what an `ImpairedTransport` did to the datagrams sent through it.
+#[derive(Debug, Default)]
+pub struct ImpairmentStats {
+    pub sent: AtomicU64,
+    pub dropped: AtomicU64,
+    pub duplicated: AtomicU64,
+    pub reordered: AtomicU64,
+}
+
+/// ImpairedTransport wraps a transport and makes it behave like a bad network, for testing how
+/// connections cope with loss, reordering and the like without root or `tc netem`. Only datagrams
+/// sent through it are impaired: wrap the transports of both ends to impair both directions.
+/// Delayed datagrams are sent by a tokio task, so it must be created inside a runtime.
+pub struct ImpairedTransport {
+    inner: Arc<dyn DatagramTransport>,
+    impairment: Impairment,
+    state: Mutex<ImpairState>,
+    queue: mpsc::UnboundedSender<Delayed>,
+    pub stats: Arc<ImpairmentStats>,
+}
+
+struct ImpairState {
+    rng: StdRng,
+    in_burst: bool,
+    // time at which the link has sent everything queued so far, used for the bandwidth cap.
+    link_free_at: Instant,
+    sequence: u64,
+}
+
+// Delayed is a datagram waiting to be sent. They are sent by time, and in the order they were
+// queued if the time is equal, so equal delays don't reorder anything.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Delayed {
+    at: Instant,
+    sequence: u64,
+    target: SocketAddr,
+    data: Vec<u8>,
+}
+
+impl ImpairedTransport {
+    pub fn new(inner: Arc<dyn DatagramTransport>, impairment: Impairment) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(deliver(inner.clone(), rx));
+        Self {
+            inner,
+            state: Mutex::new(ImpairState {
+                rng: StdRng::seed_from_u64(impairment.seed),
+                in_burst: false,
+                link_free_at: Instant::now(),
+                sequence: 0,
+            }),
+            impairment,
+            queue: tx,
+            stats: Arc::new(ImpairmentStats::default()),
+        }
+    }
+
+    // impair decides the fate of a datagram, queueing it zero or more times.
+    fn impair(&self, buf: &[u8], target: SocketAddr) {
+        let impairment = &self.impairment;
+        let mut state = self.state.lock().unwrap();
+        self.stats.sent.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(burst) = impairment.burst_loss {
+            let flip = if state.in_burst { burst.exit } else { burst.enter };
+            if state.rng.random_bool(flip) {
+                state.in_burst = !state.in_burst;
+            }
+        }
+        let too_large = impairment.mtu.is_some_and(|mtu| buf.len() > mtu);
+        if too_large || state.in_burst || state.rng.random_bool(impairment.loss) {
+            self.stats.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let now = Instant::now();
+        let mut at = now;
+        if let Some(bandwidth) = impairment.bandwidth {
+            let transmission = Duration::from_secs_f64(buf.len() as f64 / bandwidth as f64);
+            at = state.link_free_at.max(now) + transmission;
+            state.link_free_at = at;
+        }
+        at += impairment.delay;
+
+        let copies = if state.rng.random_bool(impairment.duplicate) {
+            self.stats.duplicated.fetch_add(1, Ordering::Relaxed);
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let mut copy_at = at;
+            if !impairment.jitter.is_zero() {
+                copy_at += impairment.jitter.mul_f64(state.rng.random::<f64>());
+            }
+            if state.rng.random_bool(impairment.reorder) {
+                self.stats.reordered.fetch_add(1, Ordering::Relaxed);
+                copy_at += impairment.reorder_delay;
+            }
+            state.sequence += 1;
+            let _ = self.queue.send(Delayed { at: copy_at, sequence: state.sequence, target, data: buf.to_vec() });
+        }
+    }
+}
+
+impl DatagramTransport for ImpairedTransport {
+    fn poll_send_to(&self, _cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        self.impair(buf, target);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        self.inner.poll_recv_from(cx, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+// deliver sends queued datagrams on the inner transport once their time has come. It stops once
+// the transport is dropped and everything queued has been sent.
+async fn deliver(inner: Arc<dyn DatagramTransport>, mut queue: mpsc::UnboundedReceiver<Delayed>) {
+    let mut pending: BinaryHeap<Reverse<Delayed>> = BinaryHeap::new();
+    let mut closed = false;
+    loop {
+        let next = pending.peek().map(|Reverse(delayed)| delayed.at);
+        if closed && next.is_none() {
+            return;
+        }
+        tokio::select! {
+            delayed = queue.recv(), if !closed => match delayed {
+                Some(delayed) => pending.push(Reverse(delayed)),
+                None => closed = true,
+            },
+            _ = sleep_until(next.unwrap_or_else(Instant::now)), if next.is_some() => {
+                let now = Instant::now();
+                while pending.peek().is_some_and(|Reverse(delayed)| delayed.at <= now) {
+                    let Reverse(delayed) = pending.pop().unwrap();
+                    // a lost datagram is nothing unusual on an impaired network.
+                    let _ = inner.send_to(&delayed.data, delayed.target).await;
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 pub const MTU_SIZE: u16 = 1492;
+pub const MIN_MTU_SIZE: u16 = 576;
 pub const MAX_WINDOW_SIZE: u16 = 2048;
 pub const UDP_HEADER_SIZE: u8 = 28;
 pub const PUBLIC_KEY_SIZE: u16 = 294;
@@ -17,10 +18,22 @@ pub mod motd;
 pub mod messages;
 pub mod conn;
 pub mod frame;
+pub mod ack;
+pub mod session;
+pub mod listener;
+pub mod transport;
+pub mod clock;
+pub mod impairment;
+pub mod capture;
+pub mod replay;
+pub mod dissect;
+pub mod stats;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod query;
+pub mod discovery;
 mod packet_queue;
-mod dynamic_queue;
 
-use std::net::Shutdown::Read;
 use types::{Packet, PacketId};
 use messages::unknown::UnknownPacket;
 use messages::unconnected_ping::UnconnectedPing;
@@ -31,6 +44,11 @@ use messages::open_connection_reply_1::OpenConnectionReply1;
 use messages::open_connection_reply_2::OpenConnectionReply2;
 use messages::connected_ping::ConnectedPing;
 use messages::connected_pong::ConnectedPong;
+use messages::connection_request::ConnectionRequest;
+use messages::connection_request_accepted::ConnectionRequestAccepted;
+use messages::new_incoming_connection::NewIncomingConnection;
+use messages::disconnect_notification::DisconnectNotification;
+use messages::incompatible_protocol_version::IncompatibleProtocolVersion;
 
 #[derive(Debug)]
 pub enum PacketT {
@@ -45,6 +63,12 @@ pub enum PacketT {
 
     OpenConnectionReply1(OpenConnectionReply1),
     OpenConnectionReply2(OpenConnectionReply2),
+    IncompatibleProtocolVersion(IncompatibleProtocolVersion),
+
+    ConnectionRequest(ConnectionRequest),
+    ConnectionRequestAccepted(ConnectionRequestAccepted),
+    NewIncomingConnection(NewIncomingConnection),
+    DisconnectNotification(DisconnectNotification),
 
     Unknown(UnknownPacket),
 }
@@ -53,7 +77,7 @@ pub enum PacketT {
 /// Parses the packet data directly without handling the packet type and flags.
 /// This should never be used in practice, instead use `ReceivePacket` which handles packet type and flags.
 /// Example usage:
-/// ```
+/// ```ignore
 ///     let (len, src) = socket.recv_from(&mut buf).await?;
 ///
 ///     // wont correctly parse datagrams, ack or nack packets
@@ -68,35 +92,78 @@ pub enum PacketT {
 ///     }
 /// ```
 pub fn ReadPacket(data: &[u8]) -> Result<Option<PacketT>, String> {
+    if data.is_empty() {
+        return Err("Error reading packet: empty datagram".to_string());
+    }
     let packetId = &data[0].into();
 
     let packetData = &data[1..];
-    match packetId {
-        &PacketId::ConnectedPing => {
+    match *packetId {
+        PacketId::ConnectedPing => {
             ConnectedPing::deserialize(packetData)
                 .map(|packet| Some(PacketT::ConnectedPing(packet)))
                 .map_err(|err| format!("Error deserializing ConnectedPing packet: {:?}", err.to_string()))
         },
-        &PacketId::UnconnectedPing => {
+        PacketId::ConnectedPong => {
+            ConnectedPong::deserialize(packetData)
+                .map(|packet| Some(PacketT::ConnectedPong(packet)))
+                .map_err(|err| format!("Error deserializing ConnectedPong packet: {:?}", err.to_string()))
+        },
+        PacketId::UnconnectedPing => {
             UnconnectedPing::deserialize(packetData)
                 .map(|packet| Some(PacketT::UnconnectedPing(packet)))
                 .map_err(|err| format!("Error deserializing UnconnectedPing packet: {:?}", err.to_string()))
         },
-        &PacketId::UnconnectedPong => {
+        PacketId::UnconnectedPong => {
             UnconnectedPong::deserialize(packetData)
                 .map(|packet| Some(PacketT::UnconnectedPong(packet)))
                 .map_err(|err| format!("Error deserializing UnconnectedPong packet: {:?}", err.to_string()))
         },
-        &PacketId::OpenConnectionRequest1 => {
+        PacketId::OpenConnectionRequest1 => {
             OpenConnectionRequest1::deserialize(packetData)
                 .map(|packet| Some(PacketT::OpenConnectionRequest1(packet)))
                 .map_err(|err| format!("Error deserializing OpenConnectionRequest1 packet: {:?}", err.to_string()))
         }
-        &PacketId::OpenConnectionRequest2 => {
+        PacketId::OpenConnectionRequest2 => {
             OpenConnectionRequest2::deserialize(packetData)
                 .map(|packet| Some(PacketT::OpenConnectionRequest2(packet)))
                 .map_err(|err| format!("Error deserializing OpenConnectionRequest2 packet: {:?}", err.to_string()))
         }
+        PacketId::OpenConnectionReply1 => {
+            OpenConnectionReply1::deserialize(packetData)
+                .map(|packet| Some(PacketT::OpenConnectionReply1(packet)))
+                .map_err(|err| format!("Error deserializing OpenConnectionReply1 packet: {:?}", err.to_string()))
+        }
+        PacketId::OpenConnectionReply2 => {
+            OpenConnectionReply2::deserialize(packetData)
+                .map(|packet| Some(PacketT::OpenConnectionReply2(packet)))
+                .map_err(|err| format!("Error deserializing OpenConnectionReply2 packet: {:?}", err.to_string()))
+        }
+        PacketId::IncompatibleProtocolVersion => {
+            IncompatibleProtocolVersion::deserialize(packetData)
+                .map(|packet| Some(PacketT::IncompatibleProtocolVersion(packet)))
+                .map_err(|err| format!("Error deserializing IncompatibleProtocolVersion packet: {:?}", err.to_string()))
+        }
+        PacketId::ConnectionRequest => {
+            ConnectionRequest::deserialize(packetData)
+                .map(|packet| Some(PacketT::ConnectionRequest(packet)))
+                .map_err(|err| format!("Error deserializing ConnectionRequest packet: {:?}", err.to_string()))
+        }
+        PacketId::ConnectionRequestAccepted => {
+            ConnectionRequestAccepted::deserialize(packetData)
+                .map(|packet| Some(PacketT::ConnectionRequestAccepted(packet)))
+                .map_err(|err| format!("Error deserializing ConnectionRequestAccepted packet: {:?}", err.to_string()))
+        }
+        PacketId::NewIncomingConnection => {
+            NewIncomingConnection::deserialize(packetData)
+                .map(|packet| Some(PacketT::NewIncomingConnection(packet)))
+                .map_err(|err| format!("Error deserializing NewIncomingConnection packet: {:?}", err.to_string()))
+        }
+        PacketId::DisconnectNotification => {
+            DisconnectNotification::deserialize(packetData)
+                .map(|packet| Some(PacketT::DisconnectNotification(packet)))
+                .map_err(|err| format!("Error deserializing DisconnectNotification packet: {:?}", err.to_string()))
+        }
         _ => {
             UnknownPacket::deserialize(data)
                 .map(|packet| Some(PacketT::Unknown(packet)))
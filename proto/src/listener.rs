@@ -0,0 +1,278 @@
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use bytes::Bytes;
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, error, trace};
+use crate::clock::{Clock, SystemClock};
+use crate::conn::Conn;
+use crate::messages::incompatible_protocol_version::IncompatibleProtocolVersion;
+use crate::messages::open_connection_reply_1::OpenConnectionReply1;
+use crate::messages::open_connection_reply_2::OpenConnectionReply2;
+use crate::messages::unconnected_pong::UnconnectedPong;
+use crate::packet::PacketBitFlags;
+use crate::session::Session;
+use crate::stats::{HandshakeFailure, ListenerStats, Stats};
+use crate::transport::{spawn_writer, DatagramTransport, Outbound, ReceiveBuffer};
+use crate::types::{Hex, Packet};
+use crate::{PacketT, ReadPacket, DEFAULT_PROTOCOL_VERSION, MIN_MTU_SIZE, MTU_SIZE};
+
+// INBOUND_QUEUE is how many received datagrams may wait for a connection that is busy before
+// the listener drops them, which RakNet recovers from like from any other lost datagram.
+const INBOUND_QUEUE: usize = 512;
+
+/// Listener accepts RakNet connections on a single socket. It answers the offline messages
+/// (unconnected pings and the open connection requests) itself and passes every other datagram
+/// to the connection of the address it came from. Every connection handles its datagrams in a
+/// task of its own, so one that is busy never holds up receiving for the others.
+pub struct Listener {
+    pub conn: Arc<dyn DatagramTransport>,
+    pub guid: u64,
+
+    clock: Arc<dyn Clock>,
+    // replies to offline messages, the connections have writers of their own.
+    outbound: Outbound,
+    pong_data: std::sync::Mutex<String>,
+    conns: std::sync::Mutex<HashMap<SocketAddr, Entry>>,
+    banned: std::sync::Mutex<HashSet<IpAddr>>,
+    // counters of the connections that were closed, so that the totals never go down.
+    closed_stats: std::sync::Mutex<Stats>,
+    accepted: AtomicU64,
+    handshake_failures: [AtomicU64; 5],
+    incoming_tx: mpsc::UnboundedSender<Arc<Conn>>,
+    incoming_rx: Mutex<mpsc::UnboundedReceiver<Arc<Conn>>>,
+}
+
+// Entry is a connection of the listener and the queue of datagrams received for it.
+struct Entry {
+    conn: Arc<Conn>,
+    inbound: mpsc::Sender<Bytes>,
+}
+
+impl Listener {
+    /// Binds a socket to the address passed and starts listening for connections on it.
+    pub async fn listen<A: ToSocketAddrs>(address: A) -> io::Result<Arc<Listener>> {
+        let socket = UdpSocket::bind(address).await?;
+        Ok(Self::listen_on(Arc::new(socket), Arc::new(SystemClock)))
+    }
+
+    /// Starts listening for connections on a transport that is already bound, such as a
+    /// `MemorySocket`, with the timers of the connections running on `clock`.
+    pub fn listen_on(transport: Arc<dyn DatagramTransport>, clock: Arc<dyn Clock>) -> Arc<Listener> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let listener = Arc::new(Listener {
+            conn: transport.clone(),
+            guid: rand::random(),
+
+            clock,
+            outbound: spawn_writer(transport.clone()),
+            pong_data: std::sync::Mutex::new(String::new()),
+            conns: std::sync::Mutex::new(HashMap::new()),
+            banned: std::sync::Mutex::new(HashSet::new()),
+            closed_stats: std::sync::Mutex::new(Stats::default()),
+            accepted: AtomicU64::new(0),
+            handshake_failures: Default::default(),
+            incoming_tx: tx,
+            incoming_rx: Mutex::new(rx),
+        });
+        tokio::spawn(listener.clone().read_loop());
+        listener
+    }
+
+    /// Waits for the next client to complete the connected handshake.
+    pub async fn accept(&self) -> io::Result<Arc<Conn>> {
+        self.incoming_rx.lock().await.recv().await.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotConnected, "accept: listener closed")
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.conn.local_addr()
+    }
+
+    /// Returns the stats of every connection of the listener added up.
+    pub async fn stats(&self) -> ListenerStats {
+        // taken together, as closed connections are moved from one to the other with the map locked.
+        let (conns, closed) = {
+            let conns = self.conns.lock().unwrap();
+            let closed = self.closed_stats.lock().unwrap().clone();
+            (conns.values().map(|entry| entry.conn.clone()).collect::<Vec<_>>(), closed)
+        };
+        let mut stats = Vec::with_capacity(conns.len());
+        for conn in conns {
+            stats.push(conn.stats().await);
+        }
+        self.totals(&closed, stats)
+    }
+
+    /// Returns the stats of every connection added up like `stats` does, from the stats the
+    /// connections put together every second. It never waits for a connection that is busy.
+    pub fn last_stats(&self) -> ListenerStats {
+        let conns = self.conns.lock().unwrap();
+        let closed = self.closed_stats.lock().unwrap();
+        self.totals(&closed, conns.values().map(|entry| entry.conn.last_stats()))
+    }
+
+    fn totals(&self, closed: &Stats, open: impl IntoIterator<Item = Stats>) -> ListenerStats {
+        ListenerStats {
+            accepted: self.accepted.load(Ordering::Relaxed),
+            handshake_failures: self.handshake_failures.each_ref().map(|n| n.load(Ordering::Relaxed)),
+            bans: self.banned.lock().unwrap().len() as u64,
+            ..ListenerStats::aggregate(closed, open)
+        }
+    }
+
+    /// Bans an IP: its open connection requests are ignored from now on, and the connections it
+    /// has are closed.
+    pub fn ban(&self, ip: IpAddr) {
+        self.banned.lock().unwrap().insert(ip);
+        for (src, entry) in self.conns.lock().unwrap().iter() {
+            if src.ip() == ip {
+                let conn = entry.conn.clone();
+                tokio::spawn(async move { conn.close().await });
+            }
+        }
+    }
+
+    /// Lifts the ban of an IP.
+    pub fn unban(&self, ip: IpAddr) {
+        self.banned.lock().unwrap().remove(&ip);
+    }
+
+    /// Sets the data sent in unconnected pongs, which Bedrock uses to show the server's status.
+    pub fn set_pong_data(&self, data: String) {
+        *self.pong_data.lock().unwrap() = data;
+    }
+
+    async fn read_loop(self: Arc<Self>) {
+        let mut buf = ReceiveBuffer::new();
+        loop {
+            let (data, src) = match buf.recv_from(&self.conn).await {
+                Ok(received) => received,
+                Err(e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+                Err(e) => {
+                    error!(error = %e, "listener stopped reading datagrams");
+                    return;
+                }
+            };
+            if data.is_empty() {
+                continue;
+            }
+
+            if data[0] & PacketBitFlags::Datagram as u8 != 0 {
+                if let Some(entry) = self.conns.lock().unwrap().get(&src) {
+                    // datagrams that don't fit in the queue are lost like any other.
+                    if let Err(TrySendError::Full(_)) = entry.inbound.try_send(data) {
+                        trace!(%src, "dropping datagram for a connection that is behind");
+                    }
+                    continue;
+                }
+            }
+            trace!(%src, len = data.len(), "received offline message");
+            if let Err(e) = self.handle_offline(&data, src) {
+                debug!(%src, error = %e, data = %Hex(&data), "invalid offline message");
+            }
+        }
+    }
+
+    // handle_offline answers the offline messages sent before a connection is established.
+    fn handle_offline(self: &Arc<Self>, data: &[u8], src: SocketAddr) -> Result<(), String> {
+        let packet = ReadPacket(data).inspect_err(|_| self.handshake_failed(HandshakeFailure::Malformed))?;
+        if matches!(packet, Some(PacketT::OpenConnectionRequest1(_) | PacketT::OpenConnectionRequest2(_))) && self.banned.lock().unwrap().contains(&src.ip()) {
+            debug!(%src, "ignoring open connection request of a banned client");
+            self.handshake_failed(HandshakeFailure::Banned);
+            return Ok(());
+        }
+        let response = match packet {
+            Some(PacketT::UnconnectedPing(ping)) => UnconnectedPong {
+                client_send_time_be: ping.client_send_time_be,
+                server_guid_be: self.guid,
+                data: self.pong_data.lock().unwrap().clone(),
+            }.serialize(),
+            Some(PacketT::OpenConnectionRequest1(request)) if request.client_protocol != DEFAULT_PROTOCOL_VERSION => {
+                debug!(%src, client_protocol = request.client_protocol, "refusing client of another protocol version");
+                self.handshake_failed(HandshakeFailure::IncompatibleProtocol);
+                IncompatibleProtocolVersion { server_protocol: DEFAULT_PROTOCOL_VERSION, server_guid_be: self.guid }.serialize()
+            }
+            Some(PacketT::OpenConnectionRequest1(request)) => OpenConnectionReply1 {
+                server_guid_be: self.guid,
+                server_has_security: false,
+                cookie: 0,
+                max_transmission_unit_be: request.max_transmission_unit.clamp(MIN_MTU_SIZE, MTU_SIZE),
+            }.serialize(),
+            Some(PacketT::OpenConnectionRequest2(request)) => {
+                // the MTU is up to the client, but anything below the smallest one clients try
+                // leaves no room for the frames.
+                let max_transmission_unit = request.max_transmission_unit.clamp(MIN_MTU_SIZE, MTU_SIZE);
+                self.add_conn(src, max_transmission_unit);
+                OpenConnectionReply2 {
+                    server_guid_be: self.guid,
+                    client_address: src.into(),
+                    max_transmission_unit_be: max_transmission_unit,
+                    do_security: false,
+                }.serialize()
+            }
+            _ => return Ok(()),
+        };
+        // clients resend their requests and pings, so replies are dropped rather than waited for.
+        if let Err(e) = self.outbound.try_send((response.into(), src)) {
+            debug!(%src, error = %e, "dropping reply to offline message");
+        }
+        Ok(())
+    }
+
+    // add_conn creates the connection of a client that sent its second open connection request.
+    // The client resends the request if our reply got lost, which shouldn't reset the connection.
+    fn add_conn(self: &Arc<Self>, src: SocketAddr, max_transmission_unit: u16) {
+        let mut conns = self.conns.lock().unwrap();
+        if conns.contains_key(&src) {
+            return;
+        }
+        let session = Session::server(self.clock.now(), src, self.guid, max_transmission_unit);
+        let conn = Arc::new(Conn::new(self.conn.clone(), self.clock.clone(), session));
+        debug!(parent: conn.span(), max_transmission_unit, "new connection");
+        let (inbound, mut rx) = mpsc::channel(INBOUND_QUEUE);
+        conns.insert(src, Entry { conn: conn.clone(), inbound });
+        drop(conns);
+        tokio::spawn(conn.clone().start_ticking());
+
+        // stops once the entry is removed from the map.
+        let receiver = conn.clone();
+        tokio::spawn(async move {
+            while let Some(data) = rx.recv().await {
+                // the connection logs the datagrams it can't handle itself.
+                let _ = receiver.ReceivePacket(data).await;
+            }
+        });
+
+        let listener = self.clone();
+        tokio::spawn(async move {
+            match conn.wait_connected().await {
+                Ok(()) => {
+                    listener.accepted.fetch_add(1, Ordering::Relaxed);
+                    let _ = listener.incoming_tx.send(conn.clone());
+                }
+                Err(e) => {
+                    debug!(parent: conn.span(), error = %e, "handshake failed");
+                    let failure = if e.kind() == io::ErrorKind::TimedOut { HandshakeFailure::TimedOut } else { HandshakeFailure::Aborted };
+                    listener.handshake_failed(failure);
+                }
+            }
+            conn.wait_closed().await;
+            // a closed session doesn't count anything anymore.
+            let stats = conn.stats().await;
+            let mut conns = listener.conns.lock().unwrap();
+            conns.remove(&src);
+            // counted while the map is locked, so that stats never see the connection twice or not at all.
+            listener.closed_stats.lock().unwrap().add_counters(&stats);
+        });
+    }
+
+    fn handshake_failed(&self, failure: HandshakeFailure) {
+        self.handshake_failures[failure as usize].fetch_add(1, Ordering::Relaxed);
+    }
+}
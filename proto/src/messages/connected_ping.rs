@@ -1,6 +1,7 @@
 use crate::types::{Packet, PacketId};
 use std::fmt::{Debug, Formatter};
 
+#[derive(PartialEq)]
 pub struct ConnectedPing {
     pub client_send_time_be: u64,
 }
@@ -12,13 +13,11 @@ impl Debug for ConnectedPing {
 }
 
 impl Packet for ConnectedPing {
-    fn serialize(&self) -> Vec<u8> {
-        let mut serialized = Vec::with_capacity(9);
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.reserve(9);
 
-        serialized[1] = PacketId::ConnectedPing as u8;
-        serialized.extend_from_slice(&u64::to_be_bytes(self.client_send_time_be));
-
-        serialized
+        buf.push(PacketId::ConnectedPing as u8);
+        buf.extend_from_slice(&u64::to_be_bytes(self.client_send_time_be));
     }
 
     fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
@@ -1,24 +1,25 @@
 use crate::types::{Packet, PacketId};
 use std::fmt::{Debug, Formatter};
 
+#[derive(PartialEq)]
 pub struct ConnectedPong {
     pub client_send_time_be: u64,
+    pub server_send_time_be: u64,
 }
 
 impl Debug for ConnectedPong {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{{ client_send_time_be: {} }}", self.client_send_time_be)
+        write!(f, "{{ client_send_time_be: {}, server_send_time_be: {} }}", self.client_send_time_be, self.server_send_time_be)
     }
 }
 
 impl Packet for ConnectedPong {
-    fn serialize(&self) -> Vec<u8> {
-        let mut serialized = Vec::with_capacity(9);
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.reserve(17);
 
-        serialized.push(PacketId::ConnectedPong as u8);
-        serialized.extend_from_slice(&u64::to_be_bytes(self.client_send_time_be));
-
-        serialized
+        buf.push(PacketId::ConnectedPong as u8);
+        buf.extend_from_slice(&u64::to_be_bytes(self.client_send_time_be));
+        buf.extend_from_slice(&u64::to_be_bytes(self.server_send_time_be));
     }
 
     fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
@@ -27,8 +28,12 @@ impl Packet for ConnectedPong {
         }
 
         let client_send_time_be = u64::from_be_bytes(data[0..8].try_into().unwrap());
-        Ok(ConnectedPong { client_send_time_be })
-
+        // some implementations leave out the time the pong was sent.
+        let server_send_time_be = match data.get(8..16) {
+            Some(time) => u64::from_be_bytes(time.try_into().unwrap()),
+            None => 0,
+        };
+        Ok(ConnectedPong { client_send_time_be, server_send_time_be })
 
     }
 }
@@ -0,0 +1,37 @@
+use crate::types::{read_be_u64, Packet, PacketId};
+use std::fmt::{Debug, Formatter};
+
+#[derive(PartialEq)]
+pub struct ConnectionRequest {
+    pub client_guid_be: u64,
+    pub request_time_be: u64,
+    pub secure: bool,
+}
+
+impl Debug for ConnectionRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ConnectionRequest {{ client_guid_be: {}, request_time_be: {}, secure: {} }}", self.client_guid_be, self.request_time_be, self.secure)
+    }
+}
+
+impl Packet for ConnectionRequest {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.reserve(18);
+        buf.push(PacketId::ConnectionRequest as u8);
+
+        buf.extend_from_slice(&self.client_guid_be.to_be_bytes());
+        buf.extend_from_slice(&self.request_time_be.to_be_bytes());
+        buf.push(self.secure as u8);
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
+        if data.len() < 17 {
+            return Err("Invalid data length".to_string());
+        }
+        Ok(ConnectionRequest {
+            client_guid_be: read_be_u64(data),
+            request_time_be: read_be_u64(&data[8..]),
+            secure: data[16] != 0,
+        })
+    }
+}
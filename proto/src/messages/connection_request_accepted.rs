@@ -0,0 +1,61 @@
+use crate::address::{addr_size, read_addr, Address};
+use crate::types::{read_be_u16, read_be_u64, Packet, PacketId};
+use std::fmt::{Debug, Formatter};
+
+#[derive(PartialEq)]
+pub struct ConnectionRequestAccepted {
+    pub client_address: Address,
+    pub system_index: u16,
+    pub system_addresses: Vec<Address>,
+    pub request_time_be: u64,
+    pub accepted_time_be: u64,
+}
+
+impl Debug for ConnectionRequestAccepted {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ConnectionRequestAccepted {{ client_address: {:?}, system_index: {}, system_addresses: {}, request_time_be: {}, accepted_time_be: {} }}", self.client_address, self.system_index, self.system_addresses.len(), self.request_time_be, self.accepted_time_be)
+    }
+}
+
+impl Packet for ConnectionRequestAccepted {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.reserve(35 + self.client_address.size() as usize + self.system_addresses.len() * 7);
+        buf.push(PacketId::ConnectionRequestAccepted as u8);
+
+        self.client_address.write(buf);
+        buf.extend_from_slice(&self.system_index.to_be_bytes());
+        for address in &self.system_addresses {
+            address.write(buf);
+        }
+        buf.extend_from_slice(&self.request_time_be.to_be_bytes());
+        buf.extend_from_slice(&self.accepted_time_be.to_be_bytes());
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
+        let client_address = read_addr(data)?;
+        let mut offset = addr_size(data) as usize;
+        if data.len() < offset + 2 + 16 {
+            return Err("Invalid data length".to_string());
+        }
+        let system_index = read_be_u16(&data[offset..]);
+        offset += 2;
+
+        // the amount of system addresses differs between implementations, they fill up
+        // everything up to the two timestamps at the end.
+        let mut system_addresses = Vec::new();
+        while data.len() - offset > 16 {
+            system_addresses.push(read_addr(&data[offset..data.len() - 16])?);
+            offset += addr_size(&data[offset..]) as usize;
+        }
+        if data.len() - offset < 16 {
+            return Err("Invalid data length".to_string());
+        }
+        Ok(ConnectionRequestAccepted {
+            client_address,
+            system_index,
+            system_addresses,
+            request_time_be: read_be_u64(&data[offset..]),
+            accepted_time_be: read_be_u64(&data[offset + 8..]),
+        })
+    }
+}
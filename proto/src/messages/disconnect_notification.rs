@@ -0,0 +1,21 @@
+use crate::types::{Packet, PacketId};
+use std::fmt::{Debug, Formatter};
+
+#[derive(PartialEq)]
+pub struct DisconnectNotification {}
+
+impl Debug for DisconnectNotification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DisconnectNotification {{}}")
+    }
+}
+
+impl Packet for DisconnectNotification {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.push(PacketId::DisconnectNotification as u8);
+    }
+
+    fn deserialize(_: &[u8]) -> Result<Self, String> where Self: Sized {
+        Ok(DisconnectNotification {})
+    }
+}
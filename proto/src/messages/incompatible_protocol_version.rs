@@ -0,0 +1,36 @@
+use crate::types::{read_be_u64, Packet, PacketId, UNCONNECTED_MESSAGE_SEQUENCE};
+use std::fmt::{Debug, Formatter};
+
+#[derive(PartialEq)]
+pub struct IncompatibleProtocolVersion {
+    pub server_protocol: u8,
+    pub server_guid_be: u64,
+}
+
+impl Debug for IncompatibleProtocolVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IncompatibleProtocolVersion {{ server_protocol: {}, server_guid_be: {} }}", self.server_protocol, self.server_guid_be)
+    }
+}
+
+impl Packet for IncompatibleProtocolVersion {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.reserve(26);
+        buf.push(PacketId::IncompatibleProtocolVersion as u8);
+
+        buf.push(self.server_protocol);
+        buf.extend_from_slice(&UNCONNECTED_MESSAGE_SEQUENCE);
+        buf.extend_from_slice(&self.server_guid_be.to_be_bytes());
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
+        if data.len() < 25 {
+            return Err("Invalid data length".to_string());
+        }
+        // magic: 16 bytes
+        Ok(IncompatibleProtocolVersion {
+            server_protocol: data[0],
+            server_guid_be: read_be_u64(&data[17..]),
+        })
+    }
+}
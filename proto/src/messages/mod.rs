@@ -6,4 +6,9 @@ pub mod connected_ping;
 pub mod connected_pong;
 pub mod unknown;
 pub mod unconnected_ping;
-pub mod unconnected_pong;
\ No newline at end of file
+pub mod unconnected_pong;
+pub mod connection_request;
+pub mod connection_request_accepted;
+pub mod new_incoming_connection;
+pub mod disconnect_notification;
+pub mod incompatible_protocol_version;
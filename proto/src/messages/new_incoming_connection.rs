@@ -0,0 +1,52 @@
+use crate::address::{addr_size, read_addr, Address};
+use crate::types::{read_be_u64, Packet, PacketId};
+use std::fmt::{Debug, Formatter};
+
+#[derive(PartialEq)]
+pub struct NewIncomingConnection {
+    pub server_address: Address,
+    pub system_addresses: Vec<Address>,
+    pub request_time_be: u64,
+    pub accepted_time_be: u64,
+}
+
+impl Debug for NewIncomingConnection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NewIncomingConnection {{ server_address: {:?}, system_addresses: {}, request_time_be: {}, accepted_time_be: {} }}", self.server_address, self.system_addresses.len(), self.request_time_be, self.accepted_time_be)
+    }
+}
+
+impl Packet for NewIncomingConnection {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.reserve(33 + self.server_address.size() as usize + self.system_addresses.len() * 7);
+        buf.push(PacketId::NewIncomingConnection as u8);
+
+        self.server_address.write(buf);
+        for address in &self.system_addresses {
+            address.write(buf);
+        }
+        buf.extend_from_slice(&self.request_time_be.to_be_bytes());
+        buf.extend_from_slice(&self.accepted_time_be.to_be_bytes());
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
+        let server_address = read_addr(data)?;
+        let mut offset = addr_size(data) as usize;
+
+        // see ConnectionRequestAccepted, the system addresses fill up everything up to the timestamps.
+        let mut system_addresses = Vec::new();
+        while data.len() > offset + 16 {
+            system_addresses.push(read_addr(&data[offset..data.len() - 16])?);
+            offset += addr_size(&data[offset..]) as usize;
+        }
+        if data.len() < offset + 16 {
+            return Err("Invalid data length".to_string());
+        }
+        Ok(NewIncomingConnection {
+            server_address,
+            system_addresses,
+            request_time_be: read_be_u64(&data[offset..]),
+            accepted_time_be: read_be_u64(&data[offset + 8..]),
+        })
+    }
+}
@@ -2,6 +2,7 @@ use std::fmt::{Debug, Formatter};
 use crate::address::{addr_size, read_addr, Address};
 use crate::types::{read_be_u16, Packet, PacketId, UNCONNECTED_MESSAGE_SEQUENCE};
 
+#[derive(PartialEq)]
 pub struct OpenConnectionReply2 {
     pub server_guid_be: u64,
     pub client_address: Address,
@@ -16,20 +17,17 @@ impl Debug for OpenConnectionReply2 {
 }
 
 impl Packet for OpenConnectionReply2 {
-    fn serialize(&self) -> Vec<u8> {
+    fn write(&self, buf: &mut Vec<u8>) {
         let offset = self.client_address.size() as usize;
-        let mut result = Vec::with_capacity(28 + offset);
-        result.push(PacketId::OpenConnectionReply2 as u8);
+        buf.reserve(28 + offset);
+        buf.push(PacketId::OpenConnectionReply2 as u8);
 
-        result.extend_from_slice(&UNCONNECTED_MESSAGE_SEQUENCE);
-        result.extend_from_slice(&(self.server_guid_be).to_be_bytes());
-        result.extend_from_slice(&self.client_address.serialize());
+        buf.extend_from_slice(&UNCONNECTED_MESSAGE_SEQUENCE);
+        buf.extend_from_slice(&(self.server_guid_be).to_be_bytes());
+        self.client_address.write(buf);
 
-        result.extend_from_slice(&self.max_transmission_unit_be.to_be_bytes());
-        if self.do_security {
-            result[27+offset] = 1;
-        }
-        result
+        buf.extend_from_slice(&self.max_transmission_unit_be.to_be_bytes());
+        buf.push(self.do_security as u8);
     }
 
     fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
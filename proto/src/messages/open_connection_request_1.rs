@@ -1,5 +1,10 @@
 use std::fmt::{Debug, Formatter};
 use crate::types::{Packet, PacketId, UNCONNECTED_MESSAGE_SEQUENCE};
+
+// packet id + magic + protocol version.
+const MIN_SIZE: usize = 1 + 16 + 1;
+
+#[derive(PartialEq)]
 pub struct OpenConnectionRequest1 {
     pub client_protocol: u8,
     pub max_transmission_unit: u16,
@@ -12,14 +17,17 @@ impl Debug for OpenConnectionRequest1 {
 }
 
 impl Packet for OpenConnectionRequest1 {
-    fn serialize(&self) -> Vec<u8> {
-        let mut serialized = Vec::with_capacity(self.max_transmission_unit as usize - 20 - 8);
-        serialized.push(PacketId::OpenConnectionRequest1 as u8);
-
-        serialized.extend_from_slice(&UNCONNECTED_MESSAGE_SEQUENCE);
-        serialized.push(self.client_protocol);
+    fn write(&self, buf: &mut Vec<u8>) {
+        // the request is padded so the datagram is exactly as big as the MTU, the server infers the MTU from its size.
+        // An MTU too small for the IP and UDP headers and the request itself can't be told this way.
+        let size = (self.max_transmission_unit as usize).saturating_sub(20 + 8).max(MIN_SIZE);
+        let start = buf.len();
+        buf.reserve(size);
+        buf.push(PacketId::OpenConnectionRequest1 as u8);
 
-        serialized
+        buf.extend_from_slice(&UNCONNECTED_MESSAGE_SEQUENCE);
+        buf.push(self.client_protocol);
+        buf.resize(start + size, 0);
     }
 
     fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
@@ -29,6 +37,9 @@ impl Packet for OpenConnectionRequest1 {
         // magic: 16 bytes
         let protocol_version = data[16];
         let mtu = data.len() + 20 + 8 + 1; // headers + packet id
+        if mtu > u16::MAX as usize {
+            return Err("Invalid data length".to_string());
+        }
         Ok(OpenConnectionRequest1 {
             client_protocol: protocol_version,
             max_transmission_unit: mtu as u16,
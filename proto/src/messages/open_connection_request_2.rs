@@ -1,9 +1,10 @@
 use crate::address::{addr_size, read_addr, Address};
-use crate::types::{read_be_u16, read_be_u32, read_be_u64, Packet};
+use crate::types::{read_be_u16, read_be_u32, read_be_u64, Packet, PacketId, UNCONNECTED_MESSAGE_SEQUENCE};
 use std::fmt::{Debug, Formatter};
 use std::sync::Mutex;
 
 
+#[derive(PartialEq)]
 pub struct OpenConnectionRequest2 {
     pub server_address: Address,
     pub max_transmission_unit: u16,
@@ -22,32 +23,43 @@ use std::collections::HashMap;
 lazy_static::lazy_static! {
     static ref CACHED_OCR1: Mutex<HashMap<usize, Vec<u8>>> = Mutex::new(HashMap::new());
 }
-const SERVER_HAS_SECURITY: bool = false;
+// size of the cookie and the byte telling whether the client supports security, sent to servers
+// with security.
+const SECURITY_SIZE: usize = 4 + 1;
 
 impl Packet for OpenConnectionRequest2 {
-    fn serialize(&self) -> Vec<u8> {
-        vec![]
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.reserve(34 + self.server_address.size() as usize);
+        buf.push(PacketId::OpenConnectionRequest2 as u8);
+
+        buf.extend_from_slice(&UNCONNECTED_MESSAGE_SEQUENCE);
+        if self.server_has_security {
+            buf.extend_from_slice(&self.cookie.to_be_bytes());
+            // client doesn't support security.
+            buf.push(0);
+        }
+        self.server_address.write(buf);
+        buf.extend_from_slice(&self.max_transmission_unit.to_be_bytes());
+        buf.extend_from_slice(&self.client_guid.to_be_bytes());
     }
 
     fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
-        let mut offset = 0usize;
-        if SERVER_HAS_SECURITY {
-            offset = 5;
-        }
+        // only the size of the request tells whether it holds the security fields: with them, the
+        // address starts 5 bytes later and the request is exactly that much longer.
+        let server_has_security = data.len() > 16 + SECURITY_SIZE &&
+            data.len() == 26 + SECURITY_SIZE + addr_size(&data[16 + SECURITY_SIZE..]) as usize;
+        let mut offset = if server_has_security { SECURITY_SIZE } else { 0 };
         if data.len() < 16+ offset ||
             data.len() < 26 + offset + addr_size(&data[16 + offset..]) as usize {
             return Err("invalid size".to_string());
         }
         // Magic: 16 bytes.
-        let cookie = if SERVER_HAS_SECURITY {
+        let cookie = if server_has_security {
             read_be_u32(data[16..].try_into().expect("slice with incorrect length"))
         } else {
             0
         };
-        let server_address = match read_addr(&data[16 + offset..]) {
-            Ok(addr) => addr,
-            Err(e) => return Err(e),
-        };
+        let server_address = read_addr(&data[16 + offset..])?;
         offset += addr_size(&data[16 + offset..]) as usize;
         let mtu = read_be_u16(data[16 + offset..].try_into().expect("slice with incorrect length"));
         let client_guid = read_be_u64(data[18 + offset..].try_into().expect("slice with incorrect length"));
@@ -55,7 +67,7 @@ impl Packet for OpenConnectionRequest2 {
             server_address,
             max_transmission_unit: mtu,
             client_guid,
-            server_has_security: SERVER_HAS_SECURITY,
+            server_has_security,
             cookie,
         })
     }
@@ -1,6 +1,7 @@
 use std::fmt::{Debug, Formatter};
 use crate::types::{read_be_u64, Packet, PacketId};
 
+#[derive(PartialEq)]
 pub struct UnconnectedPing {
     pub client_send_time_be: u64,
     pub client_guid_be: u64,
@@ -12,23 +13,20 @@ impl Debug for UnconnectedPing {
 }
 
 impl Packet for UnconnectedPing {
-    fn serialize(&self) -> Vec<u8> {
-        let mut serialized = Vec::with_capacity(33); // 32 bytes + packet id
-        serialized.push(PacketId::UnconnectedPing as u8);
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.reserve(33); // 32 bytes + packet id
+        buf.push(PacketId::UnconnectedPing as u8);
 
-        serialized.extend_from_slice(&self.client_send_time_be.to_be_bytes());
-        serialized.extend_from_slice(&crate::types::UNCONNECTED_MESSAGE_SEQUENCE);
-        serialized.extend_from_slice(&self.client_guid_be.to_be_bytes());
-
-
-        serialized
+        buf.extend_from_slice(&self.client_send_time_be.to_be_bytes());
+        buf.extend_from_slice(&crate::types::UNCONNECTED_MESSAGE_SEQUENCE);
+        buf.extend_from_slice(&self.client_guid_be.to_be_bytes());
     }
 
     fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
         if data.len() < 32 {
             return Err("Invalid data length".to_string());
         }
-        let client_send_time_be = read_be_u64(&data);
+        let client_send_time_be = read_be_u64(data);
         let client_guid_be = read_be_u64(&data[24..]);
 
         Ok(UnconnectedPing {
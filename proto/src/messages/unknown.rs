@@ -1,9 +1,11 @@
 use std::fmt::{Debug, Formatter};
+use bytes::Bytes;
 use crate::types::Packet;
 
+#[derive(PartialEq)]
 pub struct UnknownPacket {
     pub id: u8,
-    pub data: Vec<u8>,
+    pub data: Bytes,
 }
 
 impl Debug for UnknownPacket {
@@ -13,19 +15,30 @@ impl Debug for UnknownPacket {
 }
 
 impl Packet for UnknownPacket {
-    fn serialize(&self) -> Vec<u8> {
-        let mut serialized = Vec::with_capacity(1 + self.data.len());
-        serialized.push(self.id);
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.reserve(1 + self.data.len());
+        buf.push(self.id);
 
-        serialized.extend_from_slice(&self.data);
-
-        serialized
+        buf.extend_from_slice(&self.data);
     }
 
     fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
-        let id = data[0];
-        let data_vec = data[1..].to_vec();
+        let Some(&id) = data.first() else {
+            return Err("invalid size".to_string());
+        };
+
+        Ok(UnknownPacket { id, data: Bytes::copy_from_slice(&data[1..]) })
+    }
+}
+
+impl UnknownPacket {
+    /// read_bytes is like `deserialize`, but the data of the packet is a slice of `data` rather
+    /// than a copy of it.
+    pub fn read_bytes(data: &Bytes) -> Result<Self, String> {
+        let Some(&id) = data.first() else {
+            return Err("invalid size".to_string());
+        };
 
-        Ok(UnknownPacket { id, data: data_vec })
+        Ok(UnknownPacket { id, data: data.slice(1..) })
     }
-}
\ No newline at end of file
+}
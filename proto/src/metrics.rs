@@ -0,0 +1,181 @@
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use crate::listener::Listener;
+use crate::packet::{Priority, Reliability};
+use crate::stats::{HandshakeFailure, ListenerStats, RTT_BUCKETS};
+use crate::types::PacketId;
+
+/// The content type of the OpenMetrics text format.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+// the largest request header read, anything Prometheus sends fits easily.
+const MAX_REQUEST_SIZE: usize = 8192;
+// how long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Serves the stats of a listener in the OpenMetrics text format at `/metrics` over HTTP, so that
+/// Prometheus can scrape them. Returns the address the endpoint is bound to; it is served by a task
+/// of its own for as long as the process runs.
+pub async fn serve<A: ToSocketAddrs>(address: A, listener: Arc<Listener>) -> io::Result<SocketAddr> {
+    let socket = TcpListener::bind(address).await?;
+    let local_addr = socket.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            let stream = match socket.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    tracing::warn!(error = %e, "accepting metrics connection failed");
+                    continue;
+                }
+            };
+            let listener = listener.clone();
+            tokio::spawn(async move {
+                if let Err(e) = respond(stream, &listener).await {
+                    tracing::debug!(error = %e, "serving metrics failed");
+                }
+            });
+        }
+    });
+    Ok(local_addr)
+}
+
+// respond answers a single HTTP request and closes the connection.
+async fn respond(mut stream: TcpStream, listener: &Listener) -> io::Result<()> {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream)).await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no request in time"))??;
+    let Some(request) = request else {
+        return Ok(());
+    };
+    let request_line = request.split(|b| *b == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|b| *b == b' ');
+    let response = match (parts.next(), parts.next()) {
+        (Some(b"GET"), Some(b"/metrics")) => {
+            // the stats of the connections as they were up to a second ago, a scrape never waits for them.
+            let body = encode(&listener.last_stats());
+            format!("HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", CONTENT_TYPE, body.len(), body)
+        }
+        (Some(b"GET"), _) => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        _ => "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// read_request reads the header of a request, returning None if the client closed the connection first.
+async fn read_request(stream: &mut TcpStream) -> io::Result<Option<Vec<u8>>> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request too large"));
+        }
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    Ok(Some(request))
+}
+
+/// Encodes the stats of a listener in the OpenMetrics text format.
+pub fn encode(stats: &ListenerStats) -> String {
+    let total = &stats.total;
+    let mut out = String::new();
+
+    gauge(&mut out, "raknet_connections", "Connections currently open.", &[("", stats.connections)]);
+    counter(&mut out, "raknet_connections_accepted", "Connections that completed the handshake.", &[("", stats.accepted)]);
+    let failures: Vec<(String, u64)> = HandshakeFailure::ALL.iter()
+        .map(|reason| (format!("reason=\"{:?}\"", reason), stats.handshake_failures[*reason as usize]))
+        .collect();
+    counter(&mut out, "raknet_handshake_failures", "Handshakes that failed, by reason.", &failures);
+    gauge(&mut out, "raknet_bans", "IPs currently banned.", &[("", stats.bans)]);
+
+    counter(&mut out, "raknet_sent_bytes", "Bytes sent.", &[("", total.bytes_sent)]);
+    counter(&mut out, "raknet_received_bytes", "Bytes received.", &[("", total.bytes_received)]);
+    counter(&mut out, "raknet_datagrams_sent", "Datagrams sent, resends included.", &[("", total.datagrams_sent)]);
+    counter(&mut out, "raknet_datagrams_received", "Datagrams received, duplicates included.", &[("", total.datagrams_received)]);
+    counter(&mut out, "raknet_duplicate_datagrams", "Datagrams received more than once.", &[("", total.duplicate_datagrams)]);
+    counter(&mut out, "raknet_resends", "Datagrams resent.", &[("", total.resends)]);
+    counter(&mut out, "raknet_nacks_sent", "Sequence numbers NACKed.", &[("", total.nacks_sent)]);
+    counter(&mut out, "raknet_nacks_received", "Sequence numbers NACKed by the other end.", &[("", total.nacks_received)]);
+    let ratio = if total.datagrams_sent == 0 { 0.0 } else { total.resends as f64 / total.datagrams_sent as f64 };
+    metric(&mut out, "raknet_resend_ratio", "gauge", "Share of the datagrams sent that were resends.", &[("", ratio)]);
+
+    let by_packet = |messages: &std::collections::BTreeMap<u8, u64>| -> Vec<(String, u64)> {
+        messages.iter().map(|(id, n)| (format!("packet=\"{:?}\"", PacketId::from(*id)), *n)).collect()
+    };
+    counter(&mut out, "raknet_messages_sent", "Messages sent, by packet id.", &by_packet(&total.messages_sent));
+    counter(&mut out, "raknet_messages_received", "Messages received, by packet id.", &by_packet(&total.messages_received));
+    let by_reliability = |frames: &[u64; 8]| -> Vec<(String, u64)> {
+        frames.iter().enumerate().filter(|(_, n)| **n > 0)
+            .map(|(i, n)| (format!("reliability=\"{:?}\"", Reliability::try_from(i as u8).unwrap()), *n))
+            .collect()
+    };
+    counter(&mut out, "raknet_frames_sent", "Frames sent, by reliability.", &by_reliability(&total.frames_sent));
+    counter(&mut out, "raknet_frames_received", "Frames received, by reliability.", &by_reliability(&total.frames_received));
+
+    let _ = writeln!(out, "# TYPE raknet_rtt_seconds histogram");
+    let _ = writeln!(out, "# HELP raknet_rtt_seconds Round trip times measured from ACKs.");
+    let mut cumulative = 0;
+    for (i, n) in total.rtt_histogram.iter().enumerate() {
+        cumulative += n;
+        match RTT_BUCKETS.get(i) {
+            // Debug formats floats canonically, with "5.0" rather than "5" for whole seconds.
+            Some(bound) => { let _ = writeln!(out, "raknet_rtt_seconds_bucket{{le=\"{:?}\"}} {}", bound.as_secs_f64(), cumulative); }
+            None => { let _ = writeln!(out, "raknet_rtt_seconds_bucket{{le=\"+Inf\"}} {}", cumulative); }
+        }
+    }
+    let _ = writeln!(out, "raknet_rtt_seconds_count {}", cumulative);
+    let _ = writeln!(out, "raknet_rtt_seconds_sum {}", total.rtt_sum.as_secs_f64());
+
+    seconds(&mut out, "raknet_smoothed_rtt_seconds", "Smoothed round trip time, averaged over the open connections.", total.smoothed_rtt);
+    seconds(&mut out, "raknet_ack_latency_seconds", "Latency of the last ACK, averaged over the open connections.", total.ack_latency);
+    gauge(&mut out, "raknet_datagrams_in_flight", "Reliable datagrams awaiting an ACK.", &[("", total.in_flight)]);
+    gauge(&mut out, "raknet_splits_in_progress", "Split messages being reassembled.", &[("", total.splits_in_progress)]);
+    let queues: Vec<(String, u64)> = [Priority::Immediate, Priority::High, Priority::Medium, Priority::Low].iter()
+        .map(|priority| (format!("priority=\"{:?}\"", priority), total.send_queue[*priority as usize]))
+        .collect();
+    gauge(&mut out, "raknet_send_queue_frames", "Frames waiting to be sent, by priority.", &queues);
+    gauge(&mut out, "raknet_receive_queue_messages", "Ordered messages waiting for an earlier one.", &[("", total.receive_queue)]);
+
+    out.push_str("# EOF\n");
+    out
+}
+
+fn counter<L: AsRef<str>>(out: &mut String, name: &str, help: &str, samples: &[(L, u64)]) {
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    for (labels, value) in samples {
+        sample(out, &format!("{}_total", name), labels.as_ref(), value);
+    }
+}
+
+fn gauge<L: AsRef<str>>(out: &mut String, name: &str, help: &str, samples: &[(L, u64)]) {
+    metric(out, name, "gauge", help, samples);
+}
+
+fn seconds(out: &mut String, name: &str, help: &str, value: Duration) {
+    metric(out, name, "gauge", help, &[("", value.as_secs_f64())]);
+}
+
+fn metric<L: AsRef<str>, V: std::fmt::Display>(out: &mut String, name: &str, kind: &str, help: &str, samples: &[(L, V)]) {
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    for (labels, value) in samples {
+        sample(out, name, labels.as_ref(), value);
+    }
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl std::fmt::Display) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
@@ -12,17 +12,13 @@ impl Debug for MOTD {
 }
 
 impl Packet for MOTD {
-    fn serialize(&self) -> Vec<u8> {
-        let result: Vec<String> = vec![];
-
-        result.join(";").into()
-    }
+    fn write(&self, _buf: &mut Vec<u8>) {}
 
     fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized {
         if data.len() < 32 {
             return Err("Invalid data length".to_string());
         }
-        let client_send_time_be = read_be_u64(&data);
+        let client_send_time_be = read_be_u64(data);
         let client_guid_be = read_be_u64(&data[24..]);
 
         Ok(MOTD {
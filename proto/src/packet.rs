@@ -1,34 +1,84 @@
-use crate::types::{read_u24, uint24};
+use crate::types::U24;
+use bytes::{BufMut, Bytes};
 use std::cmp::min;
-use lazy_static::lazy_static;
-use crate::{PacketT, ReadPacket};
-use crate::frame::Window;
+use std::ops::Range;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Reliability {
     Unreliable,
     UnreliableSequenced,
     Reliable,
     ReliableOrdered,
     ReliableSequenced,
-
-    // last value in enum, used to evaluate size of enum ( we use this to check the validity of reliability type)
-    ReliabilitySize,
+    /// The variants with an ACK receipt are sent like the reliability they are based on, and
+    /// additionally report whether the message arrived, see `Session::send`.
+    UnreliableWithAckReceipt,
+    ReliableWithAckReceipt,
+    ReliableOrderedWithAckReceipt,
 }
 
-impl From<u8> for Reliability {
-    fn from(value: u8) -> Self {
-        match value {
+impl TryFrom<u8> for Reliability {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
             0 => Reliability::Unreliable,
             1 => Reliability::UnreliableSequenced,
             2 => Reliability::Reliable,
             3 => Reliability::ReliableOrdered,
             4 => Reliability::ReliableSequenced,
-            _ => panic!("Invalid reliability type"),
+            5 => Reliability::UnreliableWithAckReceipt,
+            6 => Reliability::ReliableWithAckReceipt,
+            7 => Reliability::ReliableOrderedWithAckReceipt,
+            _ => return Err(format!("invalid reliability {}", value)),
+        })
+    }
+}
+
+impl Reliability {
+    /// Reports whether the sender of a message with this reliability wants to know if it arrived.
+    pub fn ack_receipt(self) -> bool {
+        matches!(self, Reliability::UnreliableWithAckReceipt | Reliability::ReliableWithAckReceipt | Reliability::ReliableOrderedWithAckReceipt)
+    }
+
+    /// Returns the variant of this reliability with an ACK receipt, if there is one. Sequenced
+    /// messages don't have one.
+    pub fn with_ack_receipt(self) -> Option<Reliability> {
+        match self {
+            Reliability::Unreliable | Reliability::UnreliableWithAckReceipt => Some(Reliability::UnreliableWithAckReceipt),
+            Reliability::Reliable | Reliability::ReliableWithAckReceipt => Some(Reliability::ReliableWithAckReceipt),
+            Reliability::ReliableOrdered | Reliability::ReliableOrderedWithAckReceipt => Some(Reliability::ReliableOrderedWithAckReceipt),
+            Reliability::UnreliableSequenced | Reliability::ReliableSequenced => None,
         }
     }
+
+    /// Returns the reliability without an ACK receipt, which is what goes on the wire: receipts
+    /// are only known to the sender.
+    pub fn without_ack_receipt(self) -> Reliability {
+        match self {
+            Reliability::UnreliableWithAckReceipt => Reliability::Unreliable,
+            Reliability::ReliableWithAckReceipt => Reliability::Reliable,
+            Reliability::ReliableOrderedWithAckReceipt => Reliability::ReliableOrdered,
+            reliability => reliability,
+        }
+    }
+}
+/// Priority decides which of the messages waiting to be sent goes first. Messages with immediate
+/// priority are sent right away, ahead of everything else. The others take turns: while all of
+/// them have messages waiting, high priority messages get twice the bandwidth of medium ones,
+/// which get twice that of low ones, so that no priority starves.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Priority {
+    Immediate,
+    High,
+    #[default]
+    Medium,
+    Low,
 }
+
 pub const SPLIT_FLAG: u8 = 0x10;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum PacketBitFlags {
     Datagram = 0x80,
     ACK = 0x40,
@@ -38,56 +88,165 @@ pub enum PacketBitFlags {
 
 // BasePacket is an encapsulation around every packet sent after the connection is
 // established.
+#[derive(Debug, Clone)]
 pub struct Packet {
     pub reliability: Reliability,
 
-    pub message_index: uint24,
-    pub sequence_index: uint24,
-    pub order_index: uint24,
+    pub message_index: U24,
+    pub sequence_index: U24,
+    pub order_index: U24,
+    pub order_channel: u8,
 
-    pub data: Vec<u8>,
+    pub data: Bytes,
     pub split: bool,
     pub split_count: u32,
     pub split_index: u32,
     pub split_id: u16,
+
+    /// Receipt of the message the packet is part of, if its sender asked for one. It isn't encoded.
+    pub receipt: Option<u32>,
 }
 
 impl Default for Packet {
     fn default() -> Self {
         Self {
             reliability: Reliability::Unreliable,
-            message_index: 0,
-            sequence_index: 0,
-            order_index: 0,
-            data: Vec::new(),
+            message_index: U24::default(),
+            sequence_index: U24::default(),
+            order_index: U24::default(),
+            order_channel: 0,
+            data: Bytes::new(),
             split: false,
             split_count: 0,
             split_index: 0,
             split_id: 0,
+            receipt: None,
         }
     }
 }
 
 impl Packet {
     pub fn reliable(&self) -> bool {
-        match self.reliability {
-            Reliability::Reliable | Reliability::ReliableOrdered | Reliability::ReliableSequenced => true,
-            _ => false,
-        }
+        matches!(self.reliability.without_ack_receipt(), Reliability::Reliable | Reliability::ReliableOrdered | Reliability::ReliableSequenced)
     }
 
     pub fn sequenced(&self) -> bool {
-        match self.reliability {
-            Reliability::ReliableSequenced | Reliability::UnreliableSequenced => true,
-            _ => false,
-        }
+        matches!(self.reliability, Reliability::ReliableSequenced | Reliability::UnreliableSequenced)
+    }
+
+    pub fn ordered(&self) -> bool {
+        self.reliability.without_ack_receipt() == Reliability::ReliableOrdered
     }
 
     pub fn sequenced_or_ordered(&self) -> bool {
-        match self.reliability {
-            Reliability::ReliableOrdered | Reliability::ReliableSequenced | Reliability::UnreliableSequenced => true,
-            _ => false,
+        self.sequenced() || self.ordered()
+    }
+
+    /// Returns the amount of bytes `write` appends to a buffer.
+    pub fn size(&self) -> usize {
+        let mut size = 1 + 2 + self.data.len();
+        if self.reliable() {
+            size += 3;
+        }
+        if self.sequenced() {
+            size += 3;
+        }
+        if self.sequenced_or_ordered() {
+            size += 3 + 1;
+        }
+        if self.split {
+            size += 4 + 2 + 4;
+        }
+        size
+    }
+
+    /// write encodes the packet as a frame inside a datagram and appends it to `buf`.
+    pub fn write(&self, buf: &mut impl BufMut) {
+        let mut header = (self.reliability.without_ack_receipt() as u8) << 5;
+        if self.split {
+            header |= SPLIT_FLAG;
+        }
+        buf.put_u8(header);
+        // the length is written in bits.
+        buf.put_u16((self.data.len() as u16) << 3);
+        if self.reliable() {
+            buf.put_slice(&self.message_index.to_le_bytes());
+        }
+        if self.sequenced() {
+            buf.put_slice(&self.sequence_index.to_le_bytes());
+        }
+        if self.sequenced_or_ordered() {
+            buf.put_slice(&self.order_index.to_le_bytes());
+            buf.put_u8(self.order_channel);
+        }
+        if self.split {
+            buf.put_u32(self.split_count);
+            buf.put_u16(self.split_id);
+            buf.put_u32(self.split_index);
+        }
+        buf.put_slice(&self.data);
+    }
+
+    /// read decodes a single frame from the start of `data`, returning the packet and the
+    /// amount of bytes it took up.
+    pub fn read(data: &[u8]) -> Result<(Self, usize), String> {
+        let (mut packet, content) = Self::read_header(data)?;
+        let size = content.end;
+        packet.data = Bytes::copy_from_slice(&data[content]);
+        Ok((packet, size))
+    }
+
+    /// read_bytes is like `read`, but the content of the packet is a slice of `data` rather than
+    /// a copy of it.
+    pub fn read_bytes(data: &Bytes) -> Result<(Self, usize), String> {
+        let (mut packet, content) = Self::read_header(data)?;
+        let size = content.end;
+        packet.data = data.slice(content);
+        Ok((packet, size))
+    }
+
+    // read_header decodes everything but the content of a frame, returning the range of `data`
+    // the content takes up.
+    fn read_header(data: &[u8]) -> Result<(Self, Range<usize>), String> {
+        if data.len() < 3 {
+            return Err("read packet: frame header too short".to_string());
+        }
+        let header = data[0];
+        let mut packet = Packet {
+            reliability: Reliability::try_from(header >> 5).map_err(|e| format!("read packet: {}", e))?,
+            split: header & SPLIT_FLAG != 0,
+            ..Default::default()
+        };
+        let length = (u16::from_be_bytes([data[1], data[2]]) >> 3) as usize;
+        if length == 0 {
+            return Err("read packet: frame has no content".to_string());
+        }
+
+        let mut offset = 3;
+        let mut take = |n: usize| -> Result<&[u8], String> {
+            if data.len() < offset + n {
+                return Err("read packet: frame is truncated".to_string());
+            }
+            offset += n;
+            Ok(&data[offset - n..offset])
+        };
+        if packet.reliable() {
+            packet.message_index = U24::read(take(3)?);
+        }
+        if packet.sequenced() {
+            packet.sequence_index = U24::read(take(3)?);
+        }
+        if packet.sequenced_or_ordered() {
+            packet.order_index = U24::read(take(3)?);
+            packet.order_channel = take(1)?[0];
+        }
+        if packet.split {
+            packet.split_count = u32::from_be_bytes(take(4)?.try_into().unwrap());
+            packet.split_id = u16::from_be_bytes(take(2)?.try_into().unwrap());
+            packet.split_index = u32::from_be_bytes(take(4)?.try_into().unwrap());
         }
+        take(length)?;
+        Ok((packet, offset - length..offset))
     }
 }
 
@@ -105,7 +264,9 @@ const PACKET_ADDITIONAL_SIZE: u8 = 1 + 3 + 1 + 2 + 3 + 3 + 1;
 // Packet split index
 const SPLIT_ADDITIONAL_SIZE: u8 = 4 + 2 + 4;
 
-pub fn split_packet(data: &[u8], mtu: u16) -> Vec<Vec<u8>> {
+/// split_packet splits a message into fragments that each fit in a frame of a datagram of `mtu`
+/// bytes. The fragments are slices of `data`, nothing is copied.
+pub fn split_packet(data: &Bytes, mtu: u16) -> Vec<Bytes> {
     let size = data.len();
 
     let mut max_size = mtu as usize - PACKET_ADDITIONAL_SIZE as usize;
@@ -119,7 +280,7 @@ pub fn split_packet(data: &[u8], mtu: u16) -> Vec<Vec<u8>> {
     for i in 0..total_fragments {
         let start = i * max_size;
         let end = min(start + max_size, size);
-        fragments.push(data[start..end].to_vec())
+        fragments.push(data.slice(start..end))
     }
 
     fragments
@@ -1,11 +1,12 @@
 use std::fmt::Debug;
-use crate::types::uint24;
+use bytes::Bytes;
+use crate::types::U24;
 
 /// PacketQueue is an ordered queue for reliable ordered packets.
 pub struct PacketQueue {
-    pub lowest: uint24,
-    pub highest: uint24,
-    pub queue: std::collections::HashMap<uint24, Vec<u8>>,
+    pub lowest: U24,
+    pub highest: U24,
+    pub queue: std::collections::HashMap<U24, Bytes>,
 }
 
 impl Default for PacketQueue {
@@ -27,23 +28,24 @@ impl Debug for PacketQueue {
 impl PacketQueue {
     pub(crate) fn new() -> PacketQueue {
         PacketQueue {
-            lowest: 0,
-            highest: 0,
+            lowest: U24::default(),
+            highest: U24::default(),
             queue: std::collections::HashMap::new(),
         }
     }
 
     /// put puts a value at the index passed. If the index was already occupied
     /// once, false is returned.
-    pub fn put(self: &mut PacketQueue, index: uint24, buffer: Vec<u8>) -> bool {
+    pub fn put(self: &mut PacketQueue, index: U24, buffer: Bytes) -> bool {
         if index < self.lowest {
-            self.lowest = index;
+            // already fetched before.
+            return false;
         }
         if self.queue.contains_key(&index) {
             return false;
         }
         if index >= self.highest {
-            self.highest = index + 1;
+            self.highest = index.next();
         }
         self.queue.insert(index, buffer);
         true
@@ -52,26 +54,19 @@ impl PacketQueue {
     /// fetch attempts to take out as many values from the ordered queue as
     /// possible. Upon encountering an index that has no value yet, the function
     /// returns all values that it did find and takes them out.
-    pub fn fetch(self: &mut PacketQueue) -> Vec<Vec<u8>> {
+    pub fn fetch(self: &mut PacketQueue) -> Vec<Bytes> {
         let mut packets = Vec::new();
         let mut i = self.lowest;
         while i < self.highest {
-            let asasd = self.queue.clone();
-            let buffer = match asasd.get(&i) {
+            let buffer = match self.queue.remove(&i) {
                 Some(buffer) => buffer,
                 None => break,
             };
-            self.queue.remove(&i);
-            packets.push(buffer.clone());
-            i += 1;
+            packets.push(buffer);
+            i = i.next();
         }
         self.lowest = i;
 
         packets
     }
-
-    /// window_size returns the size of the window held by the packet queue.
-    pub fn window_size(self: &PacketQueue) -> uint24 {
-        self.highest - self.lowest
-    }
 }
\ No newline at end of file
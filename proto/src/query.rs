@@ -0,0 +1,156 @@
+use std::fmt::{Debug, Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::net::{lookup_host, ToSocketAddrs, UdpSocket};
+use tokio::time::timeout_at;
+use crate::messages::unconnected_ping::UnconnectedPing;
+use crate::types::Packet;
+use crate::{PacketT, ReadPacket};
+
+// amount of unconnected pings sent to a target before giving up, spread evenly over the timeout.
+const PING_ATTEMPTS: u32 = 3;
+
+/// Status is the parsed form of the string a server sends in its `UnconnectedPong`, e.g.
+/// `MCPE;Dedicated Server;786;1.21.73;0;10;11954621141260796043;Bedrock level;Survival;1;19132;19133;0;`
+#[derive(Clone, Default, PartialEq)]
+pub struct Status {
+    pub edition: String,
+    pub motd: String,
+    pub protocol_version: u32,
+    pub version: String,
+    pub player_count: u32,
+    pub max_player_count: u32,
+    /// Left out by some servers, so that only a GUID that is there tells the server apart.
+    pub server_guid: Option<u64>,
+    pub sub_motd: String,
+    pub game_mode: String,
+    pub game_mode_numeric: Option<u8>,
+    pub port_v4: Option<u16>,
+    pub port_v6: Option<u16>,
+}
+
+impl Debug for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Status {{ edition: {:?}, motd: {:?}, protocol_version: {}, version: {:?}, player_count: {}, max_player_count: {}, server_guid: {:?}, sub_motd: {:?}, game_mode: {:?}, game_mode_numeric: {:?}, port_v4: {:?}, port_v6: {:?} }}",
+               self.edition, self.motd, self.protocol_version, self.version, self.player_count, self.max_player_count, self.server_guid, self.sub_motd, self.game_mode, self.game_mode_numeric, self.port_v4, self.port_v6)
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+
+    /// Parses a pong string. Only the first six fields are required, servers other than vanilla
+    /// Bedrock frequently leave out the trailing ones. Of those, only a server GUID that doesn't
+    /// parse is an error, as it would be mistaken for another server's.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split(';').collect();
+        if fields.len() < 6 {
+            return Err(format!("Invalid status: expected at least 6 fields, got {}", fields.len()));
+        }
+        let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+        let number = |i: usize, name: &str| field(i).parse().map_err(|_| format!("Invalid status: bad {} {:?}", name, field(i)));
+
+        Ok(Status {
+            edition: field(0).to_string(),
+            motd: field(1).to_string(),
+            protocol_version: number(2, "protocol version")?,
+            version: field(3).to_string(),
+            player_count: number(4, "player count")?,
+            max_player_count: number(5, "max player count")?,
+            server_guid: match field(6) {
+                "" => None,
+                guid => Some(guid.parse().map_err(|_| format!("Invalid status: bad server guid {:?}", guid))?),
+            },
+            sub_motd: field(7).to_string(),
+            game_mode: field(8).to_string(),
+            game_mode_numeric: field(9).parse().ok(),
+            port_v4: field(10).parse().ok(),
+            port_v6: field(11).parse().ok(),
+        })
+    }
+}
+
+impl Display for Status {
+    /// Formats the status back into the string sent in an `UnconnectedPong`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let optional = |v: Option<String>| v.unwrap_or_default();
+        write!(
+            f,
+            "{};{};{};{};{};{};{};{};{};{};{};{};0;",
+            self.edition, self.motd, self.protocol_version, self.version, self.player_count, self.max_player_count,
+            optional(self.server_guid.map(|v| v.to_string())), self.sub_motd, self.game_mode,
+            optional(self.game_mode_numeric.map(|v| v.to_string())),
+            optional(self.port_v4.map(|v| v.to_string())),
+            optional(self.port_v6.map(|v| v.to_string())),
+        )
+    }
+}
+
+/// Pings a server with unconnected pings and waits for its pong. Pings are retried a few times
+/// within `timeout` in case one gets lost. The returned latency is measured from the
+/// `client_send_time_be` echoed back by the server, so a late reply to an earlier ping still
+/// reports the right round trip.
+pub async fn ping<A: ToSocketAddrs>(address: A, timeout: Duration) -> io::Result<(Status, Duration)> {
+    let target = lookup_host(address).await?.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "ping: address did not resolve")
+    })?;
+    ping_addr(target, timeout).await
+}
+
+/// Pings every address concurrently, see `ping`. Results are returned in the order of `addresses`.
+pub async fn ping_all(addresses: Vec<String>, timeout: Duration) -> Vec<(String, io::Result<(Status, Duration)>)> {
+    let tasks: Vec<_> = addresses.iter()
+        .map(|address| tokio::spawn(ping(address.clone(), timeout)))
+        .collect();
+
+    let mut results = Vec::with_capacity(addresses.len());
+    for (address, task) in addresses.into_iter().zip(tasks) {
+        let result = task.await.unwrap_or_else(|e| Err(io::Error::other(e)));
+        results.push((address, result));
+    }
+    results
+}
+
+async fn ping_addr(target: SocketAddr, timeout: Duration) -> io::Result<(Status, Duration)> {
+    let local: SocketAddr = if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }.parse().unwrap();
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(target).await?;
+
+    let start = Instant::now();
+    let deadline = start + timeout;
+    let interval = timeout / PING_ATTEMPTS;
+    let guid: u64 = rand::random();
+    let mut buf = [0u8; 1492];
+
+    let mut attempt = 0;
+    let mut next_attempt = start;
+    loop {
+        if attempt < PING_ATTEMPTS && Instant::now() >= next_attempt {
+            // the send time is relative to the start of the ping so the latency doesn't depend on the wall clock.
+            let ping = UnconnectedPing { client_send_time_be: start.elapsed().as_micros() as u64, client_guid_be: guid };
+            socket.send(&ping.serialize()).await?;
+            attempt += 1;
+            next_attempt += interval;
+        }
+
+        let wait_until = if attempt < PING_ATTEMPTS { next_attempt.min(deadline) } else { deadline };
+        let len = match timeout_at(wait_until.into(), socket.recv(&mut buf)).await {
+            Ok(res) => res?,
+            Err(_) if Instant::now() >= deadline => {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("ping: no response from {} within {:?}", target, timeout)))
+            }
+            Err(_) => continue,
+        };
+        if len == 0 {
+            continue;
+        }
+        if let Ok(Some(PacketT::UnconnectedPong(pong))) = ReadPacket(&buf[..len]) {
+            let sent = Duration::from_micros(pong.client_send_time_be);
+            let latency = start.elapsed().checked_sub(sent).unwrap_or_default();
+            let status = pong.data.parse::<Status>().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            return Ok((status, latency));
+        }
+    }
+}
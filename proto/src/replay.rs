@@ -0,0 +1,151 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use crate::capture::CapturedDatagram;
+use crate::clock::{Clock, SystemClock};
+use crate::conn::Conn;
+use crate::messages::open_connection_request_2::OpenConnectionRequest2;
+use crate::session::{Event, Session};
+use crate::transport::MemoryNetwork;
+use crate::types::{Packet, PacketId};
+use crate::MTU_SIZE;
+
+// how long a replay into a connection waits for messages once every datagram was fed to it.
+const DRAIN_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Timing is how fast datagrams are replayed into a connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Timing {
+    /// With the time that passed between them when they were captured.
+    Original,
+    /// One after the other, as fast as the connection takes them.
+    Fast,
+}
+
+/// ReplayReport is what a connection made of the datagrams replayed into it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Datagrams replayed.
+    pub datagrams: usize,
+    /// Messages passed on to the application, in the order they were passed on.
+    pub messages: Vec<Bytes>,
+    /// Datagrams that could not be handled, by their index among those replayed, with the error.
+    pub errors: Vec<(usize, String)>,
+    /// Why the connection closed, if it did.
+    pub closed: Option<String>,
+}
+
+/// Returns the datagrams of a capture sent by `from`, or by whoever sent the first datagram if
+/// `from` is None.
+pub fn side(datagrams: &[CapturedDatagram], from: Option<SocketAddr>) -> Vec<CapturedDatagram> {
+    let Some(from) = from.or_else(|| datagrams.first().map(|datagram| datagram.src)) else {
+        return Vec::new();
+    };
+    datagrams.iter().filter(|datagram| datagram.src == from).cloned().collect()
+}
+
+/// Creates a session for the other end of the datagrams sent by one side of a conversation: a
+/// server session with the MTU the client asked for if they are sent by a client, a client
+/// session otherwise.
+pub fn session_for(datagrams: &[CapturedDatagram], now: Instant) -> Option<Session> {
+    let remote = datagrams.first()?.src;
+    let is_client = datagrams.iter().any(|datagram| {
+        matches!(datagram.data.first().map(|id| PacketId::from(*id)), Some(PacketId::OpenConnectionRequest1 | PacketId::OpenConnectionRequest2))
+    });
+    if !is_client {
+        return Some(Session::client(now, remote, rand::random()));
+    }
+    let max_transmission_unit = datagrams.iter()
+        .filter(|datagram| datagram.data.first() == Some(&(PacketId::OpenConnectionRequest2 as u8)))
+        .find_map(|datagram| OpenConnectionRequest2::deserialize(&datagram.data[1..]).ok())
+        .map_or(MTU_SIZE, |request| request.max_transmission_unit.min(MTU_SIZE));
+    Some(Session::server(now, remote, rand::random(), max_transmission_unit))
+}
+
+/// Replays datagrams into a session, with time passing as it did when they were captured, so that
+/// a replay always has the same outcome. The timers of the session run between datagrams, and
+/// what the session sends is thrown away. Unlike a `Conn`, the session is not closed when a
+/// datagram can't be handled, so that every datagram that can't be is reported.
+pub fn replay_session(session: &mut Session, start: Instant, datagrams: &[CapturedDatagram]) -> ReplayReport {
+    let mut report = ReplayReport { datagrams: datagrams.len(), ..Default::default() };
+    let Some(first) = datagrams.first().map(|datagram| datagram.time) else {
+        return report;
+    };
+    for (i, datagram) in datagrams.iter().enumerate() {
+        let now = start + datagram.time.duration_since(first).unwrap_or_default();
+        while let Some(deadline) = session.poll_timeout().filter(|deadline| *deadline <= now) {
+            session.handle_timeout(deadline);
+        }
+        if let Err(e) = session.handle_datagram(now, &datagram.data) {
+            report.errors.push((i, e));
+        }
+        while session.poll_transmit().is_some() {}
+        while let Some(event) = session.poll_event() {
+            match event {
+                Event::Message(data) => report.messages.push(data),
+                Event::Disconnected(reason) => report.closed = Some(format!("{:?}", reason)),
+                Event::Connected | Event::Delivered(_) | Event::Lost(_) => {}
+            }
+        }
+    }
+    report
+}
+
+/// Replays datagrams into a connection through `ReceivePacket`, like it would receive them from
+/// the network, reading the messages it passes on. The connection must not be read from by
+/// anything else while it is replayed into.
+pub async fn replay_conn(conn: &Conn, datagrams: &[CapturedDatagram], timing: Timing) -> ReplayReport {
+    let mut errors = Vec::new();
+    let mut messages = Vec::new();
+    let mut closed = None;
+    {
+        let feed = async {
+            let start = tokio::time::Instant::now();
+            let first = datagrams.first().map(|datagram| datagram.time);
+            for (i, datagram) in datagrams.iter().enumerate() {
+                if let (Timing::Original, Some(first)) = (timing, first) {
+                    tokio::time::sleep_until(start + datagram.time.duration_since(first).unwrap_or_default()).await;
+                }
+                if let Err(e) = conn.ReceivePacket(Bytes::copy_from_slice(&datagram.data)).await {
+                    errors.push((i, e));
+                }
+            }
+        };
+        tokio::pin!(feed);
+        let mut fed = false;
+        loop {
+            tokio::select! {
+                _ = &mut feed, if !fed => fed = true,
+                res = conn.ReadPacket() => match res {
+                    Ok(message) => messages.push(message),
+                    Err(e) => {
+                        closed = Some(e.to_string());
+                        break;
+                    }
+                },
+                // every message is passed on by the time ReceivePacket returns.
+                _ = tokio::time::sleep(DRAIN_TIMEOUT), if fed => break,
+            }
+        }
+    }
+    ReplayReport { datagrams: datagrams.len(), messages, errors, closed }
+}
+
+/// Replays the datagrams sent by one side of a conversation into a new connection for the other
+/// side, created by `session_for`. The connection runs on a memory network, so that nothing it
+/// sends leaves the process.
+pub async fn replay(datagrams: &[CapturedDatagram], timing: Timing) -> io::Result<ReplayReport> {
+    let clock = Arc::new(SystemClock);
+    let session = session_for(datagrams, clock.now()).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "replay: no datagrams to replay")
+    })?;
+    let network = MemoryNetwork::new();
+    let socket = network.bind(datagrams[0].dst)?;
+    let conn = Arc::new(Conn::new(Arc::new(socket), clock, session));
+    tokio::spawn(conn.clone().start_ticking());
+    let report = replay_conn(&conn, datagrams, timing).await;
+    conn.close().await?;
+    Ok(report)
+}
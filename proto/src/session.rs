@@ -0,0 +1,1108 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use bytes::{BufMut, Bytes, BytesMut};
+use crate::ack::{read_acknowledgement, write_acknowledgement};
+use crate::address::Address;
+use crate::frame::Window;
+use crate::messages::connected_ping::ConnectedPing;
+use crate::messages::connected_pong::ConnectedPong;
+use crate::messages::connection_request::ConnectionRequest;
+use crate::messages::connection_request_accepted::ConnectionRequestAccepted;
+use crate::messages::disconnect_notification::DisconnectNotification;
+use crate::messages::new_incoming_connection::NewIncomingConnection;
+use crate::messages::open_connection_request_1::OpenConnectionRequest1;
+use crate::messages::open_connection_request_2::OpenConnectionRequest2;
+use crate::packet::{split_packet, Packet as Frame, PacketBitFlags, Priority, Reliability};
+use crate::stats::Stats;
+use crate::packet_queue::PacketQueue;
+use crate::types::{Packet, PacketId, U24};
+use crate::{PacketT, ReadPacket, DEFAULT_PROTOCOL_VERSION, MAX_WINDOW_SIZE, MIN_MTU_SIZE, MTU_SIZE, NUMBER_OF_ARRANGED_STREAMS, UDP_HEADER_SIZE};
+
+/// TICK_INTERVAL is the interval at which a session flushes ACKs, checks for resends and pings.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(100);
+/// FLUSH_INTERVAL is how long messages that aren't sent immediately are buffered at most, so that
+/// the messages sent in the meantime share a datagram.
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+// datagram flags and sequence number.
+const DATAGRAM_HEADER_SIZE: usize = 1 + 3;
+// datagrams a high, medium and low priority queue may fill per turn.
+const PRIORITY_QUANTA: [usize; 3] = [4, 2, 1];
+// maximum amount of reliable datagrams awaiting an ACK. Messages wait in their queues beyond
+// that, so a burst of bulk data can't get further ahead of messages sent after it.
+const MAX_DATAGRAMS_IN_FLIGHT: usize = 128;
+// datagrams beyond MAX_DATAGRAMS_IN_FLIGHT that frames with immediate priority may still fill, so
+// that pings and the handshake don't wait behind bulk data without lifting the cap for them.
+const IMMEDIATE_ALLOWANCE: usize = 8;
+// resends wait at most 2^MAX_RESEND_BACKOFF times as long as the first one.
+const MAX_RESEND_BACKOFF: u32 = 4;
+// size of the allocations datagrams are written into, each holds a few dozen of them.
+const SEND_BUFFER_SIZE: usize = 64 * 1024;
+
+// MTU sizes the client tries during the offline handshake, each one MTU_ATTEMPTS times.
+const MTU_SIZES: [u16; 3] = [MTU_SIZE, 1200, MIN_MTU_SIZE];
+const MTU_ATTEMPTS: u32 = 4;
+// attempts at sending the second open connection request before giving up.
+const OPEN_CONNECTION_ATTEMPTS: u32 = 5;
+const HANDSHAKE_RESEND_INTERVAL: Duration = Duration::from_millis(500);
+
+// a connection is dropped after this long without any datagram from the other end.
+const TIMEOUT: Duration = Duration::from_secs(5);
+// a closing connection lingers at most this long to get its last packets acknowledged.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// round trip samples older than this are no longer taken into account.
+const RTT_SAMPLE_DURATION: Duration = Duration::from_secs(5);
+const DEFAULT_RTT: Duration = Duration::from_millis(50);
+
+// limits on split packets, so a peer can't make us buffer unbounded amounts of fragments. A sender
+// may have many split messages in flight at once, and on a lossy link they all wait for resends.
+const MAX_SPLIT_COUNT: u32 = 4096;
+const MAX_CONCURRENT_SPLITS: usize = 256;
+
+// amount of system addresses written in the connected handshake, as vanilla Bedrock does.
+const NUMBER_OF_SYSTEM_ADDRESSES: usize = 20;
+
+/// State is the stage of the connection a session is in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum State {
+    /// The client is sending open connection request 1 to find the MTU.
+    Discovering,
+    /// The client is sending open connection request 2.
+    Requesting,
+    /// The connected handshake (connection request, accepted, new incoming connection) is in progress.
+    Handshaking,
+    Connected,
+    /// A disconnect notification was sent and the session waits for outstanding ACKs.
+    Closing,
+    Closed,
+}
+
+/// DisconnectReason is the reason a session was closed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// `close` was called on the session.
+    Closed,
+    /// The other end sent a disconnect notification.
+    PeerClosed,
+    /// Nothing was received from the other end for too long.
+    TimedOut,
+    /// The server didn't answer the offline handshake.
+    HandshakeFailed,
+    /// The server runs a different RakNet protocol version.
+    IncompatibleProtocol,
+}
+
+/// Event is something the application should know about, returned by `Session::poll_event`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Connected,
+    /// A message sent by the other end, in the order it was sent if it was ordered.
+    Message(Bytes),
+    /// Every datagram carrying the message with this receipt was acknowledged by the other end.
+    Delivered(u32),
+    /// The message with this receipt was given up on: it was unreliable and got lost, or the
+    /// session closed before it was acknowledged.
+    Lost(u32),
+    Disconnected(DisconnectReason),
+}
+
+// ResendRecord holds the frames of a datagram that has not been acknowledged yet.
+struct ResendRecord {
+    frames: Vec<Frame>,
+    sent: Instant,
+    // how often the frames were resent before.
+    resends: u32,
+}
+
+// OrderedInFlight keeps track of the ordered messages of a channel that weren't acknowledged
+// entirely yet. The other end only holds MAX_WINDOW_SIZE of them back waiting for a missing one,
+// so messages further ahead of the oldest wait to be sent.
+#[derive(Default)]
+struct OrderedInFlight {
+    // order index of the first message of `frames_left`.
+    oldest: U24,
+    // the amount of frames of every message from `oldest` on that weren't acknowledged yet.
+    frames_left: VecDeque<usize>,
+}
+
+impl OrderedInFlight {
+    fn add(&mut self, order_index: U24, frames: usize) {
+        if self.frames_left.is_empty() {
+            self.oldest = order_index;
+        }
+        self.frames_left.push_back(frames);
+    }
+
+    fn acknowledge(&mut self, order_index: U24) {
+        if let Some(left) = self.frames_left.get_mut(self.oldest.distance(order_index) as usize) {
+            *left = left.saturating_sub(1);
+        }
+        while self.frames_left.front() == Some(&0) {
+            self.frames_left.pop_front();
+            self.oldest = self.oldest.next();
+        }
+    }
+
+    fn may_send(&self, order_index: U24) -> bool {
+        self.oldest.distance(order_index) < MAX_WINDOW_SIZE as u32
+    }
+}
+
+/// Session is the RakNet state machine of a single connection, without any I/O. Datagrams received
+/// from the other end are passed to `handle_datagram`, and `handle_timeout` is called once the time
+/// returned by `poll_timeout` has passed. Everything the session wants to send is taken out with
+/// `poll_transmit` and events with `poll_event`. Every method takes the current time, so a session
+/// behaves exactly the same no matter what drives it.
+pub struct Session {
+    pub remote: SocketAddr,
+    pub guid: u64,
+    pub is_server: bool,
+    // the GUID of the other end, once it told us.
+    peer_guid: Option<u64>,
+    state: State,
+    // timestamps sent in pings and handshake messages are relative to this.
+    epoch: Instant,
+    max_transmission_unit: u16,
+
+    // offline handshake, only used by clients.
+    mtu_index: usize,
+    handshake_attempts: u32,
+    next_handshake_send: Instant,
+
+    sequence_number: U24,
+    message_index: U24,
+    order_indices: [U24; NUMBER_OF_ARRANGED_STREAMS as usize],
+    sequence_indices: [U24; NUMBER_OF_ARRANGED_STREAMS as usize],
+    split_id: u16,
+    next_receipt: u32,
+    // the amount of frames of every message with a receipt that haven't been acknowledged yet.
+    receipts: HashMap<u32, usize>,
+    unacknowledged: HashMap<U24, ResendRecord>,
+    ordered_in_flight: Vec<OrderedInFlight>,
+    rtt_samples: VecDeque<(Instant, Duration)>,
+    round_trip_time: Duration,
+
+    window: Window,
+    ack_slice: Vec<U24>,
+    packet_queues: Vec<PacketQueue>,
+    highest_sequence_indices: [U24; NUMBER_OF_ARRANGED_STREAMS as usize],
+    received_messages: HashSet<U24>,
+    lowest_message_index: U24,
+    splits: HashMap<u16, Vec<Option<Bytes>>>,
+
+    last_activity: Instant,
+    next_tick: Instant,
+    tick_count: u64,
+    closing_since: Option<Instant>,
+    acks_left: usize,
+
+    // frames waiting to be put in datagrams: those with immediate priority, and the messages of
+    // the other priorities, whose frames are taken in turns by deficit round robin.
+    immediate: VecDeque<Frame>,
+    queues: [VecDeque<VecDeque<Frame>>; 3],
+    deficits: [usize; 3],
+    turn: usize,
+    queued_bytes: usize,
+    // when the queued frames are sent at the latest.
+    flush_at: Option<Instant>,
+
+    transmits: VecDeque<Bytes>,
+    // the datagrams of `transmits` are split off this buffer, which reuses its memory once they
+    // were all sent.
+    send_buf: BytesMut,
+    events: VecDeque<Event>,
+    // the counters and round trip times of the stats, the rest is filled in by `stats`.
+    stats: Stats,
+
+    pub limits_enabled: bool,
+    /// How long a datagram must be missing before it is NACKed. None uses 1.5 times the round trip
+    /// time, so that datagrams that are merely reordered aren't resent.
+    pub nack_delay: Option<Duration>,
+}
+
+impl Session {
+    fn new(now: Instant, remote: SocketAddr, guid: u64, is_server: bool, state: State, max_transmission_unit: u16) -> Self {
+        Self {
+            remote,
+            guid,
+            peer_guid: None,
+            is_server,
+            state,
+            epoch: now,
+            max_transmission_unit,
+
+            mtu_index: 0,
+            handshake_attempts: 0,
+            next_handshake_send: now,
+
+            sequence_number: U24::default(),
+            message_index: U24::default(),
+            order_indices: [U24::default(); NUMBER_OF_ARRANGED_STREAMS as usize],
+            sequence_indices: [U24::default(); NUMBER_OF_ARRANGED_STREAMS as usize],
+            split_id: 0,
+            next_receipt: 0,
+            receipts: HashMap::new(),
+            unacknowledged: HashMap::new(),
+            ordered_in_flight: (0..NUMBER_OF_ARRANGED_STREAMS).map(|_| OrderedInFlight::default()).collect(),
+            rtt_samples: VecDeque::new(),
+            round_trip_time: DEFAULT_RTT,
+
+            window: Window::new(),
+            ack_slice: Vec::new(),
+            packet_queues: (0..NUMBER_OF_ARRANGED_STREAMS).map(|_| PacketQueue::new()).collect(),
+            highest_sequence_indices: [U24::default(); NUMBER_OF_ARRANGED_STREAMS as usize],
+            received_messages: HashSet::new(),
+            lowest_message_index: U24::default(),
+            splits: HashMap::new(),
+
+            last_activity: now,
+            next_tick: now + TICK_INTERVAL,
+            tick_count: 0,
+            closing_since: None,
+            acks_left: 0,
+
+            immediate: VecDeque::new(),
+            queues: Default::default(),
+            deficits: [0; 3],
+            turn: 0,
+            queued_bytes: 0,
+            flush_at: None,
+
+            transmits: VecDeque::new(),
+            send_buf: BytesMut::with_capacity(SEND_BUFFER_SIZE),
+            events: VecDeque::new(),
+            stats: Stats::default(),
+
+            limits_enabled: true,
+            nack_delay: None,
+        }
+    }
+
+    /// Creates the session of a client connecting to `remote`. The offline handshake starts right
+    /// away, so the first open connection request is ready to be polled.
+    pub fn client(now: Instant, remote: SocketAddr, guid: u64) -> Self {
+        let mut session = Self::new(now, remote, guid, false, State::Discovering, MTU_SIZES[0]);
+        session.send_handshake(now);
+        session
+    }
+
+    /// Creates the session of a server for a client that completed the offline handshake with the
+    /// MTU agreed on in it, limited to the sizes a client may try. The session waits for the
+    /// client's connection request.
+    pub fn server(now: Instant, remote: SocketAddr, guid: u64, max_transmission_unit: u16) -> Self {
+        Self::new(now, remote, guid, true, State::Handshaking, max_transmission_unit.clamp(MIN_MTU_SIZE, MTU_SIZE))
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the GUID of the other end, known once the handshake got far enough for it to be sent.
+    pub fn peer_guid(&self) -> Option<u64> {
+        self.peer_guid
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == State::Connected
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state == State::Closed
+    }
+
+    pub fn max_transmission_unit(&self) -> u16 {
+        self.max_transmission_unit
+    }
+
+    pub fn effective_mtu(&self) -> u16 {
+        self.max_transmission_unit.saturating_sub(UDP_HEADER_SIZE as u16)
+    }
+
+    pub fn round_trip_time(&self) -> Duration {
+        self.round_trip_time
+    }
+
+    /// Returns the next datagram to send to the other end.
+    pub fn poll_transmit(&mut self) -> Option<Bytes> {
+        let datagram = self.transmits.pop_front()?;
+        self.stats.bytes_sent += datagram.len() as u64;
+        self.stats.datagrams_sent += 1;
+        Some(datagram)
+    }
+
+    /// Returns a snapshot of the stats of the session.
+    pub fn stats(&self) -> Stats {
+        let mut send_queue = [self.immediate.len() as u64, 0, 0, 0];
+        for (depth, queue) in send_queue[1..].iter_mut().zip(&self.queues) {
+            *depth = queue.iter().map(|message| message.len() as u64).sum();
+        }
+        Stats {
+            congestion_window: MAX_DATAGRAMS_IN_FLIGHT as u64,
+            in_flight: self.unacknowledged.len() as u64,
+            splits_in_progress: self.splits.len() as u64,
+            send_queue,
+            receive_queue: self.packet_queues.iter().map(|queue| queue.queue.len() as u64).sum(),
+            ..self.stats.clone()
+        }
+    }
+
+    pub fn poll_event(&mut self) -> Option<Event> {
+        self.events.pop_front()
+    }
+
+    /// Returns when `handle_timeout` should be called next, or None once the session is closed.
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        if self.state == State::Closed {
+            return None;
+        }
+        Some(self.flush_at.map_or(self.next_tick, |flush_at| flush_at.min(self.next_tick)))
+    }
+
+    /// Sends a message to the other end with medium priority. Messages too big for a single
+    /// datagram are split. The message is buffered together with the other messages sent until
+    /// the buffer fills a datagram, `flush` is called or FLUSH_INTERVAL has passed, whichever
+    /// comes first.
+    ///
+    /// A message sent with one of the reliabilities with an ACK receipt gets a receipt, which is
+    /// returned and later reported by either an `Event::Delivered` or an `Event::Lost`.
+    pub fn send(&mut self, now: Instant, data: &[u8], reliability: Reliability, channel: u8) -> Result<Option<u32>, String> {
+        self.send_with_priority(now, data, reliability, Priority::Medium, channel)
+    }
+
+    /// Sends a message like `send`, with the priority passed. The fragments of split messages
+    /// are interleaved with the other messages of the same priority.
+    pub fn send_with_priority(&mut self, now: Instant, data: &[u8], reliability: Reliability, priority: Priority, channel: u8) -> Result<Option<u32>, String> {
+        match self.state {
+            State::Handshaking | State::Connected => {}
+            state => return Err(format!("send: session is not connected ({:?})", state)),
+        }
+        self.send_message(now, Bytes::copy_from_slice(data), reliability, priority, channel)
+    }
+
+    /// Sends the buffered messages right away, as far as the datagrams in flight allow. The
+    /// remaining ones are sent as datagrams are acknowledged.
+    pub fn flush(&mut self, now: Instant) {
+        self.assemble(now, true);
+    }
+
+    /// Closes the session. A connected session notifies the other end and stays in the closing
+    /// state until the notification is acknowledged.
+    pub fn close(&mut self, now: Instant) {
+        match self.state {
+            State::Handshaking | State::Connected => {
+                // the notification must be the last message, so nothing queued is held back anymore.
+                while self.queued_bytes > 0 && self.send_queued_datagram(now) {}
+                let _ = self.send_now(now, DisconnectNotification {}.serialize().into(), Reliability::ReliableOrdered);
+                // nor is the notification, however much is in flight. Only ordered messages too far
+                // ahead of those the other end has wait for ACKs.
+                if self.queued_bytes > 0 {
+                    self.send_queued_datagram(now);
+                }
+                self.state = State::Closing;
+                self.closing_since = Some(now);
+            }
+            State::Discovering | State::Requesting => self.finish(DisconnectReason::Closed),
+            State::Closing | State::Closed => {}
+        }
+    }
+
+    /// Handles a datagram received from the other end.
+    pub fn handle_datagram(&mut self, now: Instant, data: &[u8]) -> Result<(), String> {
+        self.handle_datagram_bytes(now, Bytes::copy_from_slice(data))
+    }
+
+    /// Handles a datagram like `handle_datagram`. The messages it carries are passed on as slices
+    /// of `data`, without copying them.
+    pub fn handle_datagram_bytes(&mut self, now: Instant, data: Bytes) -> Result<(), String> {
+        if data.is_empty() || self.state == State::Closed {
+            return Ok(());
+        }
+        self.stats.bytes_received += data.len() as u64;
+        self.stats.datagrams_received += 1;
+        self.last_activity = now;
+        if data[0] & PacketBitFlags::Datagram as u8 == 0 {
+            return self.handle_offline(now, &data);
+        }
+        if data[0] & PacketBitFlags::ACK as u8 != 0 {
+            self.handle_ack(now, &data[1..])
+        } else if data[0] & PacketBitFlags::NACK as u8 != 0 {
+            self.handle_nack(now, &data[1..])
+        } else {
+            self.receive_datagram(now, data.slice(1..))
+        }
+    }
+
+    /// Runs everything that happens periodically: flushing buffered messages and ACKs, resending,
+    /// pinging and timeouts.
+    pub fn handle_timeout(&mut self, now: Instant) {
+        if self.state == State::Closed {
+            return;
+        }
+        if self.flush_at.is_some_and(|flush_at| now >= flush_at) {
+            self.flush(now);
+        }
+        if now < self.next_tick {
+            return;
+        }
+        self.next_tick += TICK_INTERVAL;
+        if self.next_tick <= now {
+            // we fell behind, don't try to catch up on every missed tick.
+            self.next_tick = now + TICK_INTERVAL;
+        }
+        self.tick_count += 1;
+
+        match self.state {
+            State::Discovering | State::Requesting => {
+                if now >= self.next_handshake_send {
+                    self.send_handshake(now);
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        self.flush_acks();
+        self.nack_missing(now);
+        if self.tick_count.is_multiple_of(3) {
+            self.check_resend(now);
+        }
+        if let Some(closing_since) = self.closing_since {
+            let before = self.acks_left;
+            self.acks_left = self.unacknowledged.len();
+            let since = now.saturating_duration_since(closing_since);
+            let sent = self.acks_left == 0 && self.queued_bytes == 0;
+            if (sent && (before != 0 || since > Duration::from_secs(1))) || since > CLOSE_TIMEOUT {
+                self.finish(DisconnectReason::Closed);
+            }
+            return;
+        }
+        if self.tick_count.is_multiple_of(5) {
+            if self.state == State::Connected {
+                // ping the other end periodically to prevent timeouts.
+                let ping = ConnectedPing { client_send_time_be: self.timestamp(now) };
+                let _ = self.send_now(now, ping.serialize().into(), Reliability::Unreliable);
+            }
+            if now.saturating_duration_since(self.last_activity) > TIMEOUT + self.round_trip_time * 2 {
+                self.finish(DisconnectReason::TimedOut);
+            }
+        }
+    }
+
+    // finish closes the session for good.
+    fn finish(&mut self, reason: DisconnectReason) {
+        if self.state == State::Closed {
+            return;
+        }
+        self.state = State::Closed;
+        self.unacknowledged.clear();
+        self.ordered_in_flight.iter_mut().for_each(|channel| channel.frames_left.clear());
+        self.immediate.clear();
+        self.queues.iter_mut().for_each(VecDeque::clear);
+        self.queued_bytes = 0;
+        self.flush_at = None;
+        let mut lost: Vec<u32> = self.receipts.drain().map(|(receipt, _)| receipt).collect();
+        lost.sort_unstable();
+        self.events.extend(lost.into_iter().map(Event::Lost));
+        self.events.push_back(Event::Disconnected(reason));
+    }
+
+    fn timestamp(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.epoch).as_millis() as u64
+    }
+
+    // send_handshake (re)sends the offline handshake request for the current state of the client.
+    fn send_handshake(&mut self, now: Instant) {
+        self.next_handshake_send = now + HANDSHAKE_RESEND_INTERVAL;
+        match self.state {
+            State::Discovering => {
+                if self.handshake_attempts == MTU_ATTEMPTS {
+                    self.handshake_attempts = 0;
+                    self.mtu_index += 1;
+                }
+                if self.mtu_index == MTU_SIZES.len() {
+                    self.finish(DisconnectReason::HandshakeFailed);
+                    return;
+                }
+                self.handshake_attempts += 1;
+                let request = OpenConnectionRequest1 {
+                    client_protocol: DEFAULT_PROTOCOL_VERSION,
+                    max_transmission_unit: MTU_SIZES[self.mtu_index],
+                };
+                self.transmits.push_back(request.serialize().into());
+            }
+            State::Requesting => {
+                if self.handshake_attempts == OPEN_CONNECTION_ATTEMPTS {
+                    self.finish(DisconnectReason::HandshakeFailed);
+                    return;
+                }
+                self.handshake_attempts += 1;
+                let request = OpenConnectionRequest2 {
+                    server_address: Address::from(self.remote),
+                    max_transmission_unit: self.max_transmission_unit,
+                    client_guid: self.guid,
+                    server_has_security: false,
+                    cookie: 0,
+                };
+                self.transmits.push_back(request.serialize().into());
+            }
+            _ => {}
+        }
+    }
+
+    // handle_offline handles the replies of the server during the offline handshake.
+    fn handle_offline(&mut self, now: Instant, data: &[u8]) -> Result<(), String> {
+        if self.is_server {
+            return Ok(());
+        }
+        match (self.state, ReadPacket(data)?) {
+            (State::Discovering, Some(PacketT::OpenConnectionReply1(reply))) => {
+                self.max_transmission_unit = reply.max_transmission_unit_be.clamp(MIN_MTU_SIZE, MTU_SIZES[self.mtu_index]);
+                self.state = State::Requesting;
+                self.handshake_attempts = 0;
+                self.send_handshake(now);
+            }
+            (State::Requesting, Some(PacketT::OpenConnectionReply2(reply))) => {
+                self.max_transmission_unit = reply.max_transmission_unit_be.clamp(MIN_MTU_SIZE, self.max_transmission_unit);
+                self.peer_guid = Some(reply.server_guid_be);
+                self.state = State::Handshaking;
+                let request = ConnectionRequest { client_guid_be: self.guid, request_time_be: self.timestamp(now), secure: false };
+                self.send_now(now, request.serialize().into(), Reliability::ReliableOrdered)?;
+            }
+            (State::Discovering | State::Requesting, Some(PacketT::IncompatibleProtocolVersion(_))) => {
+                self.finish(DisconnectReason::IncompatibleProtocol);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn handle_ack(&mut self, now: Instant, data: &[u8]) -> Result<(), String> {
+        for sequence_number in read_acknowledgement(data)? {
+            if let Some(record) = self.unacknowledged.remove(&sequence_number) {
+                if record.resends == 0 {
+                    // an ACK for resent frames may be for any of the datagrams they were sent in,
+                    // so it says nothing about the round trip time.
+                    let rtt = now.saturating_duration_since(record.sent);
+                    self.rtt_samples.push_back((now, rtt));
+                    self.stats.ack_latency = rtt;
+                    self.stats.record_rtt(rtt);
+                    self.stats.smoothed_rtt = if self.stats.smoothed_rtt.is_zero() {
+                        rtt
+                    } else {
+                        (self.stats.smoothed_rtt * 7 + rtt) / 8
+                    };
+                }
+                for frame in &record.frames {
+                    if let Some(receipt) = frame.receipt {
+                        self.acknowledge_receipt(receipt);
+                    }
+                    if frame.ordered() {
+                        self.ordered_in_flight[frame.order_channel as usize].acknowledge(frame.order_index);
+                    }
+                }
+            }
+        }
+        if self.queued_bytes > 0 && self.flush_at.is_none() {
+            // the queued messages were held back by the datagrams in flight.
+            self.assemble(now, true);
+        }
+        Ok(())
+    }
+
+    pub fn handle_nack(&mut self, now: Instant, data: &[u8]) -> Result<(), String> {
+        let missing = read_acknowledgement(data)?;
+        self.stats.nacks_received += missing.len() as u64;
+        self.resend(now, &missing);
+        Ok(())
+    }
+
+    /// Sends an ACK or NACK, depending on `flag`, for the sequence numbers passed, split over as
+    /// many datagrams as needed.
+    pub fn send_ack(&mut self, packets: &mut [U24], flag: PacketBitFlags) {
+        packets.sort_unstable();
+        let mut written = 0;
+        let mut last = None;
+        let unique: Vec<U24> = packets.iter().copied().filter(|p| last.replace(*p) != Some(*p)).collect();
+        while written < unique.len() {
+            let mut buf = Vec::with_capacity(self.effective_mtu() as usize);
+            buf.push(flag as u8 | PacketBitFlags::Datagram as u8);
+            written += write_acknowledgement(&mut buf, &unique[written..], self.effective_mtu() as usize);
+            self.transmits.push_back(buf.into());
+        }
+    }
+
+    pub fn send_nack(&mut self, missing: &mut [U24]) {
+        self.stats.nacks_sent += missing.len() as u64;
+        self.send_ack(missing, PacketBitFlags::NACK)
+    }
+
+    fn flush_acks(&mut self) {
+        if self.ack_slice.is_empty() {
+            return;
+        }
+        let mut acks = std::mem::take(&mut self.ack_slice);
+        self.send_ack(&mut acks, PacketBitFlags::ACK);
+    }
+
+    // nack_missing sends a NACK for the datagrams that have been missing for longer than the NACK delay.
+    fn nack_missing(&mut self, now: Instant) {
+        let delay = self.nack_delay.unwrap_or(self.round_trip_time + self.round_trip_time / 2);
+        let mut missing = self.window.missing(delay, now);
+        if !missing.is_empty() {
+            self.send_nack(&mut missing);
+        }
+    }
+
+    /// Updates the round trip time and resends every datagram that was not acknowledged in time,
+    /// even if no NACK was received for it.
+    pub fn check_resend(&mut self, now: Instant) {
+        while let Some((t, _)) = self.rtt_samples.front() {
+            if now.saturating_duration_since(*t) <= RTT_SAMPLE_DURATION {
+                break;
+            }
+            self.rtt_samples.pop_front();
+        }
+        self.round_trip_time = if self.rtt_samples.is_empty() {
+            DEFAULT_RTT
+        } else {
+            self.rtt_samples.iter().map(|(_, rtt)| *rtt).sum::<Duration>() / self.rtt_samples.len() as u32
+        };
+
+        let delay = self.round_trip_time + self.round_trip_time / 2;
+        let resend: Vec<U24> = self.unacknowledged.iter()
+            // every resend doubles the time to wait for the next, so that a congested link isn't
+            // flooded with copies of datagrams that are merely slow.
+            .filter(|(_, record)| now.saturating_duration_since(record.sent) > delay * (1 << record.resends.min(MAX_RESEND_BACKOFF)))
+            .map(|(sequence_number, _)| *sequence_number)
+            .collect();
+        self.resend(now, &resend);
+    }
+
+    // resend sends the reliable frames of lost datagrams again. Unreliable frames are only kept
+    // around for their receipts, which are lost with them.
+    fn resend(&mut self, now: Instant, sequence_numbers: &[U24]) {
+        for sequence_number in sequence_numbers {
+            if let Some(record) = self.unacknowledged.remove(sequence_number) {
+                let (frames, lost): (Vec<Frame>, Vec<Frame>) = record.frames.into_iter().partition(Frame::reliable);
+                for receipt in lost.iter().filter_map(|frame| frame.receipt) {
+                    self.lose_receipt(receipt);
+                }
+                if !frames.is_empty() {
+                    self.stats.resends += 1;
+                    self.send_datagram(now, frames, record.resends + 1);
+                }
+            }
+        }
+    }
+
+    fn acknowledge_receipt(&mut self, receipt: u32) {
+        if let Some(left) = self.receipts.get_mut(&receipt) {
+            *left -= 1;
+            if *left == 0 {
+                self.receipts.remove(&receipt);
+                self.events.push_back(Event::Delivered(receipt));
+            }
+        }
+    }
+
+    fn lose_receipt(&mut self, receipt: u32) {
+        if self.receipts.remove(&receipt).is_some() {
+            self.events.push_back(Event::Lost(receipt));
+        }
+    }
+
+    // send_now sends a message of the connection itself on the first channel with immediate
+    // priority: the handshake and pings would be slowed down by buffering, and pings would
+    // measure the wrong round trip time.
+    fn send_now(&mut self, now: Instant, data: Bytes, reliability: Reliability) -> Result<(), String> {
+        self.send_message(now, data, reliability, Priority::Immediate, 0)?;
+        Ok(())
+    }
+
+    // send_message splits a message into frames and queues them to be sent, returning the receipt
+    // of the message if it has one.
+    fn send_message(&mut self, now: Instant, data: Bytes, reliability: Reliability, priority: Priority, channel: u8) -> Result<Option<u32>, String> {
+        if channel >= NUMBER_OF_ARRANGED_STREAMS {
+            return Err(format!("send: invalid channel {}", channel));
+        }
+        if data.is_empty() {
+            return Err("send: message is empty".to_string());
+        }
+        *self.stats.messages_sent.entry(PacketId::from(data[0]) as u8).or_default() += 1;
+        let mut frame = Frame { reliability, order_channel: channel, ..Default::default() };
+        // sequenced frames carry an extra sequence index that isn't accounted for in the split size.
+        let mtu = if frame.sequenced() { self.effective_mtu() - 3 } else { self.effective_mtu() };
+        let fragments = split_packet(&data, mtu);
+        if fragments.len() > 1 {
+            // a split message can only be reassembled if every fragment arrives.
+            frame.reliability = match reliability {
+                Reliability::Unreliable => Reliability::Reliable,
+                Reliability::UnreliableSequenced => Reliability::ReliableSequenced,
+                Reliability::UnreliableWithAckReceipt => Reliability::ReliableWithAckReceipt,
+                reliability => reliability,
+            };
+            frame.split = true;
+            frame.split_count = fragments.len() as u32;
+            frame.split_id = self.split_id;
+            self.split_id = self.split_id.wrapping_add(1);
+        }
+
+        let receipt = reliability.ack_receipt().then(|| {
+            let receipt = self.next_receipt;
+            self.next_receipt = self.next_receipt.wrapping_add(1);
+            self.receipts.insert(receipt, fragments.len());
+            receipt
+        });
+        frame.receipt = receipt;
+
+        let channel = channel as usize;
+        if frame.sequenced() {
+            frame.sequence_index = self.sequence_indices[channel].inc();
+            frame.order_index = self.order_indices[channel];
+        } else if frame.sequenced_or_ordered() {
+            frame.order_index = self.order_indices[channel].inc();
+            self.ordered_in_flight[channel].add(frame.order_index, fragments.len());
+        }
+
+        let mut message = VecDeque::with_capacity(fragments.len());
+        for (split_index, content) in fragments.into_iter().enumerate() {
+            let mut frame = Frame { data: content, split_index: split_index as u32, ..frame.clone() };
+            if frame.reliable() {
+                frame.message_index = self.message_index.inc();
+            }
+            self.queued_bytes += frame.size();
+            message.push_back(frame);
+        }
+        if self.flush_at.is_none() {
+            self.flush_at = Some(now + FLUSH_INTERVAL);
+        }
+        match priority {
+            Priority::Immediate => {
+                self.immediate.extend(message);
+                self.assemble(now, true);
+            }
+            priority => {
+                self.queues[priority as usize - 1].push_back(message);
+                self.assemble(now, false);
+            }
+        }
+        Ok(receipt)
+    }
+
+    // assemble puts queued frames into datagrams and sends them, until the queues are empty or
+    // too many datagrams are in flight. Unless `partial` is set, it stops once the frames left
+    // don't fill a datagram anymore, so that they can be sent together with later ones.
+    fn assemble(&mut self, now: Instant, partial: bool) {
+        let mtu = self.effective_mtu() as usize;
+        while self.queued_bytes > 0 && self.unacknowledged.len() < self.max_in_flight() {
+            if !partial && DATAGRAM_HEADER_SIZE + self.queued_bytes <= mtu {
+                return;
+            }
+            if !self.send_queued_datagram(now) {
+                break;
+            }
+        }
+        // whatever is left waits for ACKs to make room.
+        self.flush_at = None;
+    }
+
+    // max_in_flight is how many reliable datagrams may await an ACK before assemble stops.
+    fn max_in_flight(&self) -> usize {
+        if self.immediate.is_empty() {
+            MAX_DATAGRAMS_IN_FLIGHT
+        } else {
+            MAX_DATAGRAMS_IN_FLIGHT + IMMEDIATE_ALLOWANCE
+        }
+    }
+
+    // send_queued_datagram sends a datagram filled with the next frames to send, returning false
+    // if none of the queued frames may be sent yet.
+    fn send_queued_datagram(&mut self, now: Instant) -> bool {
+        let mtu = self.effective_mtu() as usize;
+        let mut frames = Vec::new();
+        let mut size = DATAGRAM_HEADER_SIZE;
+        while let Some(frame) = self.next_frame(mtu - size) {
+            size += frame.size();
+            frames.push(frame);
+        }
+        if frames.is_empty() {
+            return false;
+        }
+        self.send_datagram(now, frames, 0);
+        true
+    }
+
+    // next_frame takes the next frame to send out of the queues, if it fits in `space`. Frames
+    // with immediate priority go first, then the other queues take turns, each sending at most
+    // its share of bytes per turn. Within a queue the messages take turns too, skipping those
+    // that wait for ACKs.
+    fn next_frame(&mut self, space: usize) -> Option<Frame> {
+        if let Some(frame) = self.immediate.front().filter(|frame| self.may_send(frame)) {
+            if frame.size() > space {
+                return None;
+            }
+            self.queued_bytes -= frame.size();
+            return self.immediate.pop_front();
+        }
+        if self.queued_bytes == 0 {
+            return None;
+        }
+        // turns in a row in which the queue was idle or all of its messages waited for ACKs.
+        let mut idle = 0;
+        loop {
+            let turn = self.turn;
+            let size = if self.sendable(turn) { self.queues[turn].front().and_then(VecDeque::front).map(Frame::size) } else { None };
+            match size {
+                Some(size) if size <= self.deficits[turn] => {
+                    if size > space {
+                        return None;
+                    }
+                    let mut message = self.queues[turn].pop_front()?;
+                    let frame = message.pop_front();
+                    if !message.is_empty() {
+                        self.queues[turn].push_back(message);
+                    }
+                    self.deficits[turn] -= size;
+                    self.queued_bytes -= size;
+                    return frame;
+                }
+                Some(_) => idle = 0,
+                // an idle queue doesn't save up its share.
+                None => {
+                    self.deficits[turn] = 0;
+                    idle += 1;
+                    if idle == PRIORITY_QUANTA.len() {
+                        return None;
+                    }
+                }
+            }
+            // frames are never larger than a datagram, so every queue that may send gets to in its turn.
+            self.turn = (turn + 1) % PRIORITY_QUANTA.len();
+            self.deficits[self.turn] += PRIORITY_QUANTA[self.turn] * self.effective_mtu() as usize;
+        }
+    }
+
+    // sendable moves the messages of a queue that wait for ACKs to its back until one that may be
+    // sent is at its front, returning false if there is none.
+    fn sendable(&mut self, turn: usize) -> bool {
+        for _ in 0..self.queues[turn].len() {
+            if self.may_send(&self.queues[turn][0][0]) {
+                return true;
+            }
+            self.queues[turn].rotate_left(1);
+        }
+        false
+    }
+
+    // may_send returns false for ordered frames too far ahead of the oldest message of their
+    // channel that wasn't acknowledged yet.
+    fn may_send(&self, frame: &Frame) -> bool {
+        !frame.ordered() || self.ordered_in_flight[frame.order_channel as usize].may_send(frame.order_index)
+    }
+
+    // send_datagram writes the frames passed into a single datagram and keeps track of the ones
+    // that have to be resent when it is lost.
+    fn send_datagram(&mut self, now: Instant, mut frames: Vec<Frame>, resends: u32) {
+        let sequence_number = self.sequence_number.inc();
+
+        let mtu = self.effective_mtu() as usize;
+        let buf = &mut self.send_buf;
+        buf.reserve(mtu);
+        buf.put_u8(PacketBitFlags::Datagram as u8 | PacketBitFlags::NeedsBAndAS as u8);
+        buf.put_slice(&sequence_number.to_le_bytes());
+        for frame in &frames {
+            frame.write(buf);
+            self.stats.frames_sent[frame.reliability as usize] += 1;
+        }
+        self.transmits.push_back(buf.split().freeze());
+
+        frames.retain(|frame| frame.reliable() || frame.receipt.is_some());
+        if !frames.is_empty() {
+            self.unacknowledged.insert(sequence_number, ResendRecord { frames, sent: now, resends });
+        }
+    }
+
+    fn receive_datagram(&mut self, now: Instant, data: Bytes) -> Result<(), String> {
+        if data.len() < 3 {
+            return Err("receive datagram: missing sequence number".to_string());
+        }
+        let sequence_number = U24::read(&data);
+        match self.window.add(sequence_number, now) {
+            Ok(true) => {}
+            Ok(false) => {
+                self.stats.duplicate_datagrams += 1;
+                return Ok(());
+            }
+            // a datagram so far ahead of a missing one that we can't keep track of it. It isn't
+            // acknowledged, so the sender resends it later.
+            Err(_) => return Ok(()),
+        }
+        self.ack_slice.push(sequence_number);
+
+        if self.window.shift() == 0 {
+            self.nack_missing(now);
+        }
+        self.handle_datagram_frames(now, data.slice(3..))
+    }
+
+    fn handle_datagram_frames(&mut self, now: Instant, mut data: Bytes) -> Result<(), String> {
+        while !data.is_empty() {
+            let (frame, n) = Frame::read_bytes(&data)?;
+            self.stats.frames_received[frame.reliability as usize] += 1;
+            data = data.slice(n..);
+            if frame.reliable() && !self.new_message(frame.message_index)? {
+                // the frame was resent after it had already arrived.
+                continue;
+            }
+            if frame.split {
+                self.receive_split_frame(now, frame)?;
+            } else {
+                self.receive_frame(now, frame)?;
+            }
+        }
+        Ok(())
+    }
+
+    // new_message marks a reliable message index as received, returning false if it was already received.
+    fn new_message(&mut self, message_index: U24) -> Result<bool, String> {
+        if message_index < self.lowest_message_index || !self.received_messages.insert(message_index) {
+            return Ok(false);
+        }
+        while self.received_messages.remove(&self.lowest_message_index) {
+            self.lowest_message_index = self.lowest_message_index.next();
+        }
+        if self.received_messages.len() > MAX_WINDOW_SIZE as usize * 8 && self.limits_enabled {
+            return Err("receive frame: too many reliable messages missing".to_string());
+        }
+        Ok(true)
+    }
+
+    fn receive_split_frame(&mut self, now: Instant, frame: Frame) -> Result<(), String> {
+        if frame.split_count > MAX_SPLIT_COUNT && self.limits_enabled {
+            return Err(format!("receive split frame: split count {} exceeds the maximum of {}", frame.split_count, MAX_SPLIT_COUNT));
+        }
+        if frame.split_index >= frame.split_count {
+            return Err(format!("receive split frame: split index {} out of range ({})", frame.split_index, frame.split_count));
+        }
+        if !self.splits.contains_key(&frame.split_id) && self.splits.len() >= MAX_CONCURRENT_SPLITS && self.limits_enabled {
+            return Err(format!("receive split frame: more than {} split packets in progress", MAX_CONCURRENT_SPLITS));
+        }
+        let fragments = self.splits.entry(frame.split_id).or_insert_with(|| vec![None; frame.split_count as usize]);
+        if fragments.len() != frame.split_count as usize {
+            return Err(format!("receive split frame: split count changed from {} to {}", fragments.len(), frame.split_count));
+        }
+        let index = frame.split_index as usize;
+        fragments[index] = Some(frame.data.clone());
+        if fragments.iter().any(Option::is_none) {
+            return Ok(());
+        }
+
+        let fragments = self.splits.remove(&frame.split_id).unwrap_or_default();
+        let mut data = BytesMut::with_capacity(fragments.iter().flatten().map(Bytes::len).sum());
+        for fragment in fragments.into_iter().flatten() {
+            data.put(fragment);
+        }
+        self.receive_frame(now, Frame { data: data.freeze(), split: false, ..frame })
+    }
+
+    // receive_frame delivers a complete frame according to its reliability.
+    fn receive_frame(&mut self, now: Instant, frame: Frame) -> Result<(), String> {
+        let channel = frame.order_channel as usize;
+        if frame.sequenced_or_ordered() && channel >= NUMBER_OF_ARRANGED_STREAMS as usize {
+            return Err(format!("receive frame: invalid order channel {}", channel));
+        }
+        if frame.sequenced() {
+            if frame.sequence_index < self.highest_sequence_indices[channel] {
+                // a newer message on this channel already arrived.
+                return Ok(());
+            }
+            self.highest_sequence_indices[channel] = frame.sequence_index.next();
+            return self.handle_message(now, frame.data);
+        }
+        if !frame.ordered() {
+            return self.handle_message(now, frame.data);
+        }
+
+        let queue = &mut self.packet_queues[channel];
+        // checked before the message is queued, so that the one filling the gap always gets in.
+        if frame.order_index >= queue.lowest && queue.lowest.distance(frame.order_index) >= MAX_WINDOW_SIZE as u32 && self.limits_enabled {
+            return Err(format!("receive frame: order index {} is too far ahead of {}", frame.order_index, queue.lowest));
+        }
+        if !queue.put(frame.order_index, frame.data) {
+            // an ordered message arrived twice.
+            return Ok(());
+        }
+        for data in queue.fetch() {
+            self.handle_message(now, data)?;
+        }
+        Ok(())
+    }
+
+    // handle_message handles the messages of the connected handshake and connection upkeep, and
+    // passes every other message on to the application.
+    fn handle_message(&mut self, now: Instant, data: Bytes) -> Result<(), String> {
+        if data.is_empty() || matches!(self.state, State::Closing | State::Closed) {
+            return Ok(());
+        }
+        let id = PacketId::from(data[0]);
+        *self.stats.messages_received.entry(id as u8).or_default() += 1;
+        match id {
+            PacketId::ConnectionRequest if self.is_server && self.state == State::Handshaking => {
+                if let Some(PacketT::ConnectionRequest(request)) = ReadPacket(&data)? {
+                    self.peer_guid = Some(request.client_guid_be);
+                    let accepted = ConnectionRequestAccepted {
+                        client_address: Address::from(self.remote),
+                        system_index: 0,
+                        system_addresses: system_addresses(),
+                        request_time_be: request.request_time_be,
+                        accepted_time_be: self.timestamp(now),
+                    };
+                    self.send_now(now, accepted.serialize().into(), Reliability::ReliableOrdered)?;
+                }
+            }
+            PacketId::ConnectionRequestAccepted if !self.is_server && self.state == State::Handshaking => {
+                if let Some(PacketT::ConnectionRequestAccepted(accepted)) = ReadPacket(&data)? {
+                    let incoming = NewIncomingConnection {
+                        server_address: Address::from(self.remote),
+                        system_addresses: system_addresses(),
+                        request_time_be: accepted.accepted_time_be,
+                        accepted_time_be: self.timestamp(now),
+                    };
+                    self.send_now(now, incoming.serialize().into(), Reliability::ReliableOrdered)?;
+                    self.state = State::Connected;
+                    self.events.push_back(Event::Connected);
+                }
+            }
+            PacketId::NewIncomingConnection if self.is_server && self.state == State::Handshaking => {
+                self.state = State::Connected;
+                self.events.push_back(Event::Connected);
+            }
+            PacketId::ConnectedPing => {
+                if let Some(PacketT::ConnectedPing(ping)) = ReadPacket(&data)? {
+                    let pong = ConnectedPong { client_send_time_be: ping.client_send_time_be, server_send_time_be: self.timestamp(now) };
+                    self.send_now(now, pong.serialize().into(), Reliability::Unreliable)?;
+                }
+            }
+            PacketId::ConnectedPong => {}
+            PacketId::DetectLostConnections => {
+                let ping = ConnectedPing { client_send_time_be: self.timestamp(now) };
+                self.send_now(now, ping.serialize().into(), Reliability::Unreliable)?;
+            }
+            PacketId::DisconnectNotification => {
+                self.flush_acks();
+                self.finish(DisconnectReason::PeerClosed);
+            }
+            _ if self.state == State::Connected => self.events.push_back(Event::Message(data)),
+            // messages that arrive before the handshake is done are dropped.
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// system_addresses returns the addresses sent in the connected handshake. They are never used, so
+// they're all left empty.
+fn system_addresses() -> Vec<Address> {
+    vec![Address::from(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))); NUMBER_OF_SYSTEM_ADDRESSES]
+}
@@ -0,0 +1,160 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The upper bounds of the buckets of `Stats::rtt_histogram`, the last bucket holds everything slower.
+pub const RTT_BUCKETS: [Duration; 10] = [
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(25),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_millis(1000),
+    Duration::from_millis(2500),
+    Duration::from_millis(5000),
+];
+
+/// Stats is a snapshot of what happened on a connection. The counters only ever go up, the other
+/// fields describe the connection at the time of the snapshot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Bytes and datagrams sent and received, offline messages, ACKs and NACKs included.
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub datagrams_sent: u64,
+    pub datagrams_received: u64,
+    /// Frames sent and received, indexed by `Reliability as usize`. Frames in resent datagrams
+    /// are counted again.
+    pub frames_sent: [u64; 8],
+    pub frames_received: [u64; 8],
+    /// Messages sent and received over the connection, keyed by `PacketId as u8`. Ids this crate
+    /// doesn't know are counted as `PacketId::Unknown`.
+    pub messages_sent: BTreeMap<u8, u64>,
+    pub messages_received: BTreeMap<u8, u64>,
+    /// Datagrams resent because they were NACKed or not acknowledged in time.
+    pub resends: u64,
+    /// Sequence numbers NACKed by us and by the other end.
+    pub nacks_sent: u64,
+    pub nacks_received: u64,
+    /// Datagrams received more than once.
+    pub duplicate_datagrams: u64,
+    /// Round trip times measured from ACKs, counted in the buckets of `RTT_BUCKETS`, and their sum.
+    pub rtt_histogram: [u64; RTT_BUCKETS.len() + 1],
+    pub rtt_sum: Duration,
+
+    /// Time from sending the last datagram acknowledged until its ACK arrived.
+    pub ack_latency: Duration,
+    /// Round trip time smoothed over the ACKs received, like TCP's SRTT.
+    pub smoothed_rtt: Duration,
+    /// Reliable datagrams that may be awaiting an ACK at the same time, and those that are. There
+    /// is no congestion control, the window is a fixed cap that never grows or shrinks.
+    pub congestion_window: u64,
+    pub in_flight: u64,
+    /// Split messages of which some, but not all fragments arrived.
+    pub splits_in_progress: u64,
+    /// Frames waiting to be sent, indexed by `Priority as usize`.
+    pub send_queue: [u64; 4],
+    /// Ordered messages received that wait for an earlier message to arrive.
+    pub receive_queue: u64,
+}
+
+impl Stats {
+    /// Adds the counters of `other` to those of these stats.
+    pub fn add_counters(&mut self, other: &Stats) {
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.datagrams_sent += other.datagrams_sent;
+        self.datagrams_received += other.datagrams_received;
+        for (total, n) in self.frames_sent.iter_mut().zip(other.frames_sent) {
+            *total += n;
+        }
+        for (total, n) in self.frames_received.iter_mut().zip(other.frames_received) {
+            *total += n;
+        }
+        for (id, n) in &other.messages_sent {
+            *self.messages_sent.entry(*id).or_default() += n;
+        }
+        for (id, n) in &other.messages_received {
+            *self.messages_received.entry(*id).or_default() += n;
+        }
+        self.resends += other.resends;
+        self.nacks_sent += other.nacks_sent;
+        self.nacks_received += other.nacks_received;
+        self.duplicate_datagrams += other.duplicate_datagrams;
+        for (total, n) in self.rtt_histogram.iter_mut().zip(other.rtt_histogram) {
+            *total += n;
+        }
+        self.rtt_sum += other.rtt_sum;
+    }
+
+    /// Counts a round trip time measured in the histogram.
+    pub(crate) fn record_rtt(&mut self, rtt: Duration) {
+        let bucket = RTT_BUCKETS.iter().position(|bound| rtt <= *bound).unwrap_or(RTT_BUCKETS.len());
+        self.rtt_histogram[bucket] += 1;
+        self.rtt_sum += rtt;
+    }
+}
+
+/// HandshakeFailure is why a client that started connecting to a listener never became a connection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HandshakeFailure {
+    /// The client runs a different RakNet protocol version.
+    IncompatibleProtocol,
+    /// An offline message of the client could not be read.
+    Malformed,
+    /// The client stopped answering before the connected handshake was done.
+    TimedOut,
+    /// The connection was closed by either end before the connected handshake was done.
+    Aborted,
+    /// The client's IP is banned, see `Listener::ban`. Counted for every open connection request.
+    Banned,
+}
+
+impl HandshakeFailure {
+    pub const ALL: [HandshakeFailure; 5] = [Self::IncompatibleProtocol, Self::Malformed, Self::TimedOut, Self::Aborted, Self::Banned];
+}
+
+/// ListenerStats aggregates the stats of the connections of a listener.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListenerStats {
+    /// Connections currently open.
+    pub connections: u64,
+    /// Connections that completed the connected handshake since the listener started.
+    pub accepted: u64,
+    /// Handshakes that failed since the listener started, indexed by `HandshakeFailure as usize`.
+    pub handshake_failures: [u64; 5],
+    /// IPs currently banned.
+    pub bans: u64,
+    /// The counters are summed over every connection the listener ever had, so they keep going
+    /// up when connections close. Queue depths, splits and datagrams in flight are summed over the
+    /// open connections, and the ACK latency and round trip time are their average.
+    pub total: Stats,
+}
+
+impl ListenerStats {
+    /// Aggregates the stats of the open connections with the counters of the closed ones.
+    pub fn aggregate(closed: &Stats, open: impl IntoIterator<Item = Stats>) -> Self {
+        let mut total = Stats::default();
+        total.add_counters(closed);
+        let mut connections = 0;
+        for stats in open {
+            connections += 1;
+            total.add_counters(&stats);
+            total.ack_latency += stats.ack_latency;
+            total.smoothed_rtt += stats.smoothed_rtt;
+            total.congestion_window += stats.congestion_window;
+            total.in_flight += stats.in_flight;
+            total.splits_in_progress += stats.splits_in_progress;
+            for (total, n) in total.send_queue.iter_mut().zip(stats.send_queue) {
+                *total += n;
+            }
+            total.receive_queue += stats.receive_queue;
+        }
+        if connections > 0 {
+            total.ack_latency /= connections as u32;
+            total.smoothed_rtt /= connections as u32;
+        }
+        Self { connections, total, ..Default::default() }
+    }
+}
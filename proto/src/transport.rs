@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::future::poll_fn;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use bytes::{Bytes, BytesMut};
+use tokio::io::ReadBuf;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+
+/// DatagramTransport is what connections and listeners send and receive datagrams with. It is
+/// implemented for tokio's `UdpSocket` and for `MemorySocket`, which connects peers in the same
+/// process without opening any sockets. The methods are poll based so the trait can be used as
+/// `Arc<dyn DatagramTransport>`, `send_to` and `recv_from` wrap them in futures.
+pub trait DatagramTransport: Send + Sync {
+    /// Attempts to send a datagram to `target`, returning the amount of bytes sent.
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>>;
+
+    /// Attempts to receive a datagram into `buf`, returning the address it was sent from. A
+    /// datagram larger than `buf` is truncated, like it would be by a UDP socket.
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>>;
+
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+impl dyn DatagramTransport {
+    pub async fn send_to(&self, buf: &[u8], target: SocketAddr) -> io::Result<usize> {
+        poll_fn(|cx| self.poll_send_to(cx, buf, target)).await
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut buf = ReadBuf::new(buf);
+        let src = poll_fn(|cx| self.poll_recv_from(cx, &mut buf)).await?;
+        Ok((buf.filled().len(), src))
+    }
+}
+
+// largest datagram received, anything longer is truncated. It leaves room for the largest MTU
+// tried in the handshake.
+const MAX_DATAGRAM_SIZE: usize = 1500;
+// size of the allocations datagrams are received into, each holds a few dozen of them.
+const RECEIVE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// ReceiveBuffer receives datagrams into a large buffer and hands each of them out as `Bytes`,
+/// so that the messages in them can be passed on as slices instead of copies. Once every datagram
+/// handed out of an allocation was dropped, its memory is reused for the next ones, so a read loop
+/// allocates only as long as datagrams are held on to.
+pub struct ReceiveBuffer {
+    buf: BytesMut,
+}
+
+impl Default for ReceiveBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReceiveBuffer {
+    pub fn new() -> Self {
+        Self { buf: BytesMut::with_capacity(RECEIVE_BUFFER_SIZE) }
+    }
+
+    /// Receives the next datagram from `transport`, returning it with the address it was sent from.
+    pub async fn recv_from(&mut self, transport: &Arc<dyn DatagramTransport>) -> io::Result<(Bytes, SocketAddr)> {
+        // reclaims the allocation if nothing handed out of it is still around.
+        self.buf.clear();
+        self.buf.reserve(MAX_DATAGRAM_SIZE);
+        self.buf.resize(MAX_DATAGRAM_SIZE, 0);
+        let (len, src) = transport.recv_from(&mut self.buf).await?;
+        self.buf.truncate(len);
+        Ok((self.buf.split().freeze(), src))
+    }
+}
+
+impl DatagramTransport for UdpSocket {
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        UdpSocket::poll_send_to(self, cx, buf, target)
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        UdpSocket::poll_recv_from(self, cx, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+}
+
+type Datagram = (Bytes, SocketAddr);
+
+/// Outbound is the queue of datagrams a writer task started by `spawn_writer` sends.
+pub type Outbound = mpsc::Sender<Datagram>;
+
+// WRITER_QUEUE is how many datagrams may wait for a writer, room for a full window of them.
+const WRITER_QUEUE: usize = 256;
+
+/// Spawns a task that sends every datagram queued on the returned sender with `transport`, so
+/// that a slow socket never holds up the code handling received datagrams until the queue is
+/// full. Queueing then waits for the socket, rather than letting the queue grow without bound.
+/// The task stops once every sender is dropped.
+pub fn spawn_writer(transport: Arc<dyn DatagramTransport>) -> Outbound {
+    let (tx, mut rx) = mpsc::channel::<Datagram>(WRITER_QUEUE);
+    tokio::spawn(async move {
+        while let Some((datagram, target)) = rx.recv().await {
+            // RakNet recovers from lost datagrams, a failed send is no different.
+            if let Err(e) = transport.send_to(&datagram, target).await {
+                tracing::warn!(%target, error = %e, "sending datagram failed");
+            }
+        }
+    });
+    tx
+}
+
+// first port handed out when binding a memory socket to port 0, the start of the IANA ephemeral range.
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// MemoryNetwork is a virtual network living in the current process. Sockets bound on it can
+/// reach each other by address, and datagrams sent to an address nobody is bound to are dropped,
+/// just like on a real network. Clones of a network share the same sockets.
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    inner: Arc<Mutex<NetworkInner>>,
+}
+
+#[derive(Default)]
+struct NetworkInner {
+    sockets: HashMap<SocketAddr, mpsc::UnboundedSender<Datagram>>,
+    next_port: u16,
+}
+
+impl MemoryNetwork {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds a socket to `address` on the network. An unspecified IP binds to the loopback
+    /// address, so other sockets have something to send to, and port 0 picks a free port.
+    pub fn bind(&self, address: SocketAddr) -> io::Result<MemorySocket> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut address = address;
+        if address.ip().is_unspecified() {
+            address.set_ip(match address.ip() {
+                IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            });
+        }
+        if address.port() == 0 {
+            address.set_port(inner.free_port(address.ip())?);
+        } else if inner.sockets.contains_key(&address) {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, format!("memory network: {} is already bound", address)));
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        inner.sockets.insert(address, tx);
+        Ok(MemorySocket {
+            network: self.clone(),
+            address,
+            incoming: Mutex::new(rx),
+        })
+    }
+
+    fn deliver(&self, data: &[u8], src: SocketAddr, target: SocketAddr) {
+        if let Some(tx) = self.inner.lock().unwrap().sockets.get(&target) {
+            let _ = tx.send((Bytes::copy_from_slice(data), src));
+        }
+    }
+}
+
+impl NetworkInner {
+    fn free_port(&mut self, ip: IpAddr) -> io::Result<u16> {
+        for _ in FIRST_EPHEMERAL_PORT..=u16::MAX {
+            if self.next_port < FIRST_EPHEMERAL_PORT {
+                self.next_port = FIRST_EPHEMERAL_PORT;
+            }
+            let port = self.next_port;
+            self.next_port = self.next_port.wrapping_add(1);
+            if !self.sockets.contains_key(&SocketAddr::new(ip, port)) {
+                return Ok(port);
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::AddrInUse, "memory network: no free ports left"))
+    }
+}
+
+/// MemorySocket is a socket bound on a `MemoryNetwork`. It is unbound from the network when dropped.
+pub struct MemorySocket {
+    network: MemoryNetwork,
+    address: SocketAddr,
+    incoming: Mutex<mpsc::UnboundedReceiver<Datagram>>,
+}
+
+impl DatagramTransport for MemorySocket {
+    fn poll_send_to(&self, _cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        self.network.deliver(buf, self.address, target);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        match self.incoming.lock().unwrap().poll_recv(cx) {
+            Poll::Ready(Some((data, src))) => {
+                let len = data.len().min(buf.remaining());
+                buf.put_slice(&data[..len]);
+                Poll::Ready(Ok(src))
+            }
+            // the sender lives in the network until this socket is dropped, so this can't happen.
+            Poll::Ready(None) => Poll::Ready(Err(io::Error::new(io::ErrorKind::NotConnected, "memory socket: unbound"))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.address)
+    }
+}
+
+impl Drop for MemorySocket {
+    fn drop(&mut self) {
+        self.network.inner.lock().unwrap().sockets.remove(&self.address);
+    }
+}
@@ -1,6 +1,15 @@
-use std::fmt::Debug;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display, Formatter};
 pub trait Packet: Debug {
-    fn serialize(&self) -> Vec<u8>;
+    /// Appends the encoded message to `buf`, so that one buffer can be reused for many messages.
+    fn write(&self, buf: &mut Vec<u8>);
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write(&mut buf);
+        buf
+    }
+
     fn deserialize(data: &[u8]) -> Result<Self, String> where Self: Sized;
 }
 
@@ -53,30 +62,105 @@ impl From<u8> for PacketId {
             0x19 => PacketId::IncompatibleProtocolVersion,
 
             0x1C => PacketId::UnconnectedPong,
-            
+
+            0xFE => PacketId::GamePacket,
+
             _ => PacketId::Unknown,
         }
     }
 }
-#[allow(non_camel_case_types)]
-pub type uint24 = u32;
+/// U24 is the 24-bit unsigned integer RakNet uses for datagram sequence numbers and for the
+/// message, sequence and order indices of frames. Arithmetic on it wraps around at 2^24, and
+/// values are compared using serial number arithmetic (RFC 1982): `a < b` if `b` is less than 2^23
+/// ahead of `a`, counting around the wrap. This keeps comparisons correct on connections that send
+/// more than 2^24 datagrams, as long as the values compared are less than 2^23 apart.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Default)]
+pub struct U24(u32);
+
+impl U24 {
+    pub const MAX: U24 = U24(0xff_ffff);
+    // half of the number space, values further apart than this are considered to have wrapped.
+    const HALF: u32 = 1 << 23;
+
+    /// Creates a U24 from the lower 24 bits of `value`.
+    pub const fn new(value: u32) -> Self {
+        Self(value & Self::MAX.0)
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    pub const fn wrapping_add(self, n: u32) -> Self {
+        Self::new(self.0.wrapping_add(n))
+    }
+
+    pub const fn wrapping_sub(self, n: u32) -> Self {
+        Self::new(self.0.wrapping_sub(n))
+    }
+
+    /// Returns the value after this one, wrapping around to 0 after `U24::MAX`.
+    pub const fn next(self) -> Self {
+        self.wrapping_add(1)
+    }
+
+    /// Increments the value, returning the value it had before.
+    pub fn inc(&mut self) -> Self {
+        let value = *self;
+        *self = self.next();
+        value
+    }
+
+    /// Returns how far `other` is ahead of this value, counting around the wrap.
+    pub const fn distance(self, other: U24) -> u32 {
+        other.0.wrapping_sub(self.0) & Self::MAX.0
+    }
+
+    /// Reads a little endian U24 from the first three bytes of `data`.
+    pub fn read(data: &[u8]) -> Self {
+        Self((data[0] as u32) | ((data[1] as u32) << 8) | ((data[2] as u32) << 16))
+    }
+
+    pub const fn to_le_bytes(self) -> [u8; 3] {
+        [self.0 as u8, (self.0 >> 8) as u8, (self.0 >> 16) as u8]
+    }
+}
+
+impl Ord for U24 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match self.distance(*other) {
+            0 => Ordering::Equal,
+            distance if distance < Self::HALF => Ordering::Less,
+            // exactly half apart is undefined in RFC 1982, fall back to the plain values so that
+            // the ordering is at least antisymmetric.
+            Self::HALF => self.0.cmp(&other.0),
+            _ => Ordering::Greater,
+        }
+    }
+}
 
-pub fn read_u24(data: &[u8]) -> uint24 {
-    (data[0] as u32) | ((data[1] as u32) << 8) | ((data[2] as u32) << 16)
+impl PartialOrd for U24 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-pub fn write_u24(value: uint24) -> [u8; 3] {
-    [
-        (value & 0xff) as u8,
-        ((value >> 8) & 0xff) as u8,
-        ((value >> 16) & 0xff) as u8,
-    ]
+impl From<u32> for U24 {
+    fn from(value: u32) -> Self {
+        Self::new(value)
+    }
 }
 
-pub fn inc_u24(value: &mut uint24) -> uint24 {
-    let result = *value;
-    let _ = value.wrapping_add(1);
-    result
+impl Debug for U24 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for U24 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
 }
 
 pub fn read_be_u64(input: &[u8]) -> u64 {
@@ -98,3 +182,35 @@ pub fn read_be_i64(input: &[u8]) -> i64 {
     let (int_bytes, _) = input.split_at(size_of::<i64>());
     i64::from_be_bytes(int_bytes.try_into().unwrap())
 }
+
+/// Hex formats bytes as lowercase hex, only once it is displayed, so that it costs nothing when
+/// passed to a log event that is filtered out.
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl Display for Hex<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// json_string quotes `s` as a JSON string, escaping quotes, backslashes and control characters.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,228 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use proto::capture::{Capture, CaptureTransport, Direction, PcapngWriter};
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::listener::Listener;
+use proto::transport::{DatagramTransport, MemoryNetwork};
+
+struct Block {
+    block_type: u32,
+    body: Vec<u8>,
+}
+
+// blocks splits a pcapng file into its blocks, checking that their lengths add up.
+fn blocks(file: &[u8]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut data = file;
+    while !data.is_empty() {
+        let block_type = u32::from_le_bytes(data[0..4].try_into().unwrap());
+        let length = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        assert_eq!(length % 4, 0);
+        assert_eq!(u32::from_le_bytes(data[length - 4..length].try_into().unwrap()) as usize, length);
+        blocks.push(Block { block_type, body: data[8..length - 4].to_vec() });
+        data = &data[length..];
+    }
+    blocks
+}
+
+struct Captured {
+    micros: u64,
+    packet: Vec<u8>,
+    flags: u32,
+}
+
+// packets checks the headers of a capture and returns the packets in it.
+fn packets(file: &[u8]) -> Vec<Captured> {
+    let blocks = blocks(file);
+    assert_eq!(blocks[0].block_type, 0x0a0d0d0a);
+    assert_eq!(&blocks[0].body[..4], &0x1a2b3c4du32.to_le_bytes());
+    assert_eq!(blocks[1].block_type, 1);
+    // raw IP packets.
+    assert_eq!(&blocks[1].body[..2], &101u16.to_le_bytes());
+    blocks[2..].iter().map(|block| {
+        assert_eq!(block.block_type, 6);
+        let b = &block.body;
+        let word = |i: usize| u32::from_le_bytes(b[i..i + 4].try_into().unwrap());
+        let micros = (word(4) as u64) << 32 | word(8) as u64;
+        let len = word(12) as usize;
+        assert_eq!(word(16) as usize, len);
+        let options = &b[20 + len.next_multiple_of(4)..];
+        // the direction flags, then the end of the options.
+        assert_eq!(&options[..4], &[2, 0, 4, 0]);
+        assert_eq!(&options[8..], &[0, 0, 0, 0]);
+        Captured { micros, packet: b[20..20 + len].to_vec(), flags: u32::from_le_bytes(options[4..8].try_into().unwrap()) }
+    }).collect()
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data.chunks(2).map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32).sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    std::env::temp_dir().join(format!("{}-{}-{}.pcapng", name, std::process::id(), nanos))
+}
+
+#[test]
+fn datagrams_get_ip_and_udp_headers() {
+    let src: SocketAddr = "10.0.0.1:19132".parse().unwrap();
+    let dst: SocketAddr = "192.168.1.20:50000".parse().unwrap();
+    let time = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+    let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+    writer.write_datagram(time, src, dst, Direction::Outbound, &[0x84, 0, 0, 0, 1]).unwrap();
+    writer.write_datagram(time, "[::1]:1".parse().unwrap(), dst, Direction::Inbound, &[0xc0]).unwrap();
+    let file = writer.into_inner();
+
+    let packets = packets(&file);
+    assert_eq!(packets.len(), 2);
+    let ipv4 = &packets[0];
+    assert_eq!(ipv4.micros, 1_700_000_000_123_456);
+    assert_eq!(ipv4.flags, 2);
+    let p = &ipv4.packet;
+    assert_eq!(p.len(), 20 + 8 + 5);
+    assert_eq!(p[0], 0x45);
+    assert_eq!(u16::from_be_bytes([p[2], p[3]]), 33);
+    assert_eq!(p[9], 17);
+    assert_eq!(&p[12..16], &[10, 0, 0, 1]);
+    assert_eq!(&p[16..20], &[192, 168, 1, 20]);
+    assert_eq!(checksum(&p[..20]), 0xffff);
+    assert_eq!(u16::from_be_bytes([p[20], p[21]]), 19132);
+    assert_eq!(u16::from_be_bytes([p[22], p[23]]), 50000);
+    assert_eq!(u16::from_be_bytes([p[24], p[25]]), 13);
+    // the UDP checksum over the pseudo header and the datagram.
+    let pseudo = [&p[12..20], &[0, 17, 0, 13], &p[20..]].concat();
+    assert_eq!(checksum(&pseudo), 0xffff);
+    assert_eq!(&p[28..], &[0x84, 0, 0, 0, 1]);
+
+    // an IPv4 address is mapped when the other end is IPv6.
+    let ipv6 = &packets[1];
+    assert_eq!(ipv6.flags, 1);
+    let p = &ipv6.packet;
+    assert_eq!(p.len(), 40 + 8 + 1);
+    assert_eq!(p[0] >> 4, 6);
+    assert_eq!(p[6], 17);
+    assert_eq!(&p[24..40], &"::ffff:192.168.1.20".parse::<std::net::Ipv6Addr>().unwrap().octets());
+    assert_eq!(&p[48..], &[0xc0]);
+}
+
+#[tokio::test]
+async fn transport_captures_both_directions() {
+    let micros = || SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64;
+    let start = micros();
+    let path = temp_path("capture");
+    let network = MemoryNetwork::new();
+    let server_socket = network.bind("127.0.0.1:19132".parse().unwrap()).unwrap();
+    let capture = Arc::new(CaptureTransport::new(Arc::new(server_socket), Capture { path: path.clone(), ..Default::default() }).unwrap());
+    let listener = Listener::listen_on(capture.clone(), Arc::new(SystemClock));
+
+    let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let (client, server) = tokio::join!(
+        Conn::dial_on(Arc::new(client_socket), Arc::new(SystemClock), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+    client.Write(&[0xfe, 1, 2, 3]).await.unwrap();
+    server.ReadPacket().await.unwrap();
+    server.Write(&[0xfe, 4, 5, 6]).await.unwrap();
+    client.ReadPacket().await.unwrap();
+    // both messages were recorded before they were read, flushing waits for them to be written.
+    capture.flush().unwrap();
+
+    let packets = packets(&std::fs::read(&path).unwrap());
+    std::fs::remove_file(&path).unwrap();
+    let client_port = client.conn.local_addr().unwrap().port();
+    let inbound: Vec<&Captured> = packets.iter().filter(|p| p.flags == 1).collect();
+    let outbound: Vec<&Captured> = packets.iter().filter(|p| p.flags == 2).collect();
+    assert!(!inbound.is_empty() && !outbound.is_empty());
+    for p in &inbound {
+        assert_eq!(u16::from_be_bytes([p.packet[20], p.packet[21]]), client_port);
+        assert_eq!(u16::from_be_bytes([p.packet[22], p.packet[23]]), 19132);
+    }
+    // the open connection request comes first, and the message sent by the client is in there.
+    assert_eq!(inbound[0].packet[28], 0x05);
+    assert!(inbound.iter().any(|p| p.packet.windows(4).any(|w| w == [0xfe, 1, 2, 3])));
+    assert!(outbound.iter().any(|p| p.packet.windows(4).any(|w| w == [0xfe, 4, 5, 6])));
+    let end = micros();
+    assert!(packets.iter().all(|p| (start..=end).contains(&p.micros)));
+}
+
+#[tokio::test]
+async fn captures_rotate_and_filter_by_peer() {
+    let path = temp_path("rotate");
+    let network = MemoryNetwork::new();
+    let socket = network.bind("127.0.0.1:19132".parse().unwrap()).unwrap();
+    let capture = Arc::new(CaptureTransport::new(Arc::new(socket), Capture {
+        path: path.clone(),
+        rotate_size: Some(1000),
+        peers: vec!["127.0.0.1:0".parse().unwrap()],
+        ..Default::default()
+    }).unwrap());
+
+    // only the first address is captured, on any port.
+    let transport: Arc<dyn DatagramTransport> = capture.clone();
+    for i in 0..10 {
+        transport.send_to(&[0xfe; 300], SocketAddr::from(([127, 0, 0, 1], 40000 + i))).await.unwrap();
+        transport.send_to(&[0xfd; 300], "127.0.0.2:40000".parse().unwrap()).await.unwrap();
+    }
+    capture.flush().unwrap();
+
+    // every file starts with the headers and takes datagrams until it has grown past 1000 bytes.
+    let mut captured = Vec::new();
+    let mut files = 0;
+    for n in 0.. {
+        let file = path.with_file_name(format!("{}-{}.pcapng", path.file_stem().unwrap().to_string_lossy(), n));
+        let Ok(data) = std::fs::read(&file) else { break };
+        std::fs::remove_file(&file).unwrap();
+        let packets = packets(&data);
+        // a datagram of 300 bytes takes up 372 with its headers.
+        assert!(!packets.is_empty() && data.len() < 1000 + 372);
+        captured.extend(packets);
+        files += 1;
+    }
+    // three datagrams to a file after the 64 bytes of headers.
+    assert_eq!(files, 4);
+    assert_eq!(captured.len(), 10);
+    for (i, p) in captured.iter().enumerate() {
+        assert_eq!(&p.packet[16..20], &[127, 0, 0, 1]);
+        assert_eq!(u16::from_be_bytes([p.packet[22], p.packet[23]]), 40000 + i as u16);
+    }
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn datagrams_the_file_cant_keep_up_with_are_counted() {
+    let path = temp_path("dropped");
+    let network = MemoryNetwork::new();
+    let socket = network.bind("127.0.0.1:19132".parse().unwrap()).unwrap();
+    // a file for every datagram is much slower than sending them.
+    let capture = Arc::new(CaptureTransport::new(Arc::new(socket), Capture {
+        path: path.clone(),
+        rotate_size: Some(1),
+        queue_size: Some(1),
+        ..Default::default()
+    }).unwrap());
+
+    let transport: Arc<dyn DatagramTransport> = capture.clone();
+    for _ in 0..200 {
+        transport.send_to(&[0xfe; 100], "127.0.0.1:40000".parse().unwrap()).await.unwrap();
+    }
+    capture.flush().unwrap();
+
+    let mut captured = 0;
+    for n in 0.. {
+        let file = path.with_file_name(format!("{}-{}.pcapng", path.file_stem().unwrap().to_string_lossy(), n));
+        let Ok(data) = std::fs::read(&file) else { break };
+        std::fs::remove_file(&file).unwrap();
+        captured += packets(&data).len() as u64;
+    }
+    assert!(capture.dropped() > 0);
+    assert_eq!(captured + capture.dropped(), 200);
+}
@@ -0,0 +1,110 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+use tokio::io::ReadBuf;
+use proto::clock::{Clock, ManualClock};
+use proto::conn::Conn;
+use proto::packet::Reliability;
+use proto::session::{DisconnectReason, Event, Session};
+use proto::transport::{DatagramTransport, MemoryNetwork};
+use proto::types::U24;
+
+const TICK: Duration = Duration::from_millis(100);
+
+// settle lets the tasks woken by advancing the clock run until they are idle again.
+async fn settle() {
+    for _ in 0..16 {
+        tokio::task::yield_now().await;
+    }
+}
+
+fn try_recv(transport: &Arc<dyn DatagramTransport>) -> Option<Vec<u8>> {
+    let mut buf = [0u8; 1500];
+    let mut buf = ReadBuf::new(&mut buf);
+    let mut cx = Context::from_waker(Waker::noop());
+    match transport.poll_recv_from(&mut cx, &mut buf) {
+        Poll::Ready(res) => {
+            res.unwrap();
+            Some(buf.filled().to_vec())
+        }
+        Poll::Pending => None,
+    }
+}
+
+#[tokio::test]
+async fn open_connection_requests_follow_the_mtu_schedule() {
+    let clock = Arc::new(ManualClock::new());
+    let network = MemoryNetwork::new();
+    let server: Arc<dyn DatagramTransport> = Arc::new(network.bind("127.0.0.1:19132".parse().unwrap()).unwrap());
+    let client = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+    let start = clock.now();
+    let dial = tokio::spawn(Conn::dial_on(Arc::new(client), clock.clone(), server.local_addr().unwrap()));
+
+    // nobody answers, so the client tries every MTU four times, 500ms apart, and then gives up.
+    let mut sent = Vec::new();
+    for _ in 0..=60 {
+        settle().await;
+        while let Some(datagram) = try_recv(&server) {
+            sent.push((clock.now().duration_since(start).as_millis(), datagram.len()));
+        }
+        clock.advance(TICK);
+    }
+    let expected: Vec<(u128, usize)> = [1492, 1200, 576].iter()
+        .flat_map(|mtu| [mtu; 4])
+        .enumerate()
+        .map(|(i, mtu)| (i as u128 * 500, mtu - 28))
+        .collect();
+    assert_eq!(sent, expected);
+
+    let err = dial.await.unwrap().err().expect("dial should fail without a server");
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+
+fn sequence_number(datagram: &[u8]) -> u32 {
+    U24::read(&datagram[1..]).get()
+}
+
+fn ack(sequence_number: u32) -> Vec<u8> {
+    let mut ack = vec![0xc0, 0, 1, 1];
+    ack.extend_from_slice(&U24::new(sequence_number).to_le_bytes());
+    ack
+}
+
+#[test]
+fn unacknowledged_datagrams_are_resent_on_schedule() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let remote: SocketAddr = "127.0.0.1:19132".parse().unwrap();
+    let mut session = Session::server(start, remote, 1, 1492);
+
+    session.send(start, &[0xfe, 1, 2, 3], Reliability::Reliable, 0).unwrap();
+    session.flush(start);
+    assert_eq!(session.poll_transmit().map(|d| sequence_number(&d)), Some(0));
+
+    // resends are checked every third tick, by then the datagram is long overdue.
+    let mut resent = Vec::new();
+    for ms in (100..=900).step_by(100) {
+        session.handle_timeout(at(ms));
+        while let Some(datagram) = session.poll_transmit() {
+            resent.push((ms, sequence_number(&datagram)));
+        }
+        if ms == 600 {
+            // acknowledging the last resend stops it from being sent again.
+            session.handle_datagram(at(650), &ack(2)).unwrap();
+        }
+    }
+    assert_eq!(resent, vec![(300, 1), (600, 2)]);
+
+    // the session times out 5s plus twice the round trip time of 50ms after the ACK, checked
+    // every fifth tick.
+    for ms in (1000..=5900).step_by(100) {
+        session.handle_timeout(at(ms));
+        assert_eq!(session.poll_event(), None, "event at {}ms", ms);
+    }
+    session.handle_timeout(at(6000));
+    assert_eq!(session.poll_event(), Some(Event::Disconnected(DisconnectReason::TimedOut)));
+    assert_eq!(session.poll_timeout(), None);
+}
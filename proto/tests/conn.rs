@@ -0,0 +1,268 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use bytes::Bytes;
+use tokio::io::ReadBuf;
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::listener::Listener;
+use proto::messages::connected_ping::ConnectedPing;
+use proto::packet::Reliability;
+use proto::transport::{DatagramTransport, MemoryNetwork, MemorySocket};
+use proto::types::Packet;
+
+const WRITERS: u8 = 4;
+const MESSAGES: u8 = 50;
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn conn_and_listener_are_send_and_sync() {
+    assert_send_sync::<Conn>();
+    assert_send_sync::<Listener>();
+}
+
+#[tokio::test]
+async fn conn_is_shared_between_tasks() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(Arc::new(network.bind("127.0.0.1:19132".parse().unwrap()).unwrap()), Arc::new(SystemClock));
+    let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let (client, server) = tokio::join!(
+        Conn::dial_on(Arc::new(client_socket), Arc::new(SystemClock), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+    assert!(!client.is_closed());
+    assert_eq!(client.effective_mtu(), server.effective_mtu());
+
+    // every writer sends on its own order channel, so only the order within a channel is known.
+    let writers: Vec<_> = (0..WRITERS).map(|channel| {
+        let client = client.clone();
+        tokio::spawn(async move {
+            for i in 0..MESSAGES {
+                client.write_with(&[0xfe, channel, i], Reliability::ReliableOrdered, channel, true).await.unwrap();
+            }
+        })
+    }).collect();
+    let reader = tokio::spawn({
+        let server = server.clone();
+        async move {
+            let mut next = [0u8; WRITERS as usize];
+            for _ in 0..WRITERS as usize * MESSAGES as usize {
+                let message = server.ReadPacket().await.unwrap();
+                let (channel, i) = (message[1] as usize, message[2]);
+                assert_eq!(i, next[channel], "channel {} out of order", channel);
+                next[channel] += 1;
+            }
+            // the client closing the connection ends the read.
+            assert!(server.ReadPacket().await.is_err());
+        }
+    });
+    for writer in writers {
+        writer.await.unwrap();
+    }
+
+    let closer = tokio::spawn({
+        let client = client.clone();
+        async move { client.close().await.unwrap() }
+    });
+    closer.await.unwrap();
+    tokio::time::timeout(Duration::from_secs(10), reader).await.unwrap().unwrap();
+    assert!(server.is_closed());
+}
+
+// GatedSocket is a socket that can't send anything while its gate is closed, like a socket with
+// a full send buffer.
+struct GatedSocket {
+    inner: MemorySocket,
+    open: AtomicBool,
+    waiting: Mutex<Vec<Waker>>,
+}
+
+impl GatedSocket {
+    fn set_open(&self, open: bool) {
+        self.open.store(open, Ordering::SeqCst);
+        if open {
+            self.waiting.lock().unwrap().drain(..).for_each(Waker::wake);
+        }
+    }
+}
+
+impl DatagramTransport for GatedSocket {
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        let mut waiting = self.waiting.lock().unwrap();
+        if !self.open.load(Ordering::SeqCst) {
+            waiting.push(cx.waker().clone());
+            return Poll::Pending;
+        }
+        self.inner.poll_send_to(cx, buf, target)
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        self.inner.poll_recv_from(cx, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+#[tokio::test]
+async fn sends_do_not_wait_for_the_socket() {
+    let network = MemoryNetwork::new();
+    let gated = Arc::new(GatedSocket {
+        inner: network.bind("127.0.0.1:19132".parse().unwrap()).unwrap(),
+        open: AtomicBool::new(true),
+        waiting: Mutex::new(Vec::new()),
+    });
+    let listener = Listener::listen_on(gated.clone(), Arc::new(SystemClock));
+    let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let (client, server) = tokio::join!(
+        Conn::dial_on(Arc::new(client_socket), Arc::new(SystemClock), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    gated.set_open(false);
+    // writing only queues the message, it doesn't wait for the stuck socket...
+    tokio::time::timeout(Duration::from_millis(500), server.Write(&[0xfe, 0xff])).await
+        .expect("write waited for the socket").unwrap();
+    // ...and neither does handling the messages the client sends meanwhile.
+    for i in 0..10 {
+        client.Write(&[0xfe, i]).await.unwrap();
+    }
+    for i in 0..10 {
+        let message = tokio::time::timeout(Duration::from_secs(1), server.ReadPacket()).await
+            .expect("receiving waited for the socket").unwrap();
+        assert_eq!(message, vec![0xfe, i]);
+    }
+
+    gated.set_open(true);
+    let message = tokio::time::timeout(Duration::from_secs(5), client.ReadPacket()).await.unwrap().unwrap();
+    assert_eq!(message, vec![0xfe, 0xff]);
+}
+
+// TappedSocket holds on to what it is sent while tapped instead of sending it, so that a test can
+// hand the datagrams over itself.
+struct TappedSocket {
+    inner: MemorySocket,
+    tapped: Mutex<Option<Vec<Bytes>>>,
+}
+
+impl TappedSocket {
+    // take waits for the client's writer to send something and takes what was sent so far.
+    async fn take(&self) -> Vec<Bytes> {
+        loop {
+            let taken = self.tapped.lock().unwrap().as_mut().unwrap().split_off(0);
+            if !taken.is_empty() {
+                return taken;
+            }
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+}
+
+impl DatagramTransport for TappedSocket {
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        if let Some(tapped) = self.tapped.lock().unwrap().as_mut() {
+            tapped.push(Bytes::copy_from_slice(buf));
+            return Poll::Ready(Ok(buf.len()));
+        }
+        self.inner.poll_send_to(cx, buf, target)
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        self.inner.poll_recv_from(cx, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn messages_stay_in_order_while_the_writer_is_full() {
+    let network = MemoryNetwork::new();
+    let gated = Arc::new(GatedSocket {
+        inner: network.bind("127.0.0.1:19132".parse().unwrap()).unwrap(),
+        open: AtomicBool::new(true),
+        waiting: Mutex::new(Vec::new()),
+    });
+    let listener = Listener::listen_on(gated.clone(), Arc::new(SystemClock));
+    let tapped = Arc::new(TappedSocket { inner: network.bind("127.0.0.1:0".parse().unwrap()).unwrap(), tapped: Mutex::new(None) });
+    let (client, server) = tokio::join!(
+        Conn::dial_on(tapped.clone(), Arc::new(SystemClock), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    // the server's writer fills up, after which anything dispatching a datagram waits for room.
+    gated.set_open(false);
+    while tokio::time::timeout(Duration::from_millis(50), server.write_with(&[0xfe; 100], Reliability::Unreliable, 0, true)).await.is_ok() {}
+
+    *tapped.tapped.lock().unwrap() = Some(Vec::new());
+    // the pong answering the ping has to wait for the writer, the first message shouldn't.
+    client.write_with(&ConnectedPing { client_send_time_be: 1 }.serialize(), Reliability::Unreliable, 0, false).await.unwrap();
+    client.write_with(&[0xfe, 1], Reliability::ReliableOrdered, 0, true).await.unwrap();
+    let first = tapped.take().await;
+    client.write_with(&[0xfe, 2], Reliability::ReliableOrdered, 0, true).await.unwrap();
+    let second = tapped.take().await;
+
+    for datagram in first {
+        // gives up on the pong, the message must have been passed on before waiting for it.
+        let _ = tokio::time::timeout(Duration::from_millis(50), server.ReceivePacket(datagram)).await;
+    }
+    for datagram in second {
+        let _ = tokio::time::timeout(Duration::from_millis(50), server.ReceivePacket(datagram)).await;
+    }
+    for i in 1..=2 {
+        let message = tokio::time::timeout(Duration::from_secs(1), server.ReadPacket()).await
+            .expect("message waited for the writer").unwrap();
+        assert_eq!(message, vec![0xfe, i]);
+    }
+}
+
+// ResetSocket fails its first receives with a reset, like a socket on Windows that got an ICMP
+// port unreachable back for an earlier datagram.
+struct ResetSocket {
+    inner: MemorySocket,
+    resets: AtomicU32,
+}
+
+impl DatagramTransport for ResetSocket {
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        self.inner.poll_send_to(cx, buf, target)
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        if self.resets.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok() {
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::ConnectionReset, "reset")));
+        }
+        self.inner.poll_recv_from(cx, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn dialed_connections_keep_reading_after_a_reset() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(Arc::new(network.bind("127.0.0.1:19132".parse().unwrap()).unwrap()), Arc::new(SystemClock));
+    let socket = ResetSocket { inner: network.bind("127.0.0.1:0".parse().unwrap()).unwrap(), resets: AtomicU32::new(2) };
+    let (client, server) = tokio::time::timeout(Duration::from_secs(5), async {
+        tokio::join!(
+            Conn::dial_on(Arc::new(socket), Arc::new(SystemClock), listener.local_addr().unwrap()),
+            listener.accept(),
+        )
+    }).await.expect("the handshake stopped after a reset");
+    let (client, server) = (client.unwrap(), server.unwrap());
+    server.Write(&[0xfe, 1]).await.unwrap();
+    assert_eq!(client.ReadPacket().await.unwrap(), vec![0xfe, 1]);
+}
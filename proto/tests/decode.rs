@@ -0,0 +1,55 @@
+use std::path::Path;
+use bytes::Bytes;
+use proto::ack::read_acknowledgement;
+use proto::address::read_addr;
+use proto::dissect::dissect;
+use proto::messages::unknown::UnknownPacket;
+use proto::motd::MOTD;
+use proto::packet::Packet as Frame;
+use proto::query::Status;
+use proto::types::Packet;
+use proto::ReadPacket;
+
+// decode_all runs every decoder over `data`, they must return errors rather than panic.
+fn decode_all(data: &[u8]) {
+    let _ = ReadPacket(data);
+    let _ = UnknownPacket::deserialize(data);
+    let _ = Frame::read(data);
+    let bytes = Bytes::copy_from_slice(data);
+    let _ = UnknownPacket::read_bytes(&bytes);
+    let _ = Frame::read_bytes(&bytes);
+    let _ = read_acknowledgement(data);
+    let _ = read_addr(data);
+    let _ = MOTD::deserialize(data);
+    let _ = String::from_utf8_lossy(data).parse::<Status>();
+    let _ = dissect(data);
+}
+
+#[test]
+fn empty_input_is_an_error() {
+    assert!(ReadPacket(&[]).is_err());
+    assert!(UnknownPacket::deserialize(&[]).is_err());
+    assert!(UnknownPacket::read_bytes(&Bytes::new()).is_err());
+    decode_all(&[]);
+}
+
+#[test]
+fn truncated_seeds_dont_panic() {
+    // the seed corpora of the fuzz targets, captured from a real conversation.
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus");
+    // every truncation of every seed takes too long under Miri.
+    let step = if cfg!(miri) { 64 } else { 1 };
+    let mut seeds = 0;
+    for target in std::fs::read_dir(corpus).unwrap() {
+        for seed in std::fs::read_dir(target.unwrap().path()).unwrap() {
+            let data = std::fs::read(seed.unwrap().path()).unwrap();
+            for len in (0..=data.len().min(64)).step_by(step) {
+                decode_all(&data[..len]);
+                decode_all(&data[data.len() - len..]);
+            }
+            decode_all(&data);
+            seeds += 1;
+        }
+    }
+    assert!(seeds > 0);
+}
@@ -0,0 +1,140 @@
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use proto::clock::SystemClock;
+use proto::discovery::{advertise_on, discover_on};
+use proto::listener::Listener;
+use proto::messages::unconnected_ping::UnconnectedPing;
+use proto::query::Status;
+use proto::transport::{DatagramTransport, MemoryNetwork};
+use proto::types::Packet;
+use proto::{PacketT, ReadPacket};
+use tokio::io::ReadBuf;
+
+/// Flaky is a transport whose first receives and sends fail, like a socket on Windows that gets
+/// an ICMP port unreachable back for a pong. Receives fail with `receive_error`.
+struct Flaky {
+    inner: Arc<dyn DatagramTransport>,
+    receive_failures: AtomicU32,
+    receive_error: ErrorKind,
+    send_failures: AtomicU32,
+}
+
+// fail takes one failure off `failures`, returning whether there was one left.
+fn fail(failures: &AtomicU32) -> bool {
+    failures.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_ok()
+}
+
+impl DatagramTransport for Flaky {
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        if fail(&self.send_failures) {
+            return Poll::Ready(Err(io::Error::new(ErrorKind::PermissionDenied, "flaky send")));
+        }
+        self.inner.poll_send_to(cx, buf, target)
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        if fail(&self.receive_failures) {
+            return Poll::Ready(Err(io::Error::new(self.receive_error, "flaky receive")));
+        }
+        self.inner.poll_recv_from(cx, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+fn bind(network: &MemoryNetwork, address: &str) -> Arc<dyn DatagramTransport> {
+    Arc::new(network.bind(address.parse().unwrap()).unwrap())
+}
+
+// status is the pong of a server that listens on 19140 over IPv4 and 19141 over IPv6.
+fn status(motd: &str) -> String {
+    format!("MCPE;{};786;1.21.73;0;10;7;Bedrock level;Survival;1;19140;19141;0;", motd)
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn discovered_servers_are_deduplicated() {
+    let network = MemoryNetwork::new();
+    // the first server answers on two interfaces and over IPv6.
+    let targets: Vec<SocketAddr> = ["10.0.0.1:19132", "192.168.1.1:19132", "[fe80::1]:19133", "10.0.0.2:19132"]
+        .iter()
+        .map(|target| target.parse().unwrap())
+        .collect();
+    let advertisers: Vec<_> = targets[..3].iter()
+        .map(|target| tokio::spawn(advertise_on(bind(&network, &target.to_string()), 7, status("Advertised"))))
+        .collect();
+    // the second one is a listener, which answers pings itself.
+    let listener = Listener::listen_on(bind(&network, "10.0.0.2:19132"), Arc::new(SystemClock));
+    listener.set_pong_data("MCPE;Listener;786;1.21.73;0;10;".to_string());
+
+    let servers = discover_on(bind(&network, "10.0.0.9:0"), Some(bind(&network, "[fe80::9]:0")), &targets, Duration::from_millis(300)).await.unwrap();
+    assert_eq!(servers.len(), 2, "{:?}", servers);
+    let advertised = servers.iter().find(|server| server.server_guid == 7).unwrap();
+    assert_eq!(advertised.status, status("Advertised").parse::<Status>().unwrap());
+    // the address is the one the server listens on, not the LAN port it answered from.
+    assert!(targets[..3].iter().any(|target| target.ip() == advertised.address.ip()));
+    assert_eq!(advertised.address.port(), if advertised.address.is_ipv4() { 19140 } else { 19141 });
+
+    let listening = servers.iter().find(|server| server.server_guid == listener.guid).unwrap();
+    assert_eq!(listening.address, targets[3]);
+    assert_eq!(listening.status.motd, "Listener");
+    for advertiser in advertisers {
+        advertiser.abort();
+    }
+}
+
+#[tokio::test]
+async fn advertising_survives_socket_errors() {
+    let network = MemoryNetwork::new();
+    let transport = Arc::new(Flaky {
+        inner: bind(&network, "10.0.0.1:19132"),
+        receive_failures: AtomicU32::new(3),
+        receive_error: ErrorKind::ConnectionReset,
+        send_failures: AtomicU32::new(1),
+    });
+    let lan = transport.local_addr().unwrap();
+    let advertiser = tokio::spawn(advertise_on(transport, 7, status("Advertised")));
+    let client = bind(&network, "10.0.0.9:0");
+
+    let mut buf = [0u8; 1492];
+    for client_send_time_be in 1..=2 {
+        let ping = UnconnectedPing { client_send_time_be, client_guid_be: 99 };
+        client.send_to(&ping.serialize(), lan).await.unwrap();
+        let received = tokio::time::timeout(Duration::from_millis(200), client.recv_from(&mut buf)).await;
+        if client_send_time_be == 1 {
+            // sending the answer to the first ping failed.
+            assert!(received.is_err(), "answered although sending failed");
+            continue;
+        }
+        let (len, src) = received.expect("no answer after the socket errors").unwrap();
+        assert_eq!(src, lan);
+        let Ok(Some(PacketT::UnconnectedPong(pong))) = ReadPacket(&buf[..len]) else {
+            panic!("not a pong: {:?}", &buf[..len]);
+        };
+        assert_eq!(pong.client_send_time_be, client_send_time_be);
+        assert_eq!(pong.server_guid_be, 7);
+        assert_eq!(pong.data, status("Advertised"));
+    }
+    assert!(!advertiser.is_finished());
+    advertiser.abort();
+}
+
+#[tokio::test]
+async fn advertising_stops_when_the_socket_breaks() {
+    let network = MemoryNetwork::new();
+    let transport = Arc::new(Flaky {
+        inner: bind(&network, "10.0.0.1:19132"),
+        receive_failures: AtomicU32::new(u32::MAX),
+        receive_error: ErrorKind::NotConnected,
+        send_failures: AtomicU32::new(0),
+    });
+    let res = tokio::time::timeout(Duration::from_secs(1), advertise_on(transport, 7, status("Advertised"))).await
+        .expect("kept receiving on a broken socket");
+    assert_eq!(res.unwrap_err().kind(), ErrorKind::NotConnected);
+}
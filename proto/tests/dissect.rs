@@ -0,0 +1,141 @@
+use std::net::SocketAddr;
+use proto::ack::write_acknowledgement;
+use proto::dissect::{dissect, Tree};
+use proto::messages::connection_request_accepted::ConnectionRequestAccepted;
+use proto::messages::open_connection_request_2::OpenConnectionRequest2;
+use proto::packet::{Packet as Frame, Reliability};
+use proto::types::{Packet, U24};
+
+fn value<'a>(tree: &'a Tree, label: &str) -> &'a str {
+    &tree.find(label).unwrap_or_else(|| panic!("no {} in\n{}", label, tree)).value
+}
+
+fn range(tree: &Tree, label: &str) -> std::ops::Range<usize> {
+    tree.find(label).unwrap().range.clone().unwrap()
+}
+
+#[test]
+fn frames_of_a_datagram_are_labelled() {
+    let mut datagram = vec![0x84];
+    datagram.extend_from_slice(&U24::new(7).to_le_bytes());
+    Frame { reliability: Reliability::ReliableOrdered, message_index: U24::new(3), order_index: U24::new(2), order_channel: 1, data: vec![0xfe, 1, 2].into(), ..Default::default() }.write(&mut datagram);
+    Frame { reliability: Reliability::Reliable, message_index: U24::new(4), split: true, split_count: 2, split_id: 9, split_index: 1, data: vec![0xaa; 4].into(), ..Default::default() }.write(&mut datagram);
+
+    let tree = dissect(&datagram);
+    assert_eq!(value(&tree, "flags"), "0x84 Datagram|NeedsBAndAS");
+    assert_eq!(value(&tree, "sequence number"), "7");
+    assert_eq!(range(&tree, "sequence number"), 1..4);
+
+    let first = tree.find("frame 0").unwrap();
+    assert_eq!(first.value, "ReliableOrdered");
+    assert_eq!(first.range, Some(4..4 + 3 + 3 + 4 + 3));
+    assert_eq!(value(first, "message index"), "3");
+    assert_eq!(value(first, "order index"), "2");
+    assert_eq!(value(first, "order channel"), "1");
+    assert_eq!(value(first, "id"), "0xfe GamePacket");
+    assert_eq!(value(first, "payload"), "2 bytes: 0102");
+    assert_eq!(range(first, "payload"), 15..17);
+
+    // fragments aren't decoded as messages.
+    let second = tree.find("frame 1").unwrap();
+    assert_eq!(value(second, "header"), "0x50 Reliable split");
+    assert_eq!(value(second, "split count"), "2");
+    assert_eq!(value(second, "split id"), "9");
+    assert_eq!(value(second, "split index"), "1");
+    assert_eq!(value(second, "fragment"), "4 bytes: aaaaaaaa");
+    assert!(second.find("message").is_none());
+    assert_eq!(range(second, "fragment").end, datagram.len());
+    assert!(tree.find("error").is_none());
+}
+
+#[test]
+fn acknowledgements_and_offline_messages_are_labelled() {
+    let mut ack = vec![0xc0];
+    let packets: Vec<U24> = [1, 2, 3, 7].into_iter().map(U24::new).collect();
+    write_acknowledgement(&mut ack, &packets, 1400);
+    let tree = dissect(&ack);
+    assert_eq!(value(&tree, "flags"), "0xc0 Datagram|ACK");
+    assert_eq!(value(&tree, "record count"), "2");
+    assert_eq!(value(&tree, "record 0"), "range 1-3");
+    assert_eq!(range(&tree, "record 0"), 3..10);
+    assert_eq!(value(&tree, "record 1"), "single 7");
+
+    let request = OpenConnectionRequest2 {
+        server_address: "127.0.0.1:19132".parse::<SocketAddr>().unwrap().into(),
+        max_transmission_unit: 1400,
+        client_guid: 42,
+        server_has_security: false,
+        cookie: 0,
+    };
+    let tree = dissect(&request.serialize());
+    assert!(tree.value.starts_with("offline"));
+    assert_eq!(value(&tree, "message"), "OpenConnectionRequest2");
+    assert_eq!(value(&tree, "max_transmission_unit"), "1400");
+    assert_eq!(value(&tree, "client_guid"), "42");
+    // every field has the bytes it was read from: the ID, the magic, then the address.
+    assert_eq!(range(&tree, "magic"), 1..17);
+    assert_eq!(value(&tree, "server_address"), "127.0.0.1:19132");
+    assert_eq!(range(&tree, "server_address"), 17..24);
+    assert_eq!(value(&tree, "ip"), "127.0.0.1");
+    assert_eq!(range(&tree, "ip"), 18..22);
+    assert_eq!(value(&tree, "port"), "19132");
+    assert_eq!(range(&tree, "port"), 22..24);
+    assert_eq!(range(&tree, "max_transmission_unit"), 24..26);
+    assert_eq!(range(&tree, "client_guid"), 26..34);
+}
+
+#[test]
+fn system_addresses_are_labelled_one_by_one() {
+    let v4: SocketAddr = "192.168.1.2:19132".parse().unwrap();
+    let v6: SocketAddr = "[fe80::1]:19133".parse().unwrap();
+    let accepted = ConnectionRequestAccepted {
+        client_address: v4.into(),
+        system_index: 0,
+        system_addresses: vec![v4.into(), v6.into(), v4.into()],
+        request_time_be: 1,
+        accepted_time_be: 2,
+    };
+    let mut datagram = vec![0x84, 0, 0, 0];
+    Frame { reliability: Reliability::Unreliable, data: accepted.serialize().into(), ..Default::default() }.write(&mut datagram);
+    let tree = dissect(&datagram);
+    // frame header, length, then the message ID.
+    let start = 4 + 3 + 1;
+
+    let addresses = tree.find("system_addresses").unwrap();
+    assert_eq!(addresses.value, "3 addresses");
+    assert_eq!(addresses.range, Some(start + 9..start + 9 + 7 + 29 + 7));
+    assert_eq!(addresses.children.len(), 3);
+    let second = addresses.find("system address 1").unwrap();
+    assert_eq!(second.value, "[fe80::1]:19133");
+    assert_eq!(second.range, Some(start + 16..start + 45));
+    assert_eq!(value(second, "port"), "19133");
+    assert_eq!(range(second, "ip"), start + 25..start + 41);
+    assert_eq!(value(&tree, "request_time_be"), "1");
+    assert_eq!(range(&tree, "accepted_time_be"), datagram.len() - 8..datagram.len());
+    assert!(tree.find("trailing bytes").is_none());
+}
+
+#[test]
+fn bytes_that_cant_be_decoded_end_up_in_an_error() {
+    // a frame claiming to be longer than the datagram.
+    let tree = dissect(&[0x84, 0, 0, 0, 0x00, 0xff, 0xf8, 0xfe]);
+    assert_eq!(value(&tree, "sequence number"), "0");
+    assert_eq!(range(&tree, "error"), 4..8);
+    assert!(value(&tree, "error").contains("truncated"));
+
+    // a length that isn't a whole number of bytes shows as sent.
+    let tree = dissect(&[0x84, 0, 0, 0, 0x00, 0x00, 0x1b, 0xfe, 1, 2]);
+    assert_eq!(value(&tree, "length"), "27 bits, 3 bytes");
+
+    let tree = dissect(&[0xa0, 0, 1, 1, 9]);
+    assert_eq!(value(&tree, "error"), "record is truncated");
+    assert!(dissect(&[]).find("error").is_some());
+
+    // as JSON, with the ranges as numbers.
+    let json = dissect(&[0xc0, 0, 0]).to_json();
+    assert_eq!(json, concat!(
+        r#"{"label":"datagram","value":"3 bytes","start":0,"end":3,"children":["#,
+        r#"{"label":"flags","value":"0xc0 Datagram|ACK","start":0,"end":1},"#,
+        r#"{"label":"record count","value":"0","start":1,"end":3}]}"#,
+    ));
+}
@@ -0,0 +1,150 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::impairment::{BurstLoss, ImpairedTransport, Impairment, ImpairmentStats};
+use proto::listener::Listener;
+use proto::transport::MemoryNetwork;
+
+// these tests run for seconds of real time, far too long to run under Miri.
+
+// sizes of the messages sent, from a single frame up to messages split in many fragments.
+const SIZES: [usize; 5] = [1, 200, 1400, 3000, 10000];
+
+struct Link {
+    client: Arc<Conn>,
+    server: Arc<Conn>,
+    client_stats: Arc<ImpairmentStats>,
+    server_stats: Arc<ImpairmentStats>,
+}
+
+// connect connects a client to a listener over a memory network, impairing both directions.
+async fn connect(impairment: Impairment) -> Link {
+    let network = MemoryNetwork::new();
+    let server_socket = network.bind("127.0.0.1:19132".parse().unwrap()).unwrap();
+    let server_transport = ImpairedTransport::new(Arc::new(server_socket), Impairment { seed: impairment.seed + 1, ..impairment.clone() });
+    let server_stats = server_transport.stats.clone();
+    let listener = Listener::listen_on(Arc::new(server_transport), Arc::new(SystemClock));
+
+    let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let client_transport = ImpairedTransport::new(Arc::new(client_socket), impairment);
+    let client_stats = client_transport.stats.clone();
+
+    let (client, server) = tokio::time::timeout(Duration::from_secs(30), async {
+        tokio::join!(Conn::dial_on(Arc::new(client_transport), Arc::new(SystemClock), listener.local_addr().unwrap()), listener.accept())
+    }).await.expect("handshake timed out");
+    Link { client: client.unwrap(), server: server.unwrap(), client_stats, server_stats }
+}
+
+fn messages(count: usize) -> Vec<Vec<u8>> {
+    (0..count).map(|i| {
+        let mut message = vec![i as u8; SIZES[i % SIZES.len()]];
+        // RakNet handles some message IDs itself, game packets start with 0xfe.
+        message[0] = 0xfe;
+        message
+    }).collect()
+}
+
+// transfer sends `count` messages each way and checks that both ends receive all of them exactly
+// once and in order.
+async fn transfer(link: &Link, count: usize) {
+    let messages = messages(count);
+    let send = |from: Arc<Conn>| {
+        let messages = messages.clone();
+        async move {
+            for message in &messages {
+                from.Write(message).await.unwrap();
+            }
+        }
+    };
+    let receive = |to: Arc<Conn>| {
+        let messages = messages.clone();
+        async move {
+            for (i, message) in messages.iter().enumerate() {
+                let received = to.ReadPacket().await.unwrap();
+                assert_eq!(received.len(), message.len(), "message {} has the wrong length", i);
+                assert!(received == *message, "message {} arrived out of order or corrupted", i);
+            }
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(60), async {
+        tokio::join!(
+            send(link.client.clone()),
+            send(link.server.clone()),
+            receive(link.client.clone()),
+            receive(link.server.clone()),
+        )
+    }).await.expect("transfer timed out");
+}
+
+fn dropped(link: &Link) -> u64 {
+    link.client_stats.dropped.load(Ordering::Relaxed) + link.server_stats.dropped.load(Ordering::Relaxed)
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn random_loss() {
+    let link = connect(Impairment { seed: 1, loss: 0.1, ..Default::default() }).await;
+    transfer(&link, 100).await;
+    assert!(dropped(&link) > 0);
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn burst_loss() {
+    let link = connect(Impairment { seed: 2, burst_loss: Some(BurstLoss { enter: 0.05, exit: 0.3 }), ..Default::default() }).await;
+    transfer(&link, 100).await;
+    assert!(dropped(&link) > 0);
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn reordering_and_duplication() {
+    let link = connect(Impairment { seed: 3, reorder: 0.2, reorder_delay: Duration::from_millis(15), duplicate: 0.2, ..Default::default() }).await;
+    transfer(&link, 100).await;
+    for stats in [&link.client_stats, &link.server_stats] {
+        assert!(stats.reordered.load(Ordering::Relaxed) > 0);
+        assert!(stats.duplicated.load(Ordering::Relaxed) > 0);
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn latency_and_jitter() {
+    let link = connect(Impairment { seed: 4, delay: Duration::from_millis(40), jitter: Duration::from_millis(30), ..Default::default() }).await;
+    transfer(&link, 50).await;
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn bandwidth_cap() {
+    let link = connect(Impairment { seed: 5, bandwidth: Some(256 * 1024), ..Default::default() }).await;
+    transfer(&link, 50).await;
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn mtu_blackhole() {
+    let link = connect(Impairment { seed: 6, mtu: Some(1200), ..Default::default() }).await;
+    assert!(link.client.effective_mtu() <= 1200);
+    assert!(link.server.effective_mtu() <= 1200);
+    transfer(&link, 50).await;
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn everything_at_once() {
+    let link = connect(Impairment {
+        seed: 7,
+        loss: 0.05,
+        reorder: 0.1,
+        duplicate: 0.05,
+        delay: Duration::from_millis(10),
+        jitter: Duration::from_millis(10),
+        bandwidth: Some(1024 * 1024),
+        ..Default::default()
+    }).await;
+    transfer(&link, 100).await;
+    assert!(dropped(&link) > 0);
+}
@@ -0,0 +1,164 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::listener::Listener;
+use proto::messages::open_connection_request_1::OpenConnectionRequest1;
+use proto::messages::open_connection_request_2::OpenConnectionRequest2;
+use proto::messages::unconnected_ping::UnconnectedPing;
+use proto::packet::Reliability;
+use proto::session::Session;
+use proto::stats::HandshakeFailure;
+use proto::transport::{DatagramTransport, MemoryNetwork};
+use proto::types::Packet;
+use proto::{PacketT, ReadPacket, MIN_MTU_SIZE, MTU_SIZE};
+use tokio::io::ReadBuf;
+
+/// Stalled is a transport that never finishes sending to one address, like a socket whose send
+/// buffer is full of datagrams for a peer that stopped reading.
+struct Stalled {
+    inner: Arc<dyn DatagramTransport>,
+    target: Mutex<Option<SocketAddr>>,
+}
+
+impl DatagramTransport for Stalled {
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        if *self.target.lock().unwrap() == Some(target) {
+            return Poll::Pending;
+        }
+        self.inner.poll_send_to(cx, buf, target)
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        self.inner.poll_recv_from(cx, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+fn bind(network: &MemoryNetwork, address: &str) -> Arc<dyn DatagramTransport> {
+    Arc::new(network.bind(address.parse().unwrap()).unwrap())
+}
+
+// exchange sends `message` to `target` and waits for the answer.
+async fn exchange(socket: &Arc<dyn DatagramTransport>, message: &[u8], target: SocketAddr) -> Option<PacketT> {
+    socket.send_to(message, target).await.unwrap();
+    let mut buf = [0u8; 1500];
+    let (len, _) = tokio::time::timeout(Duration::from_secs(5), socket.recv_from(&mut buf)).await
+        .expect("no answer from the listener")
+        .unwrap();
+    ReadPacket(&buf[..len]).unwrap()
+}
+
+#[tokio::test]
+async fn tiny_mtus_are_raised_to_the_smallest_one() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(bind(&network, "10.0.0.1:19132"), Arc::new(SystemClock));
+    let target = listener.local_addr().unwrap();
+
+    for (i, (requested, agreed)) in [(0, MIN_MTU_SIZE), (28, MIN_MTU_SIZE), (40, MIN_MTU_SIZE), (1200, 1200), (u16::MAX, MTU_SIZE)].into_iter().enumerate() {
+        let client = bind(&network, &format!("10.0.1.{}:50000", i));
+        let request = OpenConnectionRequest2 {
+            server_address: target.into(),
+            max_transmission_unit: requested,
+            client_guid: i as u64,
+            server_has_security: false,
+            cookie: 0,
+        };
+        let Some(PacketT::OpenConnectionReply2(reply)) = exchange(&client, &request.serialize(), target).await else {
+            panic!("no open connection reply to an MTU of {}", requested);
+        };
+        assert_eq!(reply.max_transmission_unit_be, agreed, "requested {}", requested);
+    }
+
+    // the read loop is still running.
+    let pinger = bind(&network, "10.0.2.1:50000");
+    let ping = UnconnectedPing { client_send_time_be: 1, client_guid_be: 2 };
+    assert!(matches!(exchange(&pinger, &ping.serialize(), target).await, Some(PacketT::UnconnectedPong(_))));
+}
+
+#[test]
+fn sessions_keep_room_for_their_frames() {
+    // sessions created with an MTU nobody would agree on still split messages.
+    let now = Instant::now();
+    for requested in [0, 29, 41] {
+        let mut session = Session::server(now, "10.0.1.1:50000".parse().unwrap(), 1, requested);
+        assert_eq!(session.max_transmission_unit(), MIN_MTU_SIZE);
+        assert!(session.effective_mtu() > 0);
+        session.send(now, &[0xfe; 5000], Reliability::ReliableOrdered, 0).unwrap();
+        session.flush(now);
+        let datagrams: Vec<_> = std::iter::from_fn(|| session.poll_transmit()).collect();
+        assert!(datagrams.len() > 1);
+        assert!(datagrams.iter().all(|datagram| datagram.len() <= session.effective_mtu() as usize));
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn busy_connections_dont_hold_up_the_others() {
+    let network = MemoryNetwork::new();
+    let transport = Arc::new(Stalled { inner: bind(&network, "10.0.0.1:19132"), target: Mutex::new(None) });
+    let listener = Listener::listen_on(transport.clone(), Arc::new(SystemClock));
+    let target = listener.local_addr().unwrap();
+    let busy = Conn::dial_on(bind(&network, "10.0.1.1:50000"), Arc::new(SystemClock), target).await.unwrap();
+    let busy_server = listener.accept().await.unwrap();
+    let idle = Conn::dial_on(bind(&network, "10.0.1.2:50000"), Arc::new(SystemClock), target).await.unwrap();
+    let idle_server = listener.accept().await.unwrap();
+    assert_eq!(idle_server.remote, idle.conn.local_addr().unwrap());
+
+    // nothing the listener sends to the first client gets out anymore, while it keeps sending.
+    *transport.target.lock().unwrap() = Some(busy.conn.local_addr().unwrap());
+    for _ in 0..500 {
+        busy.write_with(&[0xfe; 1000], Reliability::Reliable, 0, false).await.unwrap();
+    }
+    busy.flush().await.unwrap();
+    // the listener's end of it keeps writing too, until its writer is full and writing waits.
+    let writer = tokio::spawn(async move {
+        loop {
+            busy_server.write_with(&[0xfe; 1000], Reliability::Unreliable, 0, true).await.unwrap();
+        }
+    });
+
+    let deadline = Duration::from_secs(5);
+    idle.Write(&[0xfe, 1]).await.unwrap();
+    let received = tokio::time::timeout(deadline, idle_server.ReadPacket()).await.expect("the other connection stalled").unwrap();
+    assert_eq!(&received[..], [0xfe, 1]);
+    idle_server.Write(&[0xfe, 2]).await.unwrap();
+    let received = tokio::time::timeout(deadline, idle.ReadPacket()).await.expect("the other connection stalled").unwrap();
+    assert_eq!(&received[..], [0xfe, 2]);
+    assert!(!writer.is_finished());
+    writer.abort();
+}
+
+#[tokio::test]
+async fn banned_clients_are_turned_away() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(bind(&network, "10.0.0.1:19132"), Arc::new(SystemClock));
+    let target = listener.local_addr().unwrap();
+    let client = Conn::dial_on(bind(&network, "10.0.1.1:50000"), Arc::new(SystemClock), target).await.unwrap();
+    let _server = listener.accept().await.unwrap();
+
+    // banning closes the connections the IP has, whatever its port...
+    listener.ban("10.0.1.1".parse().unwrap());
+    let closed = tokio::time::timeout(Duration::from_secs(5), client.ReadPacket()).await.expect("the connection stayed open");
+    assert!(closed.is_err());
+
+    // ...and its requests to connect again go unanswered.
+    let socket = bind(&network, "10.0.1.1:50001");
+    let request = OpenConnectionRequest1 { client_protocol: proto::DEFAULT_PROTOCOL_VERSION, max_transmission_unit: 1400 }.serialize();
+    socket.send_to(&request, target).await.unwrap();
+    let mut buf = [0u8; 1500];
+    assert!(tokio::time::timeout(Duration::from_millis(200), socket.recv_from(&mut buf)).await.is_err(), "answered a banned client");
+    let stats = listener.stats().await;
+    assert_eq!(stats.bans, 1);
+    assert_eq!(stats.handshake_failures[HandshakeFailure::Banned as usize], 1);
+
+    listener.unban("10.0.1.1".parse().unwrap());
+    assert!(matches!(exchange(&socket, &request, target).await, Some(PacketT::OpenConnectionReply1(_))));
+    assert_eq!(listener.stats().await.bans, 0);
+}
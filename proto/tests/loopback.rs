@@ -0,0 +1,265 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, ErrorKind};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::impairment::{ImpairedTransport, Impairment};
+use proto::listener::Listener;
+use proto::packet::Reliability;
+use proto::transport::{DatagramTransport, MemoryNetwork};
+use proto::MTU_SIZE;
+use tokio::io::ReadBuf;
+use tokio::net::UdpSocket;
+
+/// Network is what a client and a listener are connected over: UDP sockets on the loopback
+/// interface, or a memory network that loses, reorders and duplicates datagrams.
+#[derive(Debug, Copy, Clone)]
+enum Network {
+    Udp,
+    Impaired,
+}
+
+const NETWORKS: [Network; 2] = [Network::Udp, Network::Impaired];
+
+const RELIABILITIES: [Reliability; 5] = [
+    Reliability::Unreliable,
+    Reliability::UnreliableSequenced,
+    Reliability::Reliable,
+    Reliability::ReliableOrdered,
+    Reliability::ReliableSequenced,
+];
+
+const CHANNELS: [u8; 3] = [0, 7, 31];
+
+/// Cut is a transport that stops sending once it is cut, like a peer that went away without
+/// saying so.
+struct Cut {
+    inner: Arc<dyn DatagramTransport>,
+    cut: AtomicBool,
+}
+
+impl DatagramTransport for Cut {
+    fn poll_send_to(&self, cx: &mut Context<'_>, buf: &[u8], target: SocketAddr) -> Poll<io::Result<usize>> {
+        if self.cut.load(Ordering::Relaxed) {
+            return Poll::Ready(Ok(buf.len()));
+        }
+        self.inner.poll_send_to(cx, buf, target)
+    }
+
+    fn poll_recv_from(&self, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<SocketAddr>> {
+        self.inner.poll_recv_from(cx, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+struct Link {
+    listener: Arc<Listener>,
+    client: Arc<Conn>,
+    server: Arc<Conn>,
+    client_transport: Arc<Cut>,
+}
+
+// connect starts a listener and dials it, running the offline and the connected handshake.
+async fn connect(network: Network) -> Link {
+    let (listener, client_transport): (Arc<Listener>, Arc<dyn DatagramTransport>) = match network {
+        Network::Udp => (
+            Listener::listen("127.0.0.1:0").await.unwrap(),
+            Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap()),
+        ),
+        Network::Impaired => {
+            let impairment = Impairment {
+                seed: 11,
+                loss: 0.02,
+                reorder: 0.05,
+                duplicate: 0.02,
+                delay: Duration::from_millis(2),
+                jitter: Duration::from_millis(2),
+                ..Default::default()
+            };
+            let network = MemoryNetwork::new();
+            let server_socket = network.bind("127.0.0.1:19132".parse().unwrap()).unwrap();
+            let server_transport = ImpairedTransport::new(Arc::new(server_socket), Impairment { seed: 12, ..impairment.clone() });
+            let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            (
+                Listener::listen_on(Arc::new(server_transport), Arc::new(SystemClock)),
+                Arc::new(ImpairedTransport::new(Arc::new(client_socket), impairment)),
+            )
+        }
+    };
+    let client_transport = Arc::new(Cut { inner: client_transport, cut: AtomicBool::new(false) });
+    let (client, server) = tokio::time::timeout(Duration::from_secs(30), async {
+        tokio::join!(
+            Conn::dial_on(client_transport.clone(), Arc::new(SystemClock), listener.local_addr().unwrap()),
+            listener.accept(),
+        )
+    }).await.expect("handshake timed out");
+    Link { listener, client: client.unwrap(), server: server.unwrap(), client_transport }
+}
+
+// message builds a game packet of `size` bytes that names the reliability, channel and number it
+// was sent with, filled with a pattern that shows corruption.
+fn message(reliability: usize, channel: u8, n: u32, size: usize) -> Vec<u8> {
+    let mut message = vec![0xfe, reliability as u8, channel];
+    message.extend_from_slice(&n.to_be_bytes());
+    message.extend((message.len()..size.max(message.len())).map(|i| (i as u32 ^ n) as u8));
+    message
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn handshake() {
+    for network in NETWORKS {
+        let link = connect(network).await;
+        assert_eq!(link.server.remote, link.client.conn.local_addr().unwrap(), "{:?}", network);
+        assert_eq!(link.client.remote, link.listener.local_addr().unwrap());
+        assert_eq!(link.client.effective_mtu(), link.server.effective_mtu());
+        assert!(link.client.effective_mtu() <= MTU_SIZE);
+        assert_eq!(link.listener.stats().await.accepted, 1);
+        assert!(!link.client.is_closed() && !link.server.is_closed());
+    }
+
+    // the listener answers unconnected pings over UDP with its pong data.
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    listener.set_pong_data("MCPE;Loopback;786;1.21.73;0;10;1;Bedrock level;Survival;1;19132;19133;0;".to_string());
+    let (status, _) = proto::query::ping(listener.local_addr().unwrap(), Duration::from_secs(5)).await.unwrap();
+    assert_eq!(status.motd, "Loopback");
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn messages_of_many_sizes_are_reassembled() {
+    for network in NETWORKS {
+        let link = connect(network).await;
+        let mtu = link.client.effective_mtu() as usize;
+        // around the size at which messages are split, and messages split in many fragments.
+        let mut sizes: Vec<usize> = vec![1, 2, 3, 100];
+        sizes.extend(mtu - 40..mtu + 10);
+        sizes.extend([2 * mtu, 10_000, 100_000]);
+
+        let client = link.client.clone();
+        let sent = sizes.clone();
+        let send = tokio::spawn(async move {
+            for (n, size) in sent.into_iter().enumerate() {
+                client.Write(&message(0, 0, n as u32, size)).await.unwrap();
+            }
+        });
+        // the server echoes every message back.
+        let server = link.server.clone();
+        let count = sizes.len();
+        let echo = tokio::spawn(async move {
+            for _ in 0..count {
+                let received = server.ReadPacket().await.unwrap();
+                server.Write(&received).await.unwrap();
+            }
+        });
+        tokio::time::timeout(Duration::from_secs(60), async {
+            for (n, size) in sizes.iter().enumerate() {
+                let received = link.client.ReadPacket().await.unwrap();
+                assert!(received == message(0, 0, n as u32, *size), "{:?}: message {} of {} bytes", network, n, size);
+            }
+        }).await.expect("echo timed out");
+        send.await.unwrap();
+        echo.await.unwrap();
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn every_reliability_and_channel() {
+    const ROUNDS: u32 = 5;
+    const SIZES: [usize; 4] = [8, 500, 1400, 5000];
+    for network in NETWORKS {
+        let link = connect(network).await;
+        for n in 0..ROUNDS {
+            for (r, reliability) in RELIABILITIES.iter().enumerate() {
+                for channel in CHANNELS {
+                    let data = message(r, channel, n, SIZES[n as usize % SIZES.len()]);
+                    link.client.write_with(&data, *reliability, channel, false).await.unwrap();
+                }
+            }
+        }
+        link.client.flush().await.unwrap();
+
+        // wait for every reliable message, then for whatever else is still on its way.
+        let reliable = |r: u8| matches!(RELIABILITIES[r as usize], Reliability::Reliable | Reliability::ReliableOrdered);
+        let expected = 2 * CHANNELS.len() * ROUNDS as usize;
+        let mut received: Vec<(u8, u8, u32)> = Vec::new();
+        let mut reliable_received = 0;
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(30);
+        loop {
+            let wait = if reliable_received == expected { tokio::time::Instant::now() + Duration::from_millis(200) } else { deadline };
+            let Ok(data) = tokio::time::timeout_at(wait, link.server.ReadPacket()).await else { break };
+            let data = data.unwrap();
+            let (r, channel, n) = (data[1], data[2], u32::from_be_bytes(data[3..7].try_into().unwrap()));
+            assert!(data == message(r as usize, channel, n, SIZES[n as usize % SIZES.len()]), "{:?}: corrupted message", network);
+            reliable_received += reliable(r) as usize;
+            received.push((r, channel, n));
+        }
+        assert_eq!(reliable_received, expected, "{:?}: reliable messages went missing", network);
+
+        let unique: HashSet<_> = received.iter().collect();
+        assert_eq!(unique.len(), received.len(), "{:?}: a message arrived twice", network);
+        let mut ordered: BTreeMap<u8, Vec<u32>> = BTreeMap::new();
+        let mut sequenced: BTreeMap<u8, Vec<u32>> = BTreeMap::new();
+        for (r, channel, n) in received {
+            match RELIABILITIES[r as usize] {
+                Reliability::ReliableOrdered => ordered.entry(channel).or_default().push(n),
+                Reliability::UnreliableSequenced | Reliability::ReliableSequenced => sequenced.entry(channel).or_default().push(n * 2 + (r == 4) as u32),
+                _ => {}
+            }
+        }
+        for channel in CHANNELS {
+            assert_eq!(ordered[&channel], (0..ROUNDS).collect::<Vec<_>>(), "{:?}: channel {} out of order", network, channel);
+            // sequenced messages may be dropped, but never arrive after a newer one.
+            let sequence = sequenced.remove(&channel).unwrap_or_default();
+            assert!(sequence.windows(2).all(|w| w[0] < w[1]), "{:?}: channel {} sequenced out of order: {:?}", network, channel, sequence);
+        }
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn disconnect_reaches_the_other_end() {
+    for network in NETWORKS {
+        // either end may close the connection.
+        for client_closes in [true, false] {
+            let link = connect(network).await;
+            let (closing, other) = if client_closes { (&link.client, &link.server) } else { (&link.server, &link.client) };
+            closing.close().await.unwrap();
+            let err = tokio::time::timeout(Duration::from_secs(10), other.ReadPacket()).await
+                .expect("disconnect didn't arrive")
+                .unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::ConnectionAborted, "{:?}: {}", network, err);
+            assert!(other.is_closed());
+            assert!(closing.Write(&[0xfe]).await.is_err());
+        }
+    }
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn silent_peers_time_out() {
+    for network in NETWORKS {
+        let link = connect(network).await;
+        link.client_transport.cut.store(true, Ordering::Relaxed);
+        let err = tokio::time::timeout(Duration::from_secs(15), link.server.ReadPacket()).await
+            .expect("the server never gave up on the client")
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TimedOut, "{:?}: {}", network, err);
+    }
+
+    // dialing an address nobody listens on fails once every handshake attempt went unanswered.
+    let nobody = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let dial = tokio::time::timeout(Duration::from_secs(15), Conn::dial(nobody.local_addr().unwrap())).await;
+    let Err(err) = dial.expect("dial never gave up") else {
+        panic!("dialed an address nobody listens on");
+    };
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+}
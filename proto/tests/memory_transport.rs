@@ -0,0 +1,113 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::listener::Listener;
+use proto::transport::{DatagramTransport, MemoryNetwork, ReceiveBuffer};
+
+const PEERS: usize = 32;
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn many_peers_connect_to_a_listener() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(Arc::new(network.bind("0.0.0.0:19132".parse().unwrap()).unwrap()), Arc::new(SystemClock));
+    let server_addr = listener.local_addr().unwrap();
+
+    let server = tokio::spawn({
+        let listener = listener.clone();
+        async move {
+            for _ in 0..PEERS {
+                let conn = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    // echo every message back to the client that sent it.
+                    while let Ok(data) = conn.ReadPacket().await {
+                        conn.Write(&data).await.unwrap();
+                    }
+                });
+            }
+        }
+    });
+
+    let mut clients = Vec::new();
+    for i in 0..PEERS {
+        let socket = network.bind("0.0.0.0:0".parse().unwrap()).unwrap();
+        clients.push(tokio::spawn(async move {
+            let conn = Conn::dial_on(Arc::new(socket), Arc::new(SystemClock), server_addr).await?;
+            // large enough to be split over several frames. Messages start with an ID like game
+            // packets do, RakNet handles IDs such as 0x00 (connected ping) itself.
+            let mut message = vec![i as u8; 3000 + i];
+            message[0] = 0xfe;
+            conn.Write(&message).await?;
+            assert_eq!(conn.ReadPacket().await?, message);
+            conn.close().await?;
+            Ok::<_, io::Error>(())
+        }));
+    }
+    for client in clients {
+        tokio::time::timeout(Duration::from_secs(10), client).await.unwrap().unwrap().unwrap();
+    }
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn datagrams_reach_the_bound_address() {
+    let network = MemoryNetwork::new();
+    let a: Arc<dyn DatagramTransport> = Arc::new(network.bind("127.0.0.1:0".parse().unwrap()).unwrap());
+    let b: Arc<dyn DatagramTransport> = Arc::new(network.bind("127.0.0.1:0".parse().unwrap()).unwrap());
+    assert_ne!(a.local_addr().unwrap(), b.local_addr().unwrap());
+
+    // nobody is bound here, so the datagram is dropped silently like on a real network.
+    a.send_to(b"lost", "127.0.0.1:1".parse().unwrap()).await.unwrap();
+    a.send_to(b"hello", b.local_addr().unwrap()).await.unwrap();
+
+    let mut buf = [0u8; 3];
+    let (len, src) = b.recv_from(&mut buf).await.unwrap();
+    assert_eq!(&buf[..len], b"hel");
+    assert_eq!(src, a.local_addr().unwrap());
+}
+
+#[tokio::test]
+async fn receive_buffer_reuses_its_memory() {
+    let network = MemoryNetwork::new();
+    let a: Arc<dyn DatagramTransport> = Arc::new(network.bind("127.0.0.1:0".parse().unwrap()).unwrap());
+    let b: Arc<dyn DatagramTransport> = Arc::new(network.bind("127.0.0.1:0".parse().unwrap()).unwrap());
+    let mut buffer = ReceiveBuffer::new();
+
+    a.send_to(b"first", b.local_addr().unwrap()).await.unwrap();
+    a.send_to(b"second", b.local_addr().unwrap()).await.unwrap();
+    let (first, src) = buffer.recv_from(&b).await.unwrap();
+    assert_eq!(src, a.local_addr().unwrap());
+    // datagrams still held on to are left alone by the next ones.
+    let (second, _) = buffer.recv_from(&b).await.unwrap();
+    assert_eq!(&first[..], b"first");
+    assert_eq!(&second[..], b"second");
+
+    let start = first.as_ptr();
+    drop((first, second));
+
+    // datagrams are received one after another into the same allocation, which is reused from
+    // its start once it is used up and nothing received into it is left.
+    let datagram = [0xfe; 1000];
+    let mut pointers = Vec::new();
+    for _ in 0..200 {
+        a.send_to(&datagram, b.local_addr().unwrap()).await.unwrap();
+        let (received, _) = buffer.recv_from(&b).await.unwrap();
+        assert_eq!(&received[..], &datagram[..]);
+        pointers.push(received.as_ptr());
+    }
+    assert!(pointers.contains(&start), "the receive buffer never reused its memory");
+}
+
+#[test]
+fn binding_a_bound_address_fails() {
+    let network = MemoryNetwork::new();
+    let address: SocketAddr = "10.0.0.1:19132".parse().unwrap();
+    let socket = network.bind(address).unwrap();
+    assert_eq!(network.bind(address).err().unwrap().kind(), io::ErrorKind::AddrInUse);
+
+    drop(socket);
+    assert!(network.bind(address).is_ok());
+}
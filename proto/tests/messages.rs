@@ -0,0 +1,152 @@
+use std::net::SocketAddr;
+use proptest::prelude::*;
+use proto::address::{Addr, AddrType, Address};
+use proto::messages::connected_ping::ConnectedPing;
+use proto::messages::connected_pong::ConnectedPong;
+use proto::messages::connection_request::ConnectionRequest;
+use proto::messages::connection_request_accepted::ConnectionRequestAccepted;
+use proto::messages::disconnect_notification::DisconnectNotification;
+use proto::messages::incompatible_protocol_version::IncompatibleProtocolVersion;
+use proto::messages::new_incoming_connection::NewIncomingConnection;
+use proto::messages::open_connection_reply_1::OpenConnectionReply1;
+use proto::messages::open_connection_reply_2::OpenConnectionReply2;
+use proto::messages::open_connection_request_1::OpenConnectionRequest1;
+use proto::messages::open_connection_request_2::OpenConnectionRequest2;
+use proto::messages::unconnected_ping::UnconnectedPing;
+use proto::messages::unconnected_pong::UnconnectedPong;
+use proto::messages::unknown::UnknownPacket;
+use proto::types::{Packet, PacketId};
+use proto::{PacketT, ReadPacket};
+
+fn address() -> impl Strategy<Value = Address> {
+    prop_oneof![
+        any::<SocketAddr>().prop_map(Address::from),
+        (any::<[u8; 4]>(), any::<u16>()).prop_map(|(ip, port)| Address { addr: Addr::Addr4(ip), port, addr_type: AddrType::Zero }),
+    ]
+}
+
+// decode checks that a message starts with its id and is read back as the variant `ReadPacket`
+// dispatches it to, then decodes it.
+fn decode<T: Packet>(id: PacketId, data: &[u8], variant: fn(&PacketT) -> bool) -> T {
+    assert_eq!(data[0], id as u8);
+    assert!(variant(&ReadPacket(data).unwrap().unwrap()));
+    T::deserialize(&data[1..]).unwrap()
+}
+
+proptest! {
+    // a few cases are enough under Miri, which runs them thousands of times slower.
+    #![proptest_config(ProptestConfig::with_cases(if cfg!(miri) { 4 } else { 256 }))]
+
+    #[test]
+    fn unconnected_ping_round_trips(client_send_time_be: u64, client_guid_be: u64) {
+        let message = UnconnectedPing { client_send_time_be, client_guid_be };
+        let data = message.serialize();
+        prop_assert_eq!(decode::<UnconnectedPing>(PacketId::UnconnectedPing, &data, |p| matches!(p, PacketT::UnconnectedPing(_))), message);
+    }
+
+    #[test]
+    fn unconnected_pong_round_trips(client_send_time_be: u64, server_guid_be: u64, data in "\\PC{0,200}") {
+        let message = UnconnectedPong { client_send_time_be, server_guid_be, data };
+        let data = message.serialize();
+        prop_assert_eq!(decode::<UnconnectedPong>(PacketId::UnconnectedPong, &data, |p| matches!(p, PacketT::UnconnectedPong(_))), message);
+    }
+
+    #[test]
+    fn open_connection_request_1_is_padded_to_the_mtu(client_protocol: u8, max_transmission_unit in 46u16..) {
+        let message = OpenConnectionRequest1 { client_protocol, max_transmission_unit };
+        let data = message.serialize();
+        // with the IP and UDP headers, the datagram is exactly as big as the MTU.
+        prop_assert_eq!(data.len() + 20 + 8, max_transmission_unit as usize);
+        prop_assert_eq!(decode::<OpenConnectionRequest1>(PacketId::OpenConnectionRequest1, &data, |p| matches!(p, PacketT::OpenConnectionRequest1(_))), message);
+    }
+
+    #[test]
+    fn open_connection_request_1_fits_its_fields_below_the_smallest_mtu(client_protocol: u8, max_transmission_unit in 0u16..46) {
+        let data = OpenConnectionRequest1 { client_protocol, max_transmission_unit }.serialize();
+        prop_assert_eq!(data.len(), 1 + 16 + 1);
+        let message = OpenConnectionRequest1::deserialize(&data[1..]).unwrap();
+        prop_assert_eq!(message.client_protocol, client_protocol);
+        prop_assert_eq!(message.max_transmission_unit, 46);
+    }
+
+    #[test]
+    fn open_connection_reply_1_round_trips(server_guid_be: u64, cookie: Option<u32>, max_transmission_unit_be: u16) {
+        let message = OpenConnectionReply1 { server_guid_be, server_has_security: cookie.is_some(), cookie: cookie.unwrap_or(0), max_transmission_unit_be };
+        let data = message.serialize();
+        prop_assert_eq!(decode::<OpenConnectionReply1>(PacketId::OpenConnectionReply1, &data, |p| matches!(p, PacketT::OpenConnectionReply1(_))), message);
+    }
+
+    #[test]
+    fn open_connection_request_2_round_trips(server_address in address(), max_transmission_unit: u16, client_guid: u64, cookie: Option<u32>) {
+        let message = OpenConnectionRequest2 { server_address, max_transmission_unit, client_guid, server_has_security: cookie.is_some(), cookie: cookie.unwrap_or(0) };
+        let data = message.serialize();
+        prop_assert_eq!(decode::<OpenConnectionRequest2>(PacketId::OpenConnectionRequest2, &data, |p| matches!(p, PacketT::OpenConnectionRequest2(_))), message);
+    }
+
+    #[test]
+    fn open_connection_reply_2_round_trips(server_guid_be: u64, client_address in address(), max_transmission_unit_be: u16, do_security: bool) {
+        let message = OpenConnectionReply2 { server_guid_be, client_address, max_transmission_unit_be, do_security };
+        let data = message.serialize();
+        prop_assert_eq!(decode::<OpenConnectionReply2>(PacketId::OpenConnectionReply2, &data, |p| matches!(p, PacketT::OpenConnectionReply2(_))), message);
+    }
+
+    #[test]
+    fn incompatible_protocol_version_round_trips(server_protocol: u8, server_guid_be: u64) {
+        let message = IncompatibleProtocolVersion { server_protocol, server_guid_be };
+        let data = message.serialize();
+        prop_assert_eq!(decode::<IncompatibleProtocolVersion>(PacketId::IncompatibleProtocolVersion, &data, |p| matches!(p, PacketT::IncompatibleProtocolVersion(_))), message);
+    }
+
+    #[test]
+    fn connected_ping_and_pong_round_trip(client_send_time_be: u64, server_send_time_be: u64) {
+        let ping = ConnectedPing { client_send_time_be };
+        prop_assert_eq!(decode::<ConnectedPing>(PacketId::ConnectedPing, &ping.serialize(), |p| matches!(p, PacketT::ConnectedPing(_))), ping);
+        let pong = ConnectedPong { client_send_time_be, server_send_time_be };
+        prop_assert_eq!(decode::<ConnectedPong>(PacketId::ConnectedPong, &pong.serialize(), |p| matches!(p, PacketT::ConnectedPong(_))), pong);
+    }
+
+    #[test]
+    fn connection_request_round_trips(client_guid_be: u64, request_time_be: u64, secure: bool) {
+        let message = ConnectionRequest { client_guid_be, request_time_be, secure };
+        let data = message.serialize();
+        prop_assert_eq!(decode::<ConnectionRequest>(PacketId::ConnectionRequest, &data, |p| matches!(p, PacketT::ConnectionRequest(_))), message);
+    }
+
+    #[test]
+    fn connection_request_accepted_round_trips(
+        client_address in address(),
+        system_index: u16,
+        system_addresses in prop::collection::vec(address(), 0..20),
+        request_time_be: u64,
+        accepted_time_be: u64,
+    ) {
+        let message = ConnectionRequestAccepted { client_address, system_index, system_addresses, request_time_be, accepted_time_be };
+        let data = message.serialize();
+        prop_assert_eq!(decode::<ConnectionRequestAccepted>(PacketId::ConnectionRequestAccepted, &data, |p| matches!(p, PacketT::ConnectionRequestAccepted(_))), message);
+    }
+
+    #[test]
+    fn new_incoming_connection_round_trips(
+        server_address in address(),
+        system_addresses in prop::collection::vec(address(), 0..20),
+        request_time_be: u64,
+        accepted_time_be: u64,
+    ) {
+        let message = NewIncomingConnection { server_address, system_addresses, request_time_be, accepted_time_be };
+        let data = message.serialize();
+        prop_assert_eq!(decode::<NewIncomingConnection>(PacketId::NewIncomingConnection, &data, |p| matches!(p, PacketT::NewIncomingConnection(_))), message);
+    }
+
+    #[test]
+    fn unknown_packets_round_trip(id in 0x80u8.., data in prop::collection::vec(any::<u8>(), 0..100)) {
+        let message = UnknownPacket { id, data: data.into() };
+        prop_assert_eq!(UnknownPacket::deserialize(&message.serialize()).unwrap(), message);
+    }
+}
+
+#[test]
+fn disconnect_notification_round_trips() {
+    let data = DisconnectNotification {}.serialize();
+    let message = decode::<DisconnectNotification>(PacketId::DisconnectNotification, &data, |p| matches!(p, PacketT::DisconnectNotification(_)));
+    assert_eq!(message, DisconnectNotification {});
+}
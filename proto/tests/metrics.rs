@@ -0,0 +1,119 @@
+#![cfg(feature = "metrics")]
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::listener::Listener;
+use proto::messages::open_connection_request_1::OpenConnectionRequest1;
+use proto::metrics;
+use proto::stats::{HandshakeFailure, ListenerStats};
+use proto::transport::{DatagramTransport, MemoryNetwork};
+use proto::types::{Packet, PacketId};
+
+async fn get(address: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(address).await.unwrap();
+    stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[test]
+fn histogram_buckets_are_cumulative() {
+    let mut stats = ListenerStats::default();
+    stats.total.rtt_histogram[0] = 2;
+    stats.total.rtt_histogram[4] = 3;
+    stats.total.rtt_histogram[10] = 1;
+    stats.total.rtt_sum = Duration::from_millis(5250);
+    stats.handshake_failures[HandshakeFailure::TimedOut as usize] = 4;
+    stats.bans = 2;
+    stats.total.messages_received.insert(PacketId::GamePacket as u8, 7);
+
+    let text = metrics::encode(&stats);
+    for line in [
+        "raknet_rtt_seconds_bucket{le=\"0.005\"} 2",
+        "raknet_rtt_seconds_bucket{le=\"0.05\"} 2",
+        "raknet_rtt_seconds_bucket{le=\"0.1\"} 5",
+        "raknet_rtt_seconds_bucket{le=\"5.0\"} 5",
+        "raknet_rtt_seconds_bucket{le=\"+Inf\"} 6",
+        "raknet_rtt_seconds_count 6",
+        "raknet_rtt_seconds_sum 5.25",
+        "raknet_handshake_failures_total{reason=\"TimedOut\"} 4",
+        "raknet_handshake_failures_total{reason=\"Malformed\"} 0",
+        "raknet_handshake_failures_total{reason=\"Banned\"} 0",
+        "raknet_bans 2",
+        "raknet_messages_received_total{packet=\"GamePacket\"} 7",
+        "raknet_resend_ratio 0",
+    ] {
+        assert!(text.lines().any(|l| l == line), "missing {:?} in\n{}", line, text);
+    }
+    assert!(text.ends_with("# EOF\n"));
+}
+
+#[tokio::test]
+async fn endpoint_serves_listener_metrics() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(Arc::new(network.bind("127.0.0.1:19132".parse().unwrap()).unwrap()), Arc::new(SystemClock));
+    let address = metrics::serve("127.0.0.1:0", listener.clone()).await.unwrap();
+
+    let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let (client, server) = tokio::join!(
+        Conn::dial_on(Arc::new(client_socket), Arc::new(SystemClock), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+    for i in 0..3 {
+        client.Write(&[0xfe, i]).await.unwrap();
+        server.ReadPacket().await.unwrap();
+    }
+    // a client of another protocol version is turned away.
+    let outdated: Arc<dyn DatagramTransport> = Arc::new(network.bind("127.0.0.1:0".parse().unwrap()).unwrap());
+    let request = OpenConnectionRequest1 { client_protocol: 10, max_transmission_unit: 1400 }.serialize();
+    outdated.send_to(&request, listener.local_addr().unwrap()).await.unwrap();
+
+    // the connections put their stats together every second, so the messages show up in time.
+    let response = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let response = get(address, "/metrics").await;
+            if response.lines().any(|l| l == "raknet_messages_received_total{packet=\"GamePacket\"} 3") {
+                return response;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }).await.expect("the messages were never counted");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains(&format!("Content-Type: {}\r\n", metrics::CONTENT_TYPE)));
+    for line in [
+        "raknet_connections 1",
+        "raknet_connections_accepted_total 1",
+        "raknet_handshake_failures_total{reason=\"IncompatibleProtocol\"} 1",
+        "raknet_messages_received_total{packet=\"GamePacket\"} 3",
+        "raknet_messages_received_total{packet=\"NewIncomingConnection\"} 1",
+        "raknet_messages_sent_total{packet=\"ConnectionRequestAccepted\"} 1",
+    ] {
+        assert!(response.lines().any(|l| l == line), "missing {:?} in\n{}", line, response);
+    }
+    let samples = response.lines().find_map(|l| l.strip_prefix("raknet_rtt_seconds_count ")).unwrap();
+    assert!(samples.parse::<u64>().unwrap() > 0);
+
+    assert!(get(address, "/").await.starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[tokio::test]
+async fn clients_that_send_no_request_are_disconnected() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(Arc::new(network.bind("127.0.0.1:19132".parse().unwrap()).unwrap()), Arc::new(SystemClock));
+    let address = metrics::serve("127.0.0.1:0", listener).await.unwrap();
+
+    let mut silent = TcpStream::connect(address).await.unwrap();
+    // others are served meanwhile.
+    assert!(get(address, "/metrics").await.starts_with("HTTP/1.1 200 OK\r\n"));
+    let mut response = Vec::new();
+    tokio::time::timeout(Duration::from_secs(10), silent.read_to_end(&mut response)).await
+        .expect("the connection was kept open")
+        .unwrap();
+    assert!(response.is_empty());
+}
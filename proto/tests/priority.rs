@@ -0,0 +1,136 @@
+use std::net::SocketAddr;
+use std::time::Instant;
+use proto::packet::{Packet as Frame, Priority, Reliability};
+use proto::session::Session;
+use proto::types::U24;
+
+fn session() -> (Session, Instant) {
+    let start = Instant::now();
+    let remote: SocketAddr = "127.0.0.1:19132".parse().unwrap();
+    (Session::server(start, remote, 1, 1492), start)
+}
+
+// datagrams takes the datagrams the session wants to send, decoding the contents of their frames.
+fn datagrams(session: &mut Session) -> Vec<Vec<Vec<u8>>> {
+    std::iter::from_fn(|| session.poll_transmit()).map(|datagram| {
+        let mut data = &datagram[4..];
+        let mut frames = Vec::new();
+        while !data.is_empty() {
+            let (frame, n) = Frame::read(data).unwrap();
+            frames.push(frame.data.to_vec());
+            data = &data[n..];
+        }
+        frames
+    }).collect()
+}
+
+fn ack(first: u32, last: u32) -> Vec<u8> {
+    let mut ack = vec![0xc0, 0, 1, 0];
+    ack.extend_from_slice(&U24::new(first).to_le_bytes());
+    ack.extend_from_slice(&U24::new(last).to_le_bytes());
+    ack
+}
+
+// fill_window sends a burst far larger than what may be in flight, filled with `fill`, and takes
+// the datagrams sent of it so far.
+fn fill_window(session: &mut Session, start: Instant, fill: u8, priority: Priority) -> usize {
+    session.send_with_priority(start, &vec![fill; 300_000], Reliability::ReliableOrdered, priority, 0).unwrap();
+    let sent = datagrams(session);
+    assert!(sent.iter().flatten().all(|frame| frame.iter().all(|b| *b == fill)));
+    sent.len()
+}
+
+#[test]
+fn low_priority_bursts_dont_hold_back_other_messages() {
+    let (mut session, start) = session();
+    fill_window(&mut session, start, 3, Priority::Low);
+
+    // nothing more goes out until datagrams are acknowledged...
+    for i in 0..3 {
+        session.send_with_priority(start, &[1, i], Reliability::ReliableOrdered, Priority::High, 0).unwrap();
+    }
+    session.flush(start);
+    assert!(datagrams(&mut session).is_empty());
+
+    // ...and then the high priority messages go out after at most one more low priority fragment,
+    // whose turn it may have been already.
+    session.handle_datagram(start, &ack(0, 1)).unwrap();
+    let sent = datagrams(&mut session);
+    assert_eq!(sent.len(), 2);
+    assert!(sent.contains(&vec![vec![1, 0], vec![1, 1], vec![1, 2]]));
+
+    // immediate messages don't wait for anything.
+    session.send_with_priority(start, &[0, 0], Reliability::ReliableOrdered, Priority::Immediate, 0).unwrap();
+    let sent = datagrams(&mut session);
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0][0], vec![0, 0]);
+}
+
+#[test]
+fn low_priority_gets_its_share() {
+    let (mut session, start) = session();
+    fill_window(&mut session, start, 1, Priority::High);
+    session.send_with_priority(start, &vec![3; 300_000], Reliability::ReliableOrdered, Priority::Low, 0).unwrap();
+    assert!(datagrams(&mut session).is_empty());
+
+    // every acknowledged datagram makes room for one more, high priority getting four times the
+    // share of low priority.
+    let mut sent = Vec::new();
+    for sequence_number in 0..100 {
+        session.handle_datagram(start, &ack(sequence_number, sequence_number)).unwrap();
+        for datagram in datagrams(&mut session) {
+            sent.push(datagram[0][0]);
+        }
+    }
+    assert_eq!(sent.len(), 100);
+    let low = sent.iter().filter(|fill| **fill == 3).count();
+    assert!((19..=21).contains(&low), "low priority sent {} of 100 datagrams", low);
+}
+
+#[test]
+fn split_messages_interleave_with_smaller_ones() {
+    let (mut session, start) = session();
+    fill_window(&mut session, start, 2, Priority::Medium);
+    session.send(start, &[4, 0], Reliability::ReliableOrdered, 0).unwrap();
+    session.send(start, &[4, 1], Reliability::ReliableOrdered, 0).unwrap();
+
+    // the fragments of the burst take turns with the messages sent after it.
+    session.handle_datagram(start, &ack(0, 1)).unwrap();
+    let sent = datagrams(&mut session);
+    assert_eq!(sent.len(), 2);
+    assert!(sent[0].iter().all(|frame| frame[0] == 2));
+    assert_eq!(sent[1], vec![vec![4, 0], vec![4, 1]]);
+}
+
+#[test]
+fn immediate_messages_only_get_a_little_more_room() {
+    let (mut session, start) = session();
+    let in_flight = fill_window(&mut session, start, 3, Priority::Low);
+
+    // the first immediate messages go out although the window is full, but not all of them.
+    for i in 0..100 {
+        session.send_with_priority(start, &[vec![1, i], vec![0; 1000]].concat(), Reliability::Reliable, Priority::Immediate, 0).unwrap();
+    }
+    let sent = datagrams(&mut session);
+    assert!(!sent.is_empty() && sent.len() < 20, "{} datagrams sent", sent.len());
+    assert!(sent.iter().flatten().all(|frame| frame[0] == 1));
+    assert_eq!(session.stats().in_flight, (in_flight + sent.len()) as u64);
+
+    // the rest follows as datagrams are acknowledged.
+    session.handle_datagram(start, &ack(0, 1)).unwrap();
+    let more = datagrams(&mut session);
+    assert_eq!(more.len(), 2);
+    assert!(more.iter().flatten().all(|frame| frame[0] == 1));
+}
+
+#[test]
+fn everything_queued_is_sent_before_closing() {
+    let (mut session, start) = session();
+    let in_flight = fill_window(&mut session, start, 3, Priority::Low);
+    session.close(start);
+    // the rest of the burst, followed by the disconnect notification in a datagram of its own.
+    let sent = datagrams(&mut session);
+    let fragments = 300_000usize.div_ceil(1440);
+    assert_eq!(in_flight + sent.len() - 1, fragments);
+    assert_eq!(sent.last().unwrap(), &vec![vec![0x15]]);
+}
@@ -0,0 +1,109 @@
+use std::io::ErrorKind;
+use std::time::Duration;
+use proto::listener::Listener;
+use proto::query::{ping, Status};
+use proto::types::json_string;
+use tokio::net::UdpSocket;
+
+const VANILLA: &str = "MCPE;Dedicated Server;786;1.21.73;3;10;11954621141260796043;Bedrock level;Survival;1;19132;19133;0;";
+
+fn status() -> Status {
+    Status {
+        edition: "MCPE".to_string(),
+        motd: "Dedicated Server".to_string(),
+        protocol_version: 786,
+        version: "1.21.73".to_string(),
+        player_count: 3,
+        max_player_count: 10,
+        server_guid: Some(11954621141260796043),
+        sub_motd: "Bedrock level".to_string(),
+        game_mode: "Survival".to_string(),
+        game_mode_numeric: Some(1),
+        port_v4: Some(19132),
+        port_v6: Some(19133),
+    }
+}
+
+#[test]
+fn statuses_round_trip() {
+    assert_eq!(VANILLA.parse::<Status>().unwrap(), status());
+    assert_eq!(status().to_string(), VANILLA);
+    assert_eq!(status().to_string().parse::<Status>().unwrap(), status());
+    assert_eq!(Status::default().to_string().parse::<Status>().unwrap(), Status::default());
+}
+
+#[test]
+fn trailing_fields_are_optional() {
+    // servers other than vanilla Bedrock often only send the first six fields.
+    let status: Status = "MCPE;Proxy;786;1.21.73;3;10".parse().unwrap();
+    assert_eq!(status.motd, "Proxy");
+    assert_eq!(status.player_count, 3);
+    assert_eq!(status.server_guid, None);
+    assert_eq!(status.game_mode, "");
+    assert_eq!((status.game_mode_numeric, status.port_v4, status.port_v6), (None, None, None));
+    assert_eq!(status.to_string().parse::<Status>().unwrap(), status);
+
+    // fields after the required ones that don't parse are left out rather than failing.
+    let status: Status = "MCPE;Proxy;786;1.21.73;3;10;;;;mode;port;".parse().unwrap();
+    assert_eq!((status.server_guid, status.game_mode_numeric, status.port_v4, status.port_v6), (None, None, None, None));
+    // a GUID of 0 is one.
+    let status: Status = "MCPE;Proxy;786;1.21.73;3;10;0".parse().unwrap();
+    assert_eq!(status.server_guid, Some(0));
+}
+
+#[test]
+fn malformed_statuses_are_errors() {
+    for malformed in [
+        "",
+        "MCPE",
+        "MCPE;Dedicated Server;786;1.21.73;3",
+        "MCPE;Dedicated Server;protocol;1.21.73;3;10",
+        "MCPE;Dedicated Server;786;1.21.73;-3;10",
+        "MCPE;Dedicated Server;786;1.21.73;3;ten",
+        "MCPE;Dedicated Server;786;1.21.73;3;99999999999",
+        // a GUID that doesn't parse can't be told apart from one that is missing or 0.
+        "MCPE;Dedicated Server;786;1.21.73;3;10;guid",
+        "MCPE;Dedicated Server;786;1.21.73;3;10;-1;Bedrock level",
+    ] {
+        assert!(malformed.parse::<Status>().is_err(), "{:?} parsed", malformed);
+    }
+}
+
+#[test]
+fn json_strings_are_escaped() {
+    assert_eq!(json_string("Dedicated Server"), "\"Dedicated Server\"");
+    assert_eq!(json_string("a \"quoted\" \\ path"), "\"a \\\"quoted\\\" \\\\ path\"");
+    assert_eq!(json_string("line\nbreak\ttab\r\u{1}"), "\"line\\nbreak\\ttab\\r\\u0001\"");
+    assert_eq!(json_string("§aColours"), "\"§aColours\"");
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn ping_a_listener() {
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    listener.set_pong_data(status().to_string());
+    let timeout = Duration::from_secs(5);
+    let (pinged, latency) = ping(listener.local_addr().unwrap(), timeout).await.unwrap();
+    assert_eq!(pinged, status());
+    assert!(latency < timeout);
+
+    // the pong data follows the listener's.
+    listener.set_pong_data("MCPE;Changed;786;1.21.73;4;10;".to_string());
+    let (pinged, _) = ping(listener.local_addr().unwrap(), timeout).await.unwrap();
+    assert_eq!((pinged.motd.as_str(), pinged.player_count), ("Changed", 4));
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn failed_pings() {
+    // nobody answers on this address.
+    let nobody = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let err = ping(nobody.local_addr().unwrap(), Duration::from_millis(300)).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+
+    // a pong that isn't a status is reported as such.
+    let listener = Listener::listen("127.0.0.1:0").await.unwrap();
+    listener.set_pong_data("not a status".to_string());
+    let err = ping(listener.local_addr().unwrap(), Duration::from_secs(5)).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
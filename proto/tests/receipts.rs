@@ -0,0 +1,125 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::listener::Listener;
+use proto::packet::{Packet as Frame, Reliability};
+use proto::session::{DisconnectReason, Event, Session};
+use proto::transport::MemoryNetwork;
+use proto::types::U24;
+
+fn session() -> (Session, Instant) {
+    let start = Instant::now();
+    let remote: SocketAddr = "127.0.0.1:19132".parse().unwrap();
+    (Session::server(start, remote, 1, 1492), start)
+}
+
+fn sequence_numbers(session: &mut Session) -> Vec<u32> {
+    std::iter::from_fn(|| session.poll_transmit()).map(|d| U24::read(&d[1..]).get()).collect()
+}
+
+fn acknowledgement(flag: u8, sequence_number: u32) -> Vec<u8> {
+    let mut ack = vec![flag, 0, 1, 1];
+    ack.extend_from_slice(&U24::new(sequence_number).to_le_bytes());
+    ack
+}
+
+fn events(session: &mut Session) -> Vec<Event> {
+    std::iter::from_fn(|| session.poll_event()).collect()
+}
+
+#[test]
+fn reliabilities_decode_without_panicking() {
+    for value in 0..8u8 {
+        assert_eq!(Reliability::try_from(value).unwrap() as u8, value);
+    }
+    for value in 8..=u8::MAX {
+        assert!(Reliability::try_from(value).is_err());
+    }
+
+    // receipts are only known to the sender, the reliability they are based on is what's sent.
+    let frame = Frame { reliability: Reliability::ReliableOrderedWithAckReceipt, data: vec![0xfe].into(), receipt: Some(3), ..Default::default() };
+    let mut buf = Vec::new();
+    frame.write(&mut buf);
+    assert_eq!(buf.len(), frame.size());
+    let (read, _) = Frame::read(&buf).unwrap();
+    assert_eq!(read.reliability, Reliability::ReliableOrdered);
+    assert_eq!(read.receipt, None);
+
+    // a peer may still send the receipt variants, which are read like the ones they are based on.
+    // header, length and message index, without the order index and channel.
+    let mut buf = [&buf[..6], &[0xfe]].concat();
+    buf[0] = (Reliability::ReliableWithAckReceipt as u8) << 5;
+    let (read, _) = Frame::read(&buf).unwrap();
+    assert!(read.reliable() && !read.ordered());
+    assert_eq!(read.data, vec![0xfe]);
+}
+
+#[test]
+fn receipt_is_delivered_once_every_fragment_is_acknowledged() {
+    let (mut session, start) = session();
+    let receipt = session.send(start, &vec![0xfe; 4000], Reliability::ReliableWithAckReceipt, 0).unwrap();
+    assert_eq!(receipt, Some(0));
+    assert_eq!(session.send(start, &[0xfe], Reliability::Reliable, 0).unwrap(), None);
+    session.flush(start);
+    // the small message shares the datagram of the last fragment.
+    assert_eq!(sequence_numbers(&mut session), vec![0, 1, 2]);
+
+    session.handle_datagram(start, &acknowledgement(0xc0, 0)).unwrap();
+    session.handle_datagram(start, &acknowledgement(0xc0, 2)).unwrap();
+    assert_eq!(events(&mut session), vec![]);
+    // the middle fragment is lost and only acknowledged after being resent.
+    session.handle_datagram(start, &acknowledgement(0xa0, 1)).unwrap();
+    assert_eq!(sequence_numbers(&mut session), vec![3]);
+    session.handle_datagram(start, &acknowledgement(0xc0, 3)).unwrap();
+    assert_eq!(events(&mut session), vec![Event::Delivered(0)]);
+}
+
+#[test]
+fn unreliable_receipt_is_lost_with_its_datagram() {
+    let (mut session, start) = session();
+    let lost = session.send(start, &[0xfe, 1], Reliability::UnreliableWithAckReceipt, 0).unwrap().unwrap();
+    session.flush(start);
+    let delivered = session.send(start, &[0xfe, 2], Reliability::UnreliableWithAckReceipt, 0).unwrap().unwrap();
+    session.flush(start);
+    assert_eq!(sequence_numbers(&mut session), vec![0, 1]);
+
+    session.handle_datagram(start, &acknowledgement(0xa0, 0)).unwrap();
+    session.handle_datagram(start, &acknowledgement(0xc0, 1)).unwrap();
+    // unreliable messages are never resent.
+    assert_eq!(sequence_numbers(&mut session), Vec::<u32>::new());
+    assert_eq!(events(&mut session), vec![Event::Lost(lost), Event::Delivered(delivered)]);
+}
+
+#[test]
+fn receipts_are_lost_when_the_session_closes() {
+    let (mut session, start) = session();
+    let receipt = session.send(start, &[0xfe], Reliability::ReliableOrderedWithAckReceipt, 0).unwrap().unwrap();
+    session.flush(start);
+    for ms in (100..=10_000).step_by(100) {
+        session.handle_timeout(start + Duration::from_millis(ms));
+    }
+    assert_eq!(events(&mut session), vec![Event::Lost(receipt), Event::Disconnected(DisconnectReason::TimedOut)]);
+}
+
+#[tokio::test]
+async fn conn_receipts_complete_when_acknowledged() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(Arc::new(network.bind("127.0.0.1:19132".parse().unwrap()).unwrap()), Arc::new(SystemClock));
+    let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let (client, server) = tokio::join!(
+        Conn::dial_on(Arc::new(client_socket), Arc::new(SystemClock), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    let chunk = vec![0xfe; 10_000];
+    let receipt = client.write_with_receipt(&chunk, Reliability::ReliableOrdered, 0, true).await.unwrap();
+    assert_eq!(server.ReadPacket().await.unwrap(), chunk);
+    tokio::time::timeout(Duration::from_secs(1), receipt).await.unwrap().unwrap();
+
+    let err = client.write_with_receipt(&[0xfe], Reliability::ReliableSequenced, 0, true).await.err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
@@ -0,0 +1,128 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use proto::capture::{read_capture, Capture, CaptureTransport, CapturedDatagram, Direction, PcapngWriter};
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::listener::Listener;
+use proto::replay::{replay, replay_session, session_for, side, Timing};
+use proto::transport::MemoryNetwork;
+use proto::types::U24;
+
+fn messages() -> Vec<Vec<u8>> {
+    // a split message among small ones.
+    vec![vec![0xfe, 1], vec![0xfe; 5000], vec![0xfe, 3, 4, 5]]
+}
+
+// capture_conversation captures a client connecting to a server and sending it messages, from
+// the point of view of the server.
+async fn capture_conversation() -> Vec<CapturedDatagram> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+    let path = std::env::temp_dir().join(format!("replay-{}-{}.pcapng", std::process::id(), nanos));
+    let network = MemoryNetwork::new();
+    let server_socket = network.bind("127.0.0.1:19132".parse().unwrap()).unwrap();
+    let capture = Arc::new(CaptureTransport::new(Arc::new(server_socket), Capture { path: path.clone(), ..Default::default() }).unwrap());
+    let listener = Listener::listen_on(capture.clone(), Arc::new(SystemClock));
+    let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let (client, server) = tokio::join!(
+        Conn::dial_on(Arc::new(client_socket), Arc::new(SystemClock), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+    for message in messages() {
+        client.Write(&message).await.unwrap();
+        assert_eq!(server.ReadPacket().await.unwrap(), message);
+    }
+    capture.flush().unwrap();
+    let datagrams = read_capture(&std::fs::read(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    datagrams
+}
+
+#[tokio::test]
+async fn captured_conversation_replays_into_a_session() {
+    let datagrams = capture_conversation().await;
+    let client = side(&datagrams, None);
+    assert!(client.iter().all(|datagram| datagram.dst.port() == 19132));
+    assert_eq!(client[0].data[0], 0x05);
+
+    let start = Instant::now();
+    let mut session = session_for(&client, start).unwrap();
+    assert!(session.is_server);
+    assert_eq!(session.remote, client[0].src);
+    let report = replay_session(&mut session, start, &client);
+    assert_eq!(report.datagrams, client.len());
+    assert_eq!(report.messages, messages());
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+
+    // the same happens with a connection, as fast as possible or as captured.
+    for timing in [Timing::Fast, Timing::Original] {
+        let report = replay(&client, timing).await.unwrap();
+        assert_eq!(report.messages, messages());
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+    }
+}
+
+#[tokio::test]
+async fn replay_reports_datagrams_that_cant_be_decoded() {
+    let mut datagrams = side(&capture_conversation().await, None);
+    // a frame claiming to be far longer than the datagram it is in.
+    let last = datagrams.last().unwrap().clone();
+    let mut broken = vec![0x84];
+    broken.extend_from_slice(&U24::new(1000).to_le_bytes());
+    broken.extend_from_slice(&[0x00, 0xff, 0xf8, 0xfe]);
+    datagrams.push(CapturedDatagram { data: broken, ..last });
+
+    let start = Instant::now();
+    let mut session = session_for(&datagrams, start).unwrap();
+    let report = replay_session(&mut session, start, &datagrams);
+    assert_eq!(report.messages, messages());
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].0, datagrams.len() - 1);
+
+    // a connection gives up on a peer that sends it such data.
+    let report = replay(&datagrams, Timing::Fast).await.unwrap();
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.messages, messages());
+}
+
+#[test]
+fn pcap_files_of_other_tools_can_be_read() {
+    // a classic pcap file of an Ethernet link, as written by tcpdump, with a UDP datagram and an
+    // ICMP packet that is skipped.
+    let src: SocketAddr = "192.168.1.20:50000".parse().unwrap();
+    let mut file = Vec::new();
+    file.extend_from_slice(&[0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0]);
+    file.extend_from_slice(&[0; 8]);
+    file.extend_from_slice(&65535u32.to_le_bytes());
+    file.extend_from_slice(&1u32.to_le_bytes());
+    let udp = [
+        &[0x45, 0, 0, 31, 0, 0, 0x40, 0, 64, 17, 0, 0, 192, 168, 1, 20, 10, 0, 0, 1][..],
+        &[0xc3, 0x50, 0x4a, 0xbc, 0, 11, 0, 0, 0x01, 0x02, 0x03],
+    ].concat();
+    let icmp = [0x45, 0, 0, 20, 0, 0, 0, 0, 64, 1, 0, 0, 192, 168, 1, 20, 10, 0, 0, 1];
+    for (seconds, ip) in [(1_700_000_000u32, &udp[..]), (1_700_000_001, &icmp[..])] {
+        let frame = [&[0; 12][..], &[0x08, 0x00], ip].concat();
+        file.extend_from_slice(&seconds.to_le_bytes());
+        file.extend_from_slice(&250_000u32.to_le_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        file.extend_from_slice(&frame);
+    }
+
+    let datagrams = read_capture(&file).unwrap();
+    assert_eq!(datagrams, vec![CapturedDatagram {
+        time: UNIX_EPOCH + Duration::from_millis(1_700_000_000_250),
+        src,
+        dst: "10.0.0.1:19132".parse().unwrap(),
+        data: vec![1, 2, 3],
+    }]);
+
+    // pcapng files written by the capture writer read back the same.
+    let mut writer = PcapngWriter::new(Vec::new()).unwrap();
+    let datagram = &datagrams[0];
+    writer.write_datagram(datagram.time, datagram.src, datagram.dst, Direction::Inbound, &datagram.data).unwrap();
+    assert_eq!(read_capture(&writer.into_inner()).unwrap(), datagrams);
+
+    assert!(read_capture(b"not a capture").is_err());
+}
@@ -0,0 +1,121 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::listener::Listener;
+use proto::packet::{Packet as Frame, Reliability};
+use proto::session::{Session, FLUSH_INTERVAL};
+use proto::transport::MemoryNetwork;
+
+fn session() -> (Session, Instant) {
+    let start = Instant::now();
+    let remote: SocketAddr = "127.0.0.1:19132".parse().unwrap();
+    (Session::server(start, remote, 1, 1492), start)
+}
+
+// frames decodes the contents of the frames in a datagram.
+fn frames(datagram: &[u8]) -> Vec<Vec<u8>> {
+    let mut data = &datagram[4..];
+    let mut frames = Vec::new();
+    while !data.is_empty() {
+        let (frame, n) = Frame::read(data).unwrap();
+        frames.push(frame.data.to_vec());
+        data = &data[n..];
+    }
+    frames
+}
+
+fn transmits(session: &mut Session) -> Vec<Vec<u8>> {
+    std::iter::from_fn(|| session.poll_transmit()).map(|datagram| datagram.to_vec()).collect()
+}
+
+#[test]
+fn buffered_messages_share_a_datagram() {
+    let (mut session, start) = session();
+    let messages: Vec<Vec<u8>> = (0..50).map(|i| vec![0xfe, i, 1, 2, 3, 4, 5, 6]).collect();
+    for message in &messages {
+        session.send(start, message, Reliability::ReliableOrdered, 0).unwrap();
+    }
+    assert!(transmits(&mut session).is_empty());
+    assert_eq!(session.poll_timeout(), Some(start + FLUSH_INTERVAL));
+
+    session.handle_timeout(start + FLUSH_INTERVAL - Duration::from_millis(1));
+    assert!(transmits(&mut session).is_empty());
+    session.handle_timeout(start + FLUSH_INTERVAL);
+    let datagrams = transmits(&mut session);
+    assert_eq!(datagrams.len(), 1);
+    assert_eq!(frames(&datagrams[0]), messages);
+}
+
+#[test]
+fn full_datagrams_are_sent_without_waiting() {
+    let (mut session, start) = session();
+    let mtu = session.effective_mtu() as usize;
+    let messages: Vec<Vec<u8>> = (0..7).map(|i| vec![0xfe, i].into_iter().chain([0; 498]).collect()).collect();
+    for message in &messages {
+        session.send(start, message, Reliability::Reliable, 0).unwrap();
+    }
+    // two messages fit in a datagram, the third doesn't.
+    let datagrams = transmits(&mut session);
+    assert_eq!(datagrams.iter().map(|d| frames(d).len()).collect::<Vec<_>>(), vec![2, 2, 2]);
+    assert!(datagrams.iter().all(|d| d.len() <= mtu));
+
+    session.flush(start);
+    let datagrams = [datagrams, transmits(&mut session)].concat();
+    assert_eq!(datagrams.iter().flat_map(|d| frames(d)).collect::<Vec<_>>(), messages);
+    // nothing is left to be flushed.
+    assert!(session.poll_timeout().unwrap() > start + FLUSH_INTERVAL);
+}
+
+#[test]
+fn flushing_keeps_the_order_of_messages() {
+    let (mut session, start) = session();
+    session.send(start, &[0xfe, 1], Reliability::Unreliable, 0).unwrap();
+    session.send(start, &[0xfe, 2], Reliability::ReliableOrdered, 0).unwrap();
+    session.send(start, &[0xfe, 3], Reliability::ReliableOrdered, 0).unwrap();
+    session.flush(start);
+    let datagrams = transmits(&mut session);
+    assert_eq!(datagrams.len(), 1);
+    assert_eq!(frames(&datagrams[0]), vec![vec![0xfe, 1], vec![0xfe, 2], vec![0xfe, 3]]);
+
+    // large messages are split in fragments that take up a datagram each, after what was buffered.
+    session.send(start, &[0xfe, 4], Reliability::ReliableOrdered, 0).unwrap();
+    let large = vec![0xfe; 4000];
+    session.send(start, &large, Reliability::ReliableOrdered, 0).unwrap();
+    session.flush(start);
+    let datagrams = transmits(&mut session);
+    assert_eq!(frames(&datagrams[0]), vec![vec![0xfe, 4]]);
+    assert_eq!(datagrams[1..].iter().flat_map(|d| frames(d)).flatten().collect::<Vec<_>>(), large);
+}
+
+#[tokio::test]
+async fn conn_flushes_buffered_messages() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(Arc::new(network.bind("127.0.0.1:19132".parse().unwrap()).unwrap()), Arc::new(SystemClock));
+    let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let (client, server) = tokio::join!(
+        Conn::dial_on(Arc::new(client_socket), Arc::new(SystemClock), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    // sent by the ticker once the flush interval passed.
+    for i in 0..10 {
+        client.write_with(&[0xfe, i], Reliability::ReliableOrdered, 0, false).await.unwrap();
+    }
+    for i in 0..10 {
+        let message = tokio::time::timeout(Duration::from_secs(1), server.ReadPacket()).await.unwrap().unwrap();
+        assert_eq!(message, vec![0xfe, i]);
+    }
+
+    // sent by an explicit flush, or by an immediate message after the buffered ones.
+    client.write_with(&[0xfe, 10], Reliability::ReliableOrdered, 0, false).await.unwrap();
+    client.flush().await.unwrap();
+    client.write_with(&[0xfe, 11], Reliability::ReliableOrdered, 0, false).await.unwrap();
+    client.Write(&[0xfe, 12]).await.unwrap();
+    for i in 10..13 {
+        let message = tokio::time::timeout(Duration::from_secs(1), server.ReadPacket()).await.unwrap().unwrap();
+        assert_eq!(message, vec![0xfe, i]);
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use proto::clock::{Clock, ManualClock};
+use proto::messages::open_connection_reply_1::OpenConnectionReply1;
+use proto::messages::open_connection_reply_2::OpenConnectionReply2;
+use proto::packet::{Packet as Frame, PacketBitFlags, Reliability};
+use proto::session::{Event, Session, FLUSH_INTERVAL};
+use proto::types::Packet;
+use proto::{PacketT, ReadPacket, MTU_SIZE};
+
+const CLIENT_GUID: u64 = 1;
+const SERVER_GUID: u64 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Side {
+    Client,
+    Server,
+}
+
+/// Pair is a client and a server session connected by hand, on a clock that only moves when the
+/// test says so. The offline handshake is answered the way a listener does, and the server
+/// session is created once the client's second open connection request arrives.
+struct Pair {
+    clock: ManualClock,
+    client: Session,
+    server: Option<Session>,
+    // how long datagrams take to get to the other end.
+    latency: Duration,
+    // datagrams on their way, with when they arrive and who sent them.
+    in_flight: VecDeque<(Instant, Side, Vec<u8>)>,
+    // replies to offline messages on their way to the client.
+    offline: VecDeque<Vec<u8>>,
+    client_events: Vec<Event>,
+    server_events: Vec<Event>,
+}
+
+impl Pair {
+    fn new() -> Self {
+        let clock = ManualClock::new();
+        let client = Session::client(clock.now(), server_addr(), CLIENT_GUID);
+        Pair {
+            clock,
+            client,
+            server: None,
+            latency: Duration::ZERO,
+            in_flight: VecDeque::new(),
+            offline: VecDeque::new(),
+            client_events: Vec::new(),
+            server_events: Vec::new(),
+        }
+    }
+
+    fn server(&mut self) -> &mut Session {
+        self.server.as_mut().expect("the client never got to the server")
+    }
+
+    fn connected(&self) -> bool {
+        self.client.is_connected() && self.server.as_ref().is_some_and(Session::is_connected)
+    }
+
+    // step sends every datagram waiting to be sent to the other end, unless `drop` drops it,
+    // delivers those that arrived by now, then runs the timers of both ends.
+    fn step(&mut self, drop: &mut impl FnMut(Side, &[u8]) -> bool) {
+        let now = self.clock.now();
+        loop {
+            let arrival = now + self.latency;
+            while let Some(datagram) = self.client.poll_transmit() {
+                if !drop(Side::Client, &datagram) {
+                    self.in_flight.push_back((arrival, Side::Client, datagram.to_vec()));
+                }
+            }
+            let mut datagrams: Vec<Vec<u8>> = self.offline.drain(..).collect();
+            if let Some(server) = &mut self.server {
+                datagrams.extend(std::iter::from_fn(|| server.poll_transmit().map(|datagram| datagram.to_vec())));
+            }
+            for datagram in datagrams {
+                if !drop(Side::Server, &datagram) {
+                    self.in_flight.push_back((arrival, Side::Server, datagram));
+                }
+            }
+
+            let mut delivered = false;
+            while self.in_flight.front().is_some_and(|(at, _, _)| *at <= now) {
+                let (_, from, datagram) = self.in_flight.pop_front().unwrap();
+                match from {
+                    Side::Client => self.deliver_to_server(&datagram),
+                    Side::Server => self.client.handle_datagram(now, &datagram).unwrap(),
+                }
+                delivered = true;
+            }
+            if !delivered {
+                break;
+            }
+        }
+        self.client.handle_timeout(now);
+        self.client_events.extend(std::iter::from_fn(|| self.client.poll_event()));
+        if let Some(server) = &mut self.server {
+            server.handle_timeout(now);
+            self.server_events.extend(std::iter::from_fn(|| server.poll_event()));
+        }
+    }
+
+    fn deliver_to_server(&mut self, datagram: &[u8]) {
+        let now = self.clock.now();
+        if datagram[0] & PacketBitFlags::Datagram as u8 != 0 {
+            self.server().handle_datagram(now, datagram).unwrap();
+            return;
+        }
+        let reply = match ReadPacket(datagram).unwrap() {
+            Some(PacketT::OpenConnectionRequest1(request)) => OpenConnectionReply1 {
+                server_guid_be: SERVER_GUID,
+                server_has_security: false,
+                cookie: 0,
+                max_transmission_unit_be: request.max_transmission_unit.min(MTU_SIZE),
+            }.serialize(),
+            Some(PacketT::OpenConnectionRequest2(request)) => {
+                let max_transmission_unit = request.max_transmission_unit.min(MTU_SIZE);
+                self.server.get_or_insert_with(|| Session::server(now, client_addr(), SERVER_GUID, max_transmission_unit));
+                OpenConnectionReply2 {
+                    server_guid_be: SERVER_GUID,
+                    client_address: client_addr().into(),
+                    max_transmission_unit_be: max_transmission_unit,
+                    do_security: false,
+                }.serialize()
+            }
+            packet => panic!("unexpected offline message {:?}", packet),
+        };
+        self.offline.push_back(reply);
+    }
+
+    // run steps both ends for `duration`, FLUSH_INTERVAL at a time.
+    fn run(&mut self, duration: Duration, drop: &mut impl FnMut(Side, &[u8]) -> bool) {
+        let end = self.clock.now() + duration;
+        while self.clock.now() < end {
+            self.step(drop);
+            self.clock.advance(FLUSH_INTERVAL);
+        }
+        self.step(drop);
+    }
+
+    // run_until steps both ends until `done`, returning how long that took.
+    fn run_until(&mut self, done: impl Fn(&Pair) -> bool, drop: &mut impl FnMut(Side, &[u8]) -> bool) -> Duration {
+        let start = self.clock.now();
+        loop {
+            self.step(drop);
+            if done(self) {
+                return self.clock.now() - start;
+            }
+            assert!(self.clock.now() - start < Duration::from_secs(10), "gave up waiting");
+            self.clock.advance(FLUSH_INTERVAL);
+        }
+    }
+}
+
+fn server_addr() -> SocketAddr {
+    "10.0.0.1:19132".parse().unwrap()
+}
+
+fn client_addr() -> SocketAddr {
+    "10.0.0.2:50000".parse().unwrap()
+}
+
+fn keep_all(_: Side, _: &[u8]) -> bool {
+    false
+}
+
+fn connect() -> Pair {
+    let mut pair = Pair::new();
+    pair.run_until(Pair::connected, &mut keep_all);
+    pair
+}
+
+// is_data tells datagrams carrying frames apart from ACKs, NACKs and offline messages.
+fn is_data(datagram: &[u8]) -> bool {
+    let flags = datagram[0];
+    flags & PacketBitFlags::Datagram as u8 != 0 && flags & (PacketBitFlags::ACK as u8 | PacketBitFlags::NACK as u8) == 0
+}
+
+fn frames(datagram: &[u8]) -> Vec<Frame> {
+    let mut data = &datagram[4..];
+    let mut frames = Vec::new();
+    while !data.is_empty() {
+        let (frame, n) = Frame::read(data).unwrap();
+        frames.push(frame);
+        data = &data[n..];
+    }
+    frames
+}
+
+fn messages(events: &[Event]) -> Vec<&[u8]> {
+    events.iter().filter_map(|event| match event {
+        Event::Message(data) => Some(&data[..]),
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn handshake() {
+    let mut pair = Pair::new();
+    // nothing is lost, so the whole handshake happens right away.
+    assert_eq!(pair.run_until(Pair::connected, &mut keep_all), Duration::ZERO);
+    assert_eq!(pair.client_events, vec![Event::Connected]);
+    assert_eq!(pair.server_events, vec![Event::Connected]);
+    assert_eq!(pair.client.peer_guid(), Some(SERVER_GUID));
+    assert_eq!(pair.server().peer_guid(), Some(CLIENT_GUID));
+    assert_eq!(pair.client.max_transmission_unit(), MTU_SIZE);
+    assert_eq!(pair.server().max_transmission_unit(), MTU_SIZE);
+}
+
+#[test]
+fn handshake_falls_back_to_a_smaller_mtu() {
+    // requests for an MTU the path can't carry never arrive.
+    let mut too_large = |side: Side, datagram: &[u8]| {
+        side == Side::Client && matches!(ReadPacket(datagram), Ok(Some(PacketT::OpenConnectionRequest1(request))) if request.max_transmission_unit > 1200)
+    };
+    let mut pair = Pair::new();
+    let elapsed = pair.run_until(Pair::connected, &mut too_large);
+    // the first size is tried four times, half a second apart, on the next tick after that.
+    assert!(elapsed >= Duration::from_millis(2000) && elapsed < Duration::from_millis(2100), "{:?}", elapsed);
+    assert_eq!(pair.client.max_transmission_unit(), 1200);
+    assert_eq!(pair.server().max_transmission_unit(), 1200);
+}
+
+#[test]
+fn acknowledged_datagrams_are_not_resent() {
+    let mut pair = connect();
+    let now = pair.clock.now();
+    pair.client.send(now, &[0xfe, 1], Reliability::Reliable, 0).unwrap();
+    pair.server().send(now, &[0xfe, 2], Reliability::ReliableOrdered, 0).unwrap();
+    pair.run(Duration::from_secs(2), &mut keep_all);
+
+    assert_eq!(messages(&pair.server_events), vec![&[0xfe, 1]]);
+    assert_eq!(messages(&pair.client_events), vec![&[0xfe, 2]]);
+    for session in [&pair.client, pair.server.as_ref().unwrap()] {
+        let stats = session.stats();
+        assert_eq!(stats.resends, 0);
+        assert_eq!(stats.in_flight, 0);
+        assert_eq!(stats.nacks_sent, 0);
+    }
+}
+
+#[test]
+fn lost_datagrams_are_nacked_and_resent() {
+    let mut pair = connect();
+    let now = pair.clock.now();
+    for i in 1..=2 {
+        pair.client.send(now, &[0xfe, i], Reliability::ReliableOrdered, 0).unwrap();
+        pair.client.flush(now);
+    }
+    // the datagram of the first message is lost, the second one arrives.
+    let mut lost = false;
+    let mut lose_first = |side: Side, datagram: &[u8]| {
+        let first = side == Side::Client && is_data(datagram) && frames(datagram).iter().any(|frame| frame.data[..] == [0xfe, 1]);
+        first && !std::mem::replace(&mut lost, true)
+    };
+    pair.run(Duration::from_millis(500), &mut lose_first);
+
+    assert!(lost);
+    assert_eq!(messages(&pair.server_events), vec![&[0xfe, 1], &[0xfe, 2]]);
+    assert_eq!(pair.server().stats().nacks_sent, 1);
+    let stats = pair.client.stats();
+    assert_eq!(stats.nacks_received, 1);
+    assert_eq!(stats.resends, 1);
+    assert_eq!(stats.in_flight, 0);
+}
+
+#[test]
+fn resends_back_off_while_the_link_is_down() {
+    let mut pair = connect();
+    let start = pair.clock.now();
+    pair.client.send(start, &[0xfe, 3], Reliability::Reliable, 0).unwrap();
+
+    // nothing the client sends gets through for four and a half seconds, short of the
+    // timeout.
+    let mut resends = Vec::new();
+    let mut cut = |side: Side, datagram: &[u8]| side == Side::Client && is_data(datagram);
+    while pair.clock.now() - start < Duration::from_millis(4500) {
+        pair.step(&mut cut);
+        if pair.client.stats().resends > resends.len() as u64 {
+            resends.push(pair.clock.now() - start);
+        }
+        pair.clock.advance(FLUSH_INTERVAL);
+    }
+    let gaps: Vec<Duration> = resends.windows(2).map(|w| w[1] - w[0]).collect();
+    assert!(gaps.len() >= 3, "resent at {:?}", resends);
+    assert!(gaps.windows(2).all(|w| w[0] < w[1]), "resent at {:?}", resends);
+    assert!(messages(&pair.server_events).is_empty());
+
+    // once the link is back, the message arrives once and the resends stop.
+    pair.run(Duration::from_secs(2), &mut keep_all);
+    assert_eq!(messages(&pair.server_events), vec![&[0xfe, 3]]);
+    assert_eq!(pair.client.stats().in_flight, 0);
+    assert!(pair.connected());
+}
+
+#[test]
+fn round_trip_times_follow_the_latency() {
+    let mut pair = connect();
+    pair.latency = Duration::from_millis(40);
+    for i in 0..20 {
+        let now = pair.clock.now();
+        pair.client.send(now, &[0xfe, i], Reliability::Reliable, 0).unwrap();
+        pair.server().send(now, &[0xfe, i], Reliability::Reliable, 0).unwrap();
+        pair.run(Duration::from_millis(50), &mut keep_all);
+    }
+    pair.run(Duration::from_secs(1), &mut keep_all);
+
+    assert_eq!(messages(&pair.server_events).len(), 20);
+    assert_eq!(messages(&pair.client_events).len(), 20);
+    for session in [&pair.client, pair.server.as_ref().unwrap()] {
+        let round_trip_time = session.round_trip_time();
+        // ACKs wait for the next tick, up to 100ms after the datagram arrived.
+        assert!(round_trip_time >= Duration::from_millis(80) && round_trip_time < Duration::from_millis(180), "{:?}", round_trip_time);
+    }
+}
+
+#[test]
+fn ordered_messages_get_past_a_lost_datagram() {
+    // far more ordered messages than the other end holds back waiting for a missing one.
+    const COUNT: u16 = 3000;
+    let mut pair = connect();
+    let now = pair.clock.now();
+    for i in 0..COUNT {
+        let [high, low] = i.to_be_bytes();
+        pair.client.send(now, &[0xfe, high, low], Reliability::ReliableOrdered, 0).unwrap();
+    }
+    pair.client.flush(now);
+    let mut lost = false;
+    let mut lose_first = |side: Side, datagram: &[u8]| side == Side::Client && is_data(datagram) && !std::mem::replace(&mut lost, true);
+    pair.run(Duration::from_secs(3), &mut lose_first);
+
+    let expected: Vec<[u8; 3]> = (0..COUNT).map(|i| {
+        let [high, low] = i.to_be_bytes();
+        [0xfe, high, low]
+    }).collect();
+    assert_eq!(messages(&pair.server_events), expected.iter().map(|message| &message[..]).collect::<Vec<_>>());
+    assert!(pair.connected());
+    assert_eq!(pair.client.stats().in_flight, 0);
+}
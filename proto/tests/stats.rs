@@ -0,0 +1,106 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use proto::clock::SystemClock;
+use proto::conn::Conn;
+use proto::listener::Listener;
+use proto::packet::{Priority, Reliability};
+use proto::session::Session;
+use proto::transport::MemoryNetwork;
+use proto::types::U24;
+
+fn datagram(sequence_number: u32, message: &[u8]) -> Vec<u8> {
+    let mut datagram = vec![0x84];
+    datagram.extend_from_slice(&U24::new(sequence_number).to_le_bytes());
+    // an unreliable frame.
+    datagram.push(0);
+    datagram.extend_from_slice(&((message.len() as u16) << 3).to_be_bytes());
+    datagram.extend_from_slice(message);
+    datagram
+}
+
+#[test]
+fn session_counts_what_it_sends_and_receives() {
+    let start = Instant::now();
+    let remote: SocketAddr = "127.0.0.1:19132".parse().unwrap();
+    let mut session = Session::server(start, remote, 1, 1492);
+
+    session.send(start, &vec![0xfe; 3000], Reliability::ReliableOrdered, 0).unwrap();
+    session.send_with_priority(start, &[0xfe], Reliability::Unreliable, Priority::Low, 0).unwrap();
+    let stats = session.stats();
+    assert_eq!(stats.send_queue, [0, 0, 1, 1]);
+    assert_eq!(stats.in_flight, 2);
+    session.flush(start);
+    let sent: Vec<Bytes> = std::iter::from_fn(|| session.poll_transmit()).collect();
+
+    for (i, sequence_number) in [0, 0, 2].into_iter().enumerate() {
+        session.handle_datagram(start + Duration::from_millis(i as u64), &datagram(sequence_number, &[0xfe])).unwrap();
+    }
+    // NACKing the first datagram resends it.
+    session.handle_datagram(start, &[0xa0, 0, 1, 1, 0, 0, 0]).unwrap();
+    // sequence number 1 is missing for long enough to be NACKed.
+    session.handle_timeout(start + Duration::from_millis(100));
+
+    let stats = session.stats();
+    assert_eq!(stats.datagrams_received, 4);
+    assert_eq!(stats.bytes_received, 3 * 8 + 7);
+    assert_eq!(stats.duplicate_datagrams, 1);
+    assert_eq!(stats.frames_received[Reliability::Unreliable as usize], 2);
+    assert_eq!(stats.frames_sent[Reliability::ReliableOrdered as usize], 4);
+    assert_eq!(stats.frames_sent[Reliability::Unreliable as usize], 1);
+    assert_eq!(stats.nacks_received, 1);
+    assert_eq!(stats.resends, 1);
+    assert_eq!(stats.nacks_sent, 1);
+    assert_eq!(stats.send_queue, [0; 4]);
+
+    let transmits: Vec<Bytes> = std::iter::from_fn(|| session.poll_transmit()).collect();
+    let stats = session.stats();
+    assert_eq!(stats.datagrams_sent, (sent.len() + transmits.len()) as u64);
+    assert_eq!(stats.bytes_sent, sent.iter().chain(&transmits).map(|d| d.len() as u64).sum::<u64>());
+}
+
+#[tokio::test]
+async fn listener_adds_up_its_connections() {
+    let network = MemoryNetwork::new();
+    let listener = Listener::listen_on(Arc::new(network.bind("127.0.0.1:19132".parse().unwrap()).unwrap()), Arc::new(SystemClock));
+    let client_socket = network.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let (client, server) = tokio::join!(
+        Conn::dial_on(Arc::new(client_socket), Arc::new(SystemClock), listener.local_addr().unwrap()),
+        listener.accept(),
+    );
+    let (client, server) = (client.unwrap(), server.unwrap());
+
+    for i in 0..20 {
+        client.Write(&[0xfe, i]).await.unwrap();
+    }
+    for _ in 0..20 {
+        server.ReadPacket().await.unwrap();
+    }
+    // the server acknowledges what it received with the next tick.
+    tokio::time::sleep(Duration::from_millis(250)).await;
+
+    let stats = client.stats().await;
+    // the connection request, the new incoming connection and the messages.
+    assert!(stats.frames_sent[Reliability::ReliableOrdered as usize] >= 21);
+    assert!(stats.smoothed_rtt > Duration::ZERO);
+    assert_eq!(stats.in_flight, 0);
+    let server_stats = server.stats().await;
+    assert!(server_stats.frames_received[Reliability::ReliableOrdered as usize] >= 21);
+
+    let listener_stats = listener.stats().await;
+    assert_eq!(listener_stats.connections, 1);
+    assert_eq!(listener_stats.total.bytes_received, server_stats.bytes_received);
+    assert_eq!(listener_stats.total.smoothed_rtt, server_stats.smoothed_rtt);
+
+    // the counters of closed connections stay in the totals.
+    client.close().await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while listener.stats().await.connections > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.unwrap();
+    let listener_stats = listener.stats().await;
+    assert!(listener_stats.total.bytes_received >= server_stats.bytes_received);
+    assert!(listener_stats.total.frames_received[Reliability::ReliableOrdered as usize] >= 22);
+}
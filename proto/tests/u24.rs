@@ -0,0 +1,73 @@
+use proto::ack::{read_acknowledgement, write_acknowledgement};
+use proto::packet::{Packet, Reliability};
+use proto::types::U24;
+
+#[test]
+fn wraps_around() {
+    assert_eq!(U24::MAX.next(), U24::new(0));
+    assert_eq!(U24::new(0).wrapping_sub(1), U24::MAX);
+    assert_eq!(U24::new(0x1ff_fffe), U24::new(0xff_fffe));
+
+    let mut index = U24::MAX;
+    assert_eq!(index.inc(), U24::MAX);
+    assert_eq!(index, U24::new(0));
+}
+
+#[test]
+fn orders_serially() {
+    assert!(U24::new(1) < U24::new(2));
+    assert!(U24::MAX < U24::new(0));
+    assert!(U24::new(0xff_fff0) < U24::new(0x10));
+    assert!(U24::new(0x10) > U24::new(0xff_fff0));
+    assert_eq!(U24::new(5).max(U24::MAX), U24::new(5));
+
+    // values just under half the number space apart still compare by distance.
+    assert!(U24::new(0) < U24::new(0x7f_ffff));
+    assert!(U24::new(0) > U24::new(0x80_0001));
+    // exactly half apart is undefined, but must not be less in both directions.
+    let (a, b) = (U24::new(0), U24::new(0x80_0000));
+    assert_ne!(a < b, b < a);
+
+    assert_eq!(U24::new(0xff_fffe).distance(U24::new(1)), 3);
+    assert_eq!(U24::new(1).distance(U24::new(0xff_fffe)), 0xff_fffd);
+}
+
+#[test]
+fn encodes_little_endian() {
+    assert_eq!(U24::new(0x123456).to_le_bytes(), [0x56, 0x34, 0x12]);
+    assert_eq!(U24::read(&[0x56, 0x34, 0x12, 0xff]), U24::new(0x123456));
+    for value in [0, 1, 0xff, 0x100, 0xffff, 0x10000, 0xff_ffff] {
+        assert_eq!(U24::read(&U24::new(value).to_le_bytes()).get(), value);
+    }
+}
+
+#[test]
+fn acknowledgement_ranges_cross_the_wrap() {
+    let packets: Vec<U24> = (0..10).map(|i| U24::new(0xff_fffb).wrapping_add(i)).collect();
+    let mut buf = Vec::new();
+    assert_eq!(write_acknowledgement(&mut buf, &packets, 1400), packets.len());
+    // a single range record: count, type, start and end.
+    assert_eq!(buf.len(), 2 + 1 + 3 + 3);
+    assert_eq!(read_acknowledgement(&buf).unwrap(), packets);
+}
+
+#[test]
+fn frame_indices_round_trip() {
+    let frame = Packet {
+        reliability: Reliability::ReliableSequenced,
+        message_index: U24::MAX,
+        sequence_index: U24::new(0xabcdef),
+        order_index: U24::new(1),
+        order_channel: 3,
+        data: vec![0xfe, 1, 2, 3].into(),
+        ..Default::default()
+    };
+    let mut buf = Vec::new();
+    frame.write(&mut buf);
+    let (read, len) = Packet::read(&buf).unwrap();
+    assert_eq!(len, buf.len());
+    assert_eq!(read.message_index, frame.message_index);
+    assert_eq!(read.sequence_index, frame.sequence_index);
+    assert_eq!(read.order_index, frame.order_index);
+    assert_eq!(read.data, frame.data);
+}
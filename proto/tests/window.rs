@@ -0,0 +1,191 @@
+use std::time::{Duration, Instant};
+use proto::frame::{Window, WINDOW_SIZE};
+use proto::types::U24;
+
+// starting points of the windows tested: the start, somewhere in the middle, and right before
+// the sequence numbers wrap around.
+const BASES: [u32; 3] = [0, 123_456, 0xff_fffa];
+// every set of twelve datagrams takes too long under Miri.
+const SPAN: u32 = if cfg!(miri) { 6 } else { 12 };
+
+fn indices(base: u32, mask: u32) -> Vec<U24> {
+    (0..SPAN).filter(|i| mask & (1 << i) != 0).map(|i| U24::new(base).wrapping_add(i)).collect()
+}
+
+// check compares a window that received the indices in `mask` to what it should look like.
+// `reported` holds the gaps reported by earlier checks, which must not be reported again.
+fn check(window: &mut Window, base: u32, mask: u32, now: Instant, reported: &mut Vec<U24>) {
+    let base = U24::new(base);
+    let leading = mask.trailing_ones();
+    let highest = if mask == 0 { 0 } else { 32 - mask.leading_zeros() };
+    assert_eq!(window.lowest(), base.wrapping_add(leading), "lowest of {:b}", mask);
+    assert_eq!(window.highest(), base.wrapping_add(highest.max(leading)), "highest of {:b}", mask);
+    assert_eq!(window.len() as u32, highest.max(leading) - leading);
+
+    for i in 0..SPAN + 2 {
+        let expected = i < SPAN && mask & (1 << i) != 0;
+        assert_eq!(window.seen(base.wrapping_add(i)), expected, "seen {} of {:b}", i, mask);
+    }
+    let gaps: Vec<U24> = (leading..highest)
+        .filter(|i| mask & (1 << i) == 0)
+        .map(|i| base.wrapping_add(i))
+        .filter(|index| !reported.contains(index))
+        .collect();
+    assert_eq!(window.missing(Duration::ZERO, now), gaps, "missing of {:b}", mask);
+    reported.extend(gaps);
+    assert!(window.missing(Duration::ZERO, now).is_empty(), "missing reported twice for {:b}", mask);
+}
+
+#[test]
+fn every_set_of_arrivals() {
+    let now = Instant::now();
+    for base in BASES {
+        for mask in 0..1u32 << SPAN {
+            for reverse in [false, true] {
+                let mut window = Window::starting_at(U24::new(base));
+                let mut arrivals = indices(base, mask);
+                if reverse {
+                    arrivals.reverse();
+                }
+                for &index in &arrivals {
+                    assert_eq!(window.add(index, now), Ok(true));
+                }
+                window.shift();
+                check(&mut window, base, mask, now, &mut Vec::new());
+                for &index in &arrivals {
+                    assert_eq!(window.add(index, now), Ok(false), "duplicate {} of {:b}", index, mask);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn every_order_of_arrivals() {
+    fn permutations(items: &mut Vec<u32>, k: usize, out: &mut Vec<Vec<u32>>) {
+        if k == items.len() {
+            out.push(items.clone());
+            return;
+        }
+        for i in k..items.len() {
+            items.swap(k, i);
+            permutations(items, k + 1, out);
+            items.swap(k, i);
+        }
+    }
+    // every order of seven datagrams takes too long under Miri.
+    let count = if cfg!(miri) { 4 } else { 7 };
+    let mut orders = Vec::new();
+    permutations(&mut (0..count).collect(), 0, &mut orders);
+
+    let now = Instant::now();
+    for base in BASES {
+        for order in &orders {
+            let mut window = Window::starting_at(U24::new(base));
+            let mut mask = 0;
+            let mut reported = Vec::new();
+            for &i in order {
+                assert_eq!(window.add(U24::new(base).wrapping_add(i), now), Ok(true));
+                mask |= 1 << i;
+                window.shift();
+                check(&mut window, base, mask, now, &mut reported);
+            }
+            assert_eq!(window.lowest(), U24::new(base).wrapping_add(count));
+            assert!(window.is_empty());
+        }
+    }
+}
+
+#[test]
+fn gaps_are_reported_after_the_delay() {
+    let start = Instant::now();
+    let at = |ms: u64| start + Duration::from_millis(ms);
+    let delay = Duration::from_millis(100);
+    let mut window = Window::new();
+
+    window.add(U24::new(0), at(0)).unwrap();
+    window.add(U24::new(2), at(0)).unwrap();
+    window.add(U24::new(5), at(50)).unwrap();
+    window.shift();
+
+    assert!(window.missing(delay, at(99)).is_empty());
+    assert_eq!(window.missing(delay, at(100)), vec![U24::new(1)]);
+    // 4 arrives before its gap is old enough, so only 3 is still missing.
+    window.add(U24::new(4), at(120)).unwrap();
+    assert!(window.missing(delay, at(149)).is_empty());
+    assert_eq!(window.missing(delay, at(150)), vec![U24::new(3)]);
+    assert!(window.missing(delay, at(1000)).is_empty());
+
+    // filling the gaps moves the window past them.
+    window.add(U24::new(1), at(200)).unwrap();
+    window.add(U24::new(3), at(200)).unwrap();
+    assert_eq!(window.shift(), 5);
+    assert_eq!(window.lowest(), U24::new(6));
+}
+
+#[test]
+fn sequence_numbers_outside_the_window() {
+    let now = Instant::now();
+    for base in BASES {
+        let base = U24::new(base);
+        let mut window = Window::starting_at(base);
+        let last = base.wrapping_add(WINDOW_SIZE as u32 - 1);
+        assert!(window.add(base.wrapping_add(WINDOW_SIZE as u32), now).is_err());
+        assert_eq!(window.add(last, now), Ok(true));
+        assert!(!window.seen(base.wrapping_add(WINDOW_SIZE as u32)));
+        // numbers before the window were received long ago.
+        assert_eq!(window.add(base.wrapping_sub(1), now), Ok(false));
+        assert!(window.seen(base.wrapping_sub(WINDOW_SIZE as u32)));
+    }
+}
+
+#[test]
+fn reported_gaps_dont_hold_the_window_back() {
+    // datagram 1 is lost, and its frames are resent in datagrams with new sequence numbers.
+    let now = Instant::now();
+    let mut window = Window::new();
+    let end = WINDOW_SIZE as u32 + 1;
+    for i in (0..end).filter(|&i| i != 1) {
+        window.add(U24::new(i), now).unwrap();
+    }
+    window.shift();
+    assert!(window.add(U24::new(end), now).is_err());
+    assert_eq!(window.missing(Duration::ZERO, now), vec![U24::new(1)]);
+    // once reported missing, it is given up on when the window runs full.
+    assert_eq!(window.add(U24::new(end), now), Ok(true));
+    window.shift();
+    assert_eq!(window.lowest(), U24::new(end + 1));
+    assert!(window.seen(U24::new(1)));
+}
+
+#[test]
+fn stream_across_the_wrap() {
+    // a long stream in which every fourth datagram arrives late, reusing every slot of the ring
+    // several times and wrapping around once.
+    let now = Instant::now();
+    let base = U24::new(0xff_ffff - 3 * WINDOW_SIZE as u32);
+    let mut window = Window::starting_at(base);
+    let total = 8 * WINDOW_SIZE as u32;
+    let mut late = Vec::new();
+    for i in 0..total {
+        let index = base.wrapping_add(i);
+        if i % 4 == 1 {
+            late.push(index);
+        } else {
+            assert_eq!(window.add(index, now), Ok(true));
+        }
+        if late.len() == 8 {
+            for index in late.drain(..) {
+                assert_eq!(window.add(index, now), Ok(true));
+            }
+        }
+        window.shift();
+        assert!(window.len() < 64);
+    }
+    for index in late.drain(..) {
+        window.add(index, now).unwrap();
+    }
+    window.shift();
+    assert_eq!(window.lowest(), base.wrapping_add(total));
+    assert!(window.is_empty());
+}
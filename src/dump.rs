@@ -0,0 +1,65 @@
+use std::io::{BufRead, Error, ErrorKind};
+use proto::capture::read_capture;
+use proto::dissect::dissect;
+
+/// Prints an annotated decode of every datagram in a capture file, or of the datagrams read from
+/// stdin as hex, one a line, as an indented tree or as a JSON array when `--json` is passed.
+/// Lines of hex may be written as `84000000...` or as the `[0x84, 0x00, ...]` lists in logs.
+/// Usage: dump [--json] [<capture>]
+pub fn dump(args: Vec<String>) -> std::io::Result<()> {
+    let json = args.iter().any(|arg| arg == "--json");
+    let path = args.into_iter().find(|arg| arg != "--json");
+
+    // each datagram with a line describing where it came from.
+    let datagrams: Vec<(String, Vec<u8>)> = match path {
+        Some(path) => read_capture(&std::fs::read(&path)?)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, datagram)| (format!("#{} {} -> {}", i, datagram.src, datagram.dst), datagram.data))
+            .collect(),
+        None => {
+            let mut datagrams = Vec::new();
+            for (i, line) in std::io::stdin().lock().lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let data = parse_hex(&line).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, format!("dump: line {} is not hex", i + 1))
+                })?;
+                datagrams.push((format!("#{}", datagrams.len()), data));
+            }
+            datagrams
+        }
+    };
+
+    if json {
+        let entries: Vec<String> = datagrams.iter().map(|(_, data)| dissect(data).to_json()).collect();
+        println!("[{}]", entries.join(","));
+    } else {
+        for (header, data) in &datagrams {
+            println!("{}", header);
+            print!("{}", dissect(data));
+        }
+    }
+    Ok(())
+}
+
+// parse_hex reads bytes written as a string of hex digits, or as a list of `0x..` bytes separated
+// by commas or whitespace.
+fn parse_hex(line: &str) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    for token in line.split(|c: char| c.is_whitespace() || c == ',' || c == '[' || c == ']').filter(|t| !t.is_empty()) {
+        if let Some(byte) = token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+            data.push(u8::from_str_radix(byte, 16).ok()?);
+            continue;
+        }
+        if token.len() % 2 != 0 {
+            return None;
+        }
+        for i in (0..token.len()).step_by(2) {
+            data.push(u8::from_str_radix(token.get(i..i + 2)?, 16).ok()?);
+        }
+    }
+    Some(data)
+}
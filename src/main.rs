@@ -1,32 +1,81 @@
 mod client;
 mod server;
+mod ping;
+mod replay;
+mod dump;
 
-use proto::types::Packet;
 use std::env::args_os;
 use std::process::exit;
+use tracing::{info, Level};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
 use crate::client::client;
 use crate::server::server;
+use crate::ping::{discover, ping};
+use crate::replay::replay;
+use crate::dump::dump;
 
 #[tokio::main]
 async fn main() -> std::io::Result<()> {
+    init_logging();
     let mut args = args_os();
-    if args.len() < 3 {
-        println!("Usage: {} <address> <server|client>", args.next().unwrap().to_str().unwrap());
+    let program = args.next().unwrap().to_str().unwrap().to_string();
+    let args: Vec<String> = args.map(|arg| arg.into_string().unwrap()).collect();
+
+    match args.first().map(String::as_str) {
+        Some("ping") => {
+            if !ping(args[1..].to_vec()).await? {
+                exit(2);
+            }
+            return Ok(());
+        }
+        Some("discover") => {
+            discover(args[1..].to_vec()).await?;
+            return Ok(());
+        }
+        Some("replay") => {
+            if !replay(args[1..].to_vec()).await? {
+                exit(2);
+            }
+            return Ok(());
+        }
+        Some("dump") => {
+            dump(args[1..].to_vec())?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if args.len() < 2 {
+        println!("Usage: {} <address> <server [--lan] [--metrics <address>] [--capture <file.pcapng>]|client>", program);
+        println!("       {} ping [--json] [--timeout <ms>] <address>...", program);
+        println!("       {} discover [--json] [--window <ms>]", program);
+        println!("       {} replay [--from <address>] [--realtime] <capture>", program);
+        println!("       {} dump [--json] [<capture>]", program);
         exit(1);
     }
-    // skip the first arg;
-    args.next();
 
-    let addr = args.next().unwrap().into_string().unwrap();
-    println!("Address: {}", addr);
-    let run_server = args.next().unwrap().to_str().unwrap() == "server";
+    let addr = args[0].clone();
+    info!(address = %addr, "starting");
+    let run_server = args[1] == "server";
     if run_server {
-        println!("Starting server");
-        server(addr).await?;
+        let lan = args[2..].iter().any(|arg| arg == "--lan");
+        let option = |name: &str| args[2..].iter().skip_while(|arg| *arg != name).nth(1).cloned();
+        server(addr, lan, option("--metrics"), option("--capture")).await?;
     } else {
-        println!("Starting client");
         client(addr).await?;
     }
     Ok(())
 }
 
+// init_logging logs to stderr, filtered by RUST_LOG as a list of `target=level` directives and a
+// default level, such as `info,proto::conn=trace` to see every datagram. Logs info and up by default.
+fn init_logging() {
+    let filter = std::env::var("RUST_LOG").ok()
+        .and_then(|directives| directives.parse::<Targets>().ok())
+        .unwrap_or_else(|| Targets::new().with_default(Level::INFO));
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr))
+        .with(filter)
+        .init();
+}
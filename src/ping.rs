@@ -0,0 +1,106 @@
+use std::time::Duration;
+use proto::discovery::discover as discover_lan;
+use proto::query::ping_all;
+use proto::types::json_string;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(3000);
+
+/// Pings every target given on the command line and prints the results as a table, or as a
+/// JSON array when `--json` is passed. Returns whether every target answered.
+/// Usage: ping [--json] [--timeout <ms>] <address>...
+pub async fn ping(args: Vec<String>) -> std::io::Result<bool> {
+    let (json, timeout, targets) = parse_args(args, "--timeout")?;
+    if targets.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "ping expects at least one address"));
+    }
+
+    let results = ping_all(targets, timeout).await;
+    let all_ok = results.iter().all(|(_, res)| res.is_ok());
+    print_results(&results, json);
+    Ok(all_ok)
+}
+
+/// Lists the servers on the local network that answer broadcast pings, in the same format as `ping`.
+/// Usage: discover [--json] [--window <ms>]
+pub async fn discover(args: Vec<String>) -> std::io::Result<()> {
+    let (json, window, _) = parse_args(args, "--window")?;
+
+    let results: Vec<PingResult> = discover_lan(window).await?
+        .into_iter()
+        .map(|server| (server.address.to_string(), Ok((server.status, server.latency))))
+        .collect();
+    print_results(&results, json);
+    Ok(())
+}
+
+// parse_args splits the arguments into the --json flag, a duration in milliseconds passed with
+// `duration_flag`, and the remaining positional arguments.
+fn parse_args(args: Vec<String>, duration_flag: &str) -> std::io::Result<(bool, Duration, Vec<String>)> {
+    let mut json = false;
+    let mut duration = DEFAULT_TIMEOUT;
+    let mut positional = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            flag if flag == duration_flag => {
+                let ms = args.next().and_then(|v| v.parse().ok()).ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{} expects a number of milliseconds", duration_flag))
+                })?;
+                duration = Duration::from_millis(ms);
+            }
+            _ => positional.push(arg),
+        }
+    }
+    Ok((json, duration, positional))
+}
+
+fn print_results(results: &[PingResult], json: bool) {
+    if json {
+        print_json(results);
+    } else {
+        print_table(results);
+    }
+}
+
+type PingResult = (String, std::io::Result<(proto::query::Status, Duration)>);
+
+fn print_table(results: &[PingResult]) {
+    let width = results.iter().map(|(address, _)| address.len()).max().unwrap_or(0).max("ADDRESS".len());
+    println!("{:<width$}  {:>9}  {:>9}  {:<10}  MOTD", "ADDRESS", "LATENCY", "PLAYERS", "VERSION");
+    for (address, result) in results {
+        match result {
+            Ok((status, latency)) => println!(
+                "{:<width$}  {:>7.1}ms  {:>9}  {:<10}  {}",
+                address,
+                latency.as_secs_f64() * 1000.0,
+                format!("{}/{}", status.player_count, status.max_player_count),
+                status.version,
+                status.motd,
+            ),
+            Err(e) => println!("{:<width$}  {:>9}  {:>9}  {:<10}  error: {}", address, "-", "-", "-", e),
+        }
+    }
+}
+
+fn print_json(results: &[PingResult]) {
+    let entries: Vec<String> = results.iter().map(|(address, result)| match result {
+        Ok((status, latency)) => format!(
+            "{{\"address\":{},\"online\":true,\"latency_ms\":{:.3},\"edition\":{},\"motd\":{},\"sub_motd\":{},\"protocol_version\":{},\"version\":{},\"players\":{},\"max_players\":{},\"server_guid\":{},\"game_mode\":{}}}",
+            json_string(address),
+            latency.as_secs_f64() * 1000.0,
+            json_string(&status.edition),
+            json_string(&status.motd),
+            json_string(&status.sub_motd),
+            status.protocol_version,
+            json_string(&status.version),
+            status.player_count,
+            status.max_player_count,
+            status.server_guid.map_or("null".to_string(), |guid| guid.to_string()),
+            json_string(&status.game_mode),
+        ),
+        Err(e) => format!("{{\"address\":{},\"online\":false,\"error\":{}}}", json_string(address), json_string(&e.to_string())),
+    }).collect();
+    println!("[{}]", entries.join(","));
+}
@@ -0,0 +1,45 @@
+use std::io::{Error, ErrorKind};
+use std::net::SocketAddr;
+use proto::capture::read_capture;
+use proto::replay::{replay as replay_datagrams, side, Timing};
+
+/// Replays one side of a captured conversation into a new connection and prints the messages it
+/// decoded and the datagrams it couldn't handle. Returns whether every datagram was handled.
+/// Usage: replay [--from <address>] [--realtime] <capture>
+pub async fn replay(args: Vec<String>) -> std::io::Result<bool> {
+    let mut from = None;
+    let mut timing = Timing::Fast;
+    let mut path = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => {
+                let address: SocketAddr = args.next().and_then(|v| v.parse().ok()).ok_or_else(|| {
+                    Error::new(ErrorKind::InvalidInput, "--from expects an address such as 192.168.1.20:50000")
+                })?;
+                from = Some(address);
+            }
+            "--realtime" => timing = Timing::Original,
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.ok_or_else(|| Error::new(ErrorKind::InvalidInput, "replay expects a capture file"))?;
+
+    let datagrams = side(&read_capture(&std::fs::read(&path)?)?, from);
+    let Some(first) = datagrams.first() else {
+        return Err(Error::new(ErrorKind::InvalidInput, "replay: no datagrams sent by that address"));
+    };
+    println!("Replaying {} datagrams from {} to {}", datagrams.len(), first.src, first.dst);
+    let report = replay_datagrams(&datagrams, timing).await?;
+    for message in &report.messages {
+        println!("message 0x{:02x} ({} bytes)", message[0], message.len());
+    }
+    for (i, error) in &report.errors {
+        println!("datagram {}: error: {}", i, error);
+    }
+    if let Some(reason) = &report.closed {
+        println!("connection closed: {}", reason);
+    }
+    println!("{} messages, {} errors", report.messages.len(), report.errors.len());
+    Ok(report.errors.is_empty())
+}
@@ -1,80 +1,82 @@
 use std::sync::Arc;
-use rand::random;
 use tokio::net::UdpSocket;
-use tokio::sync::Mutex;
-use proto::conn::Conn;
-use proto::messages::connected_pong::ConnectedPong;
-use proto::messages::open_connection_reply_1::OpenConnectionReply1;
-use proto::messages::open_connection_reply_2::OpenConnectionReply2;
-use proto::messages::unconnected_pong::UnconnectedPong;
-use proto::PacketT;
-use proto::types::Packet;
+use tracing::{info, trace, warn, Instrument};
+use proto::capture::{Capture, CaptureTransport};
+use proto::clock::SystemClock;
+use proto::discovery::advertise;
+use proto::listener::Listener;
+#[cfg(feature = "metrics")]
+use proto::metrics;
+use proto::query::Status;
 
-pub async fn server(local_addr: String) -> std::io::Result<()> {
-    println!("Listening on {}", local_addr);
-    let socket = UdpSocket::bind(local_addr).await?;
-    let server_id: u64 = random();
-    let mut buf = [0u8; 1492];
-    let socket = Arc::new(Mutex::new(socket));
-    let conn = Conn::new(socket.clone(), 1492, true).await;
-    loop {
-        let (len, src) = socket.lock().await.recv_from(&mut buf).await?;
-
-        let received_data = &buf[..len];
-        match conn.ReceivePacket(received_data).await {
-            Ok(p) => {
-                if p.is_none() {
-                    continue;
-                }
-                let packet = p.unwrap();
-                println!("Received {:?}", packet);
-                match packet {
-                    PacketT::ConnectedPing(packet) => {
-                        let response = ConnectedPong{ client_send_time_be: packet.client_send_time_be };
-                        conn.WritePacketTo(Box::new(&response), src, true).await?;
-                        println!("Sent ConnectedPong to {}", src);
-                    }
-                    PacketT::UnconnectedPing(packet) => {
-                        let response =  UnconnectedPong{server_guid_be: server_id, client_send_time_be: packet.client_send_time_be, data: "MCPE;Dedicated Server;786;1.21.73;0;10;11954621141260796043;Bedrock level;Survival;1;19132;19133;0;".to_string() };
-                        conn.WritePacketTo(Box::new(&response), src, true).await?;
-                    }
-                    PacketT::OpenConnectionRequest1(packet) => {
-                        if packet.max_transmission_unit as usize > buf.len() {
-                            println!("Ignoring MTU {} as it is larger than buffer size", packet.max_transmission_unit);
-                            continue;
-                        }
-
-                        let response = OpenConnectionReply1{
-                            server_has_security: false,
-                            server_guid_be: server_id,
-                            max_transmission_unit_be: packet.max_transmission_unit,
-                            cookie: 0,
-                        };
-
-                        conn.WritePacketTo(Box::new(&response), src, true).await?;
-                    }
-                    PacketT::OpenConnectionRequest2(packet) => {
-                        if packet.max_transmission_unit as usize > buf.len() {
-                            continue;
-                        }
-
-                        let response = OpenConnectionReply2{
-                            client_address: src.into(),
-                            do_security: packet.server_has_security,
-                            max_transmission_unit_be: 1492,
-                            server_guid_be: server_id,
-                        };
+pub async fn server(local_addr: String, lan: bool, metrics_addr: Option<String>, capture_path: Option<String>) -> std::io::Result<()> {
+    info!(address = %local_addr, "listening");
+    let listener = match capture_path {
+        Some(path) => {
+            let socket = UdpSocket::bind(local_addr).await?;
+            let capture = Capture { path: path.into(), ..Default::default() };
+            info!(path = %capture.path.display(), "capturing traffic");
+            Listener::listen_on(Arc::new(CaptureTransport::new(Arc::new(socket), capture)?), Arc::new(SystemClock))
+        }
+        None => Listener::listen(local_addr).await?,
+    };
+    let port = listener.local_addr()?.port();
+    let status = Status {
+        edition: "MCPE".to_string(),
+        motd: "Dedicated Server".to_string(),
+        protocol_version: 786,
+        version: "1.21.73".to_string(),
+        player_count: 0,
+        max_player_count: 10,
+        server_guid: Some(listener.guid),
+        sub_motd: "Bedrock level".to_string(),
+        game_mode: "Survival".to_string(),
+        game_mode_numeric: Some(1),
+        port_v4: Some(port),
+        port_v6: Some(port),
+    }.to_string();
+    listener.set_pong_data(status.clone());
+    if let Some(metrics_addr) = metrics_addr {
+        serve_metrics(metrics_addr, &listener).await?;
+    }
+    if lan {
+        let server_guid = listener.guid;
+        tokio::spawn(async move {
+            // the server's own socket already answers broadcasts when it is bound to the LAN port.
+            if let Err(e) = advertise(server_guid, status).await {
+                warn!(error = %e, "not advertising on LAN");
+            }
+        });
+    }
 
-                        conn.WritePacketTo(Box::new(&response), src, true).await?;
+    loop {
+        let conn = listener.accept().await?;
+        let span = conn.span().clone();
+        info!(parent: &span, "accepted connection");
+        tokio::spawn(async move {
+            loop {
+                match conn.ReadPacket().await {
+                    Ok(packet) => {
+                        trace!(id = packet[0], len = packet.len(), "received packet");
                     }
-                    _ => {
-                        println!("Received Unhandled packet id: 0x{:02X}", received_data[0]);
+                    Err(e) => {
+                        info!(reason = %e, "connection closed");
+                        break;
                     }
                 }
             }
-            Err(e) => {
-                println!("{} | data: [{}]", e,  received_data.to_vec().iter().map(|&b| format!("0x{:02X}", b)).collect::<Vec<String>>().join(", "));
-            }
-        }
+        }.instrument(span));
     }
 }
+
+#[cfg(feature = "metrics")]
+async fn serve_metrics(address: String, listener: &Arc<Listener>) -> std::io::Result<()> {
+    let address = metrics::serve(address, listener.clone()).await?;
+    info!("serving metrics on http://{}/metrics", address);
+    Ok(())
+}
+
+#[cfg(not(feature = "metrics"))]
+async fn serve_metrics(_: String, _: &Arc<Listener>) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "serve metrics: built without the metrics feature"))
+}